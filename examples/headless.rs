@@ -0,0 +1,69 @@
+use pollster::block_on;
+use wgpu_text::glyph_brush::{Section, Text};
+use wgpu_text::BrushBuilder;
+
+// Renders text without a window and saves it as 'headless.png'.
+fn main() {
+    if std::env::var("RUST_LOG").is_err() {
+        std::env::set_var("RUST_LOG", "error");
+    }
+    env_logger::init();
+
+    let backends =
+        wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all);
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        dx12_shader_compiler: wgpu::Dx12Compiler::Fxc,
+    });
+    // Prefer a software adapter, like on CI machines without a GPU.
+    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        force_fallback_adapter: true,
+        ..Default::default()
+    }))
+    .or_else(|| block_on(instance.request_adapter(&Default::default())))
+    .expect("No adapters found!");
+
+    let (device, queue) = block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("Device"),
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::downlevel_webgl2_defaults(),
+        },
+        None,
+    ))
+    .unwrap();
+
+    // All wgpu-text related below:
+    let (width, height) = (400, 100);
+    let font: &[u8] = include_bytes!("fonts/DejaVuSans.ttf");
+    let mut brush = BrushBuilder::using_font_bytes(font).unwrap().build(
+        &device,
+        width,
+        height,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+    );
+
+    let section = Section::default()
+        .add_text(
+            Text::new("Rendered offscreen")
+                .with_scale(40.0)
+                .with_color([0.9, 0.5, 0.5, 1.0]),
+        )
+        .with_screen_position((20.0, 25.0));
+    brush.queue(&device, &queue, vec![&section]).unwrap();
+
+    let image = brush
+        .render_to_image(&device, &queue, width, height, wgpu::Color::BLACK)
+        .unwrap();
+
+    let file = std::fs::File::create("headless.png").unwrap();
+    let mut encoder = png::Encoder::new(file, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(&image.data)
+        .unwrap();
+    println!("Saved 'headless.png' using {:?}", adapter.get_info().name);
+}
@@ -6,7 +6,7 @@ use utils::WgpuUtils;
 use wgpu_text::glyph_brush::{
     BuiltInLineBreaker, Layout, OwnedText, Section, Text, VerticalAlign,
 };
-use wgpu_text::BrushBuilder;
+use wgpu_text::{BrushBuilder, DebugOverlay};
 use winit::{
     event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::{self, ControlFlow},
@@ -42,7 +42,8 @@ fn main() {
         .add_text(
             Text::new(
                 "Try typing some text,\n \
-                del - delete all, backspace - remove last character",
+                del - delete all, backspace - remove last character, \
+                F1 - toggle the debug overlay",
             )
             .with_scale(font_size)
             .with_color([0.9, 0.5, 0.5, 1.0]),
@@ -110,6 +111,13 @@ fn main() {
                 } => match keypress {
                     VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
                     VirtualKeyCode::Delete => section.text.clear(),
+                    VirtualKeyCode::F1 => {
+                        brush.set_debug_overlay(if brush.debug_overlay().is_enabled() {
+                            DebugOverlay::default()
+                        } else {
+                            DebugOverlay::ALL
+                        })
+                    }
                     VirtualKeyCode::Back if !section.text.is_empty() => {
                         let mut end_text = section.text.remove(section.text.len() - 1);
                         end_text.text.pop();
@@ -1,6 +1,5 @@
 use pollster::block_on;
 
-// TODO add cache texture preview example
 // TODO add mip-mapping example
 // TODO add wasm example
 pub struct WgpuUtils;
@@ -0,0 +1,1070 @@
+use std::collections::HashMap;
+
+use glyph_brush::{
+    ab_glyph::{
+        point, Font, Glyph, GlyphId, OutlineCurve, Point, PxScale, Rect, ScaleFont,
+    },
+    Rectangle,
+};
+
+use crate::{
+    bitmap::{BitmapFont, Pixels},
+    cache::CacheSettings,
+    persist::{Decoder, Encoder},
+    subpixel::SubpixelOrder,
+};
+
+/// Specifies how glyphs are rasterized into the cache texture and how they are
+/// reconstructed by the fragment shader.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RenderMode {
+    /// Glyphs are rasterized at their exact scale and subpixel position into
+    /// alpha coverage bitmaps. The sharpest option for text drawn at 1:1 scale.
+    #[default]
+    Coverage,
+    /// Each glyph is rasterized only once, at `size` pixels, into a signed distance
+    /// field fading out over `spread` pixels. Text stays crisp when magnified
+    /// through [`TextBrush::update_matrix()`](crate::TextBrush::update_matrix)
+    /// or drawn at scales far from `size`.
+    Sdf { size: f32, spread: f32 },
+    /// Like [`RenderMode::Sdf`], but generates multi-channel distance fields straight
+    /// from the glyph outlines, which preserve sharp corners even under heavy
+    /// magnification. Uses an RGBA cache texture, so it takes four times the memory.
+    Msdf { size: f32, spread: f32 },
+    /// Glyphs are rasterized like [`RenderMode::Coverage`], but at three times the
+    /// horizontal resolution, one sample for each color stripe of the display
+    /// pixels. Makes small text sharper on LCD monitors with the given
+    /// [`SubpixelOrder`], but looks wrong on rotated or scaled text. Uses an RGBA
+    /// cache texture.
+    ///
+    /// Glyphs are blended in two passes, so glyphs whose quads overlap, e.g.
+    /// through kerning or text effects, are drawn in separate runs. This costs
+    /// extra draw calls for tightly packed text.
+    ///
+    /// Falls back to [`RenderMode::Coverage`] on render targets which can't blend
+    /// each color channel on its own, like single channel formats.
+    Subpixel(SubpixelOrder),
+}
+
+impl RenderMode {
+    /// [`RenderMode::Sdf`] with a `size` of 48 and a `spread` of 6 pixels.
+    pub const SDF: RenderMode = RenderMode::Sdf {
+        size: 48.0,
+        spread: 6.0,
+    };
+
+    /// [`RenderMode::Msdf`] with a `size` of 48 and a `spread` of 6 pixels.
+    pub const MSDF: RenderMode = RenderMode::Msdf {
+        size: 48.0,
+        spread: 6.0,
+    };
+
+    /// Format of the cache texture glyphs are rasterized into.
+    pub(crate) fn cache_format(&self) -> wgpu::TextureFormat {
+        match self {
+            RenderMode::Coverage | RenderMode::Sdf { .. } => wgpu::TextureFormat::R8Unorm,
+            RenderMode::Msdf { .. } | RenderMode::Subpixel(_) => {
+                wgpu::TextureFormat::Rgba8Unorm
+            }
+        }
+    }
+}
+
+/// Kind of cache texture page a glyph is stored in.
+///
+/// Each kind has its own cache texture, an array of one or more pages of the
+/// same size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Page {
+    /// Glyphs rasterized according to the [`RenderMode`], tinted with the text color.
+    Main = 0,
+    /// Color glyphs as premultiplied RGBA, drawn in their own colors.
+    Color = 1,
+}
+
+/// Size of the color page when color glyphs are enabled. Most text has few of
+/// them, so it starts smaller than the main page and grows on its own.
+const COLOR_PAGE_DIMENSIONS: (u32, u32) = (256, 256);
+
+/// No page of the cache texture of the `Page` kind has space left for new glyphs.
+#[derive(Debug)]
+pub struct AtlasFull(pub Page);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font_id: usize,
+    glyph_id: GlyphId,
+    scale: [u32; 2],
+    offset: [u32; 2],
+}
+
+/// Placement of a rasterized glyph inside the cache texture.
+#[derive(Debug, Clone, Copy)]
+struct AtlasGlyph {
+    page: Page,
+    /// Index of the page in the cache texture array.
+    index: u32,
+    tex_rect: Rectangle<u32>,
+    /// Quad bounds relative to the glyph origin the glyph was rasterized at.
+    bounds: Rect,
+}
+
+/// Shelf of glyphs with the same maximum height.
+#[derive(Debug)]
+struct Row {
+    y: u32,
+    height: u32,
+    width: u32,
+}
+
+/// Shelf packer of the pages of one cache texture.
+#[derive(Debug)]
+struct Packer {
+    dimensions: (u32, u32),
+    /// Rows of every page, there is always at least one.
+    pages: Vec<Vec<Row>>,
+    /// Empty pixels right and below every glyph.
+    gap: u32,
+    /// Glyphs and their gaps start and end at multiples of it.
+    alignment: u32,
+}
+
+/// Rasterized glyph bitmap ready to be uploaded.
+struct Raster {
+    page: Page,
+    bounds: Rect,
+    width: u32,
+    height: u32,
+    /// Empty border around the glyph included in `data`.
+    padding: u32,
+    data: Vec<u8>,
+}
+
+impl Raster {
+    /// Pixels of the raster in the top left corner of an otherwise transparent
+    /// `width` x `height` region.
+    fn expand(&self, width: u32, height: u32) -> Vec<u8> {
+        let texel_bytes = self.data.len() / (self.width * self.height).max(1) as usize;
+        let row_bytes = width as usize * texel_bytes;
+        let mut data = vec![0; row_bytes * height as usize];
+        for (y, row) in self
+            .data
+            .chunks_exact(self.width as usize * texel_bytes)
+            .enumerate()
+        {
+            data[y * row_bytes..y * row_bytes + row.len()].copy_from_slice(row);
+        }
+        data
+    }
+}
+
+/// Packs glyphs rasterized according to the [`RenderMode`] into the cache texture
+/// and remembers where each of them is.
+#[derive(Debug)]
+pub struct Atlas {
+    mode: RenderMode,
+    /// Format of the color page, `None` if color glyphs are disabled.
+    color_format: Option<wgpu::TextureFormat>,
+    scale_tolerance: f32,
+    position_tolerance: f32,
+
+    main: Packer,
+    color: Packer,
+    // `None` marks glyphs without anything to draw, e.g. whitespace.
+    glyphs: HashMap<GlyphKey, Option<AtlasGlyph>>,
+    /// Bitmap fonts by font id, their glyphs are copied instead of rasterized.
+    bitmap_fonts: HashMap<usize, BitmapFont>,
+}
+
+impl Atlas {
+    pub fn new(
+        mode: RenderMode,
+        color_format: Option<wgpu::TextureFormat>,
+        dimensions: (u32, u32),
+        scale_tolerance: f32,
+        position_tolerance: f32,
+        settings: &CacheSettings,
+    ) -> Self {
+        let color_dimensions = match color_format {
+            Some(_) => COLOR_PAGE_DIMENSIONS,
+            None => (1, 1),
+        };
+        let (gap, alignment) = (settings.gap(), settings.alignment());
+        Self {
+            mode,
+            color_format,
+            scale_tolerance,
+            position_tolerance,
+            main: Packer::new(dimensions, gap, alignment),
+            color: Packer::new(color_dimensions, gap, alignment),
+            glyphs: HashMap::new(),
+            bitmap_fonts: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn render_mode(&self) -> RenderMode {
+        self.mode
+    }
+
+    #[inline]
+    pub fn dimensions(&self, page: Page) -> (u32, u32) {
+        self.packer(page).dimensions
+    }
+
+    /// Number of pages of the cache texture of the `page` kind.
+    #[inline]
+    pub fn page_count(&self, page: Page) -> u32 {
+        self.packer(page).pages.len() as u32
+    }
+
+    /// Size of the cache texture of the `page` kind, with a layer for every page.
+    pub fn texture_size(&self, page: Page) -> wgpu::Extent3d {
+        let (width, height) = self.dimensions(page);
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: self.page_count(page),
+        }
+    }
+
+    /// Whether the last page of the `page` kind has no glyphs, when a glyph which
+    /// didn't fit won't fit into another page either.
+    pub fn last_page_empty(&self, page: Page) -> bool {
+        self.packer(page).pages.last().is_none_or(Vec::is_empty)
+    }
+
+    /// Forgets all cached glyphs, but keeps the pages.
+    pub fn clear(&mut self) {
+        for packer in [&mut self.main, &mut self.color] {
+            packer.pages.iter_mut().for_each(Vec::clear);
+        }
+        self.glyphs.clear();
+    }
+
+    /// Continues packing the pages of the `page` kind with the bigger
+    /// `dimensions`, cached glyphs stay where they are.
+    pub fn grow(&mut self, page: Page, dimensions: (u32, u32)) {
+        self.packer_mut(page).dimensions = dimensions;
+    }
+
+    /// Adds an empty page of the `page` kind for new glyphs.
+    pub fn add_page(&mut self, page: Page) {
+        self.packer_mut(page).pages.push(Vec::new());
+    }
+
+    /// Fraction of the pages of the `page` kind taken up by rows of glyphs,
+    /// from `0.0` to `1.0`.
+    pub fn usage(&self, page: Page) -> f32 {
+        let packer = self.packer(page);
+        let (width, height) = packer.dimensions;
+        let used: u64 = packer
+            .pages
+            .iter()
+            .flatten()
+            .map(|row| row.width.min(width) as u64 * row.height as u64)
+            .sum();
+        let total = width as u64 * height as u64 * packer.pages.len() as u64;
+        (used as f64 / total as f64) as f32
+    }
+
+    /// Number of cached glyphs, including those without anything to draw.
+    #[inline]
+    pub fn glyph_count(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// Writes the settings, the packing and the glyphs of the atlas. Font ids
+    /// are written as their `font_index`, glyphs of fonts without one are skipped.
+    pub fn encode(&self, out: &mut Encoder, font_index: impl Fn(usize) -> Option<u32>) {
+        self.encode_settings(out);
+        for packer in [&self.main, &self.color] {
+            out.u32(packer.dimensions.0);
+            out.u32(packer.dimensions.1);
+            out.u32(packer.pages.len() as u32);
+            for rows in &packer.pages {
+                out.u32(rows.len() as u32);
+                for row in rows {
+                    out.u32(row.y);
+                    out.u32(row.height);
+                    out.u32(row.width);
+                }
+            }
+        }
+
+        // Sorted so the same cache always gives the same data.
+        let mut glyphs: Vec<_> = self
+            .glyphs
+            .iter()
+            .filter_map(|(key, glyph)| Some((font_index(key.font_id)?, key, glyph)))
+            .collect();
+        glyphs.sort_unstable_by_key(|(font_index, key, _)| {
+            (*font_index, key.glyph_id.0, key.scale, key.offset)
+        });
+        out.u32(glyphs.len() as u32);
+        for (font_index, key, glyph) in glyphs {
+            out.u32(font_index);
+            out.u32(key.glyph_id.0 as u32);
+            key.scale
+                .into_iter()
+                .chain(key.offset)
+                .for_each(|v| out.u32(v));
+            match glyph {
+                Some(AtlasGlyph {
+                    page,
+                    index,
+                    tex_rect,
+                    bounds,
+                }) => {
+                    out.u8(1);
+                    out.u8(*page as u8);
+                    out.u32(*index);
+                    tex_rect
+                        .min
+                        .into_iter()
+                        .chain(tex_rect.max)
+                        .for_each(|v| out.u32(v));
+                    [bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y]
+                        .into_iter()
+                        .for_each(|v| out.f32(v));
+                }
+                None => out.u8(0),
+            }
+        }
+    }
+
+    /// Reads an atlas written by [`Atlas::encode()`] with font indices below
+    /// `font_count` as font ids. Returns `None` if the data is invalid or the
+    /// settings differ from the ones of `self`, which is replaced otherwise.
+    pub fn decode(&mut self, input: &mut Decoder, font_count: usize) -> Option<()> {
+        let mut settings = Encoder::default();
+        self.encode_settings(&mut settings);
+        input.expect(&settings.data)?;
+
+        let (gap, alignment) = (self.main.gap, self.main.alignment);
+        let mut packers = [
+            Packer::new((1, 1), gap, alignment),
+            Packer::new((1, 1), gap, alignment),
+        ];
+        for packer in &mut packers {
+            packer.dimensions = (input.u32()?, input.u32()?);
+            // Cache textures can't be empty.
+            if packer.dimensions.0 == 0 || packer.dimensions.1 == 0 {
+                return None;
+            }
+            packer.pages = (0..input.u32()?)
+                .map(|_| {
+                    (0..input.u32()?)
+                        .map(|_| {
+                            Some(Row {
+                                y: input.u32()?,
+                                height: input.u32()?,
+                                width: input.u32()?,
+                            })
+                        })
+                        .collect()
+                })
+                .collect::<Option<_>>()?;
+            if packer.pages.is_empty() {
+                return None;
+            }
+        }
+
+        let mut glyphs = HashMap::new();
+        for _ in 0..input.u32()? {
+            let font_id = input.u32()? as usize;
+            let glyph_id = GlyphId(u16::try_from(input.u32()?).ok()?);
+            let key = GlyphKey {
+                font_id,
+                glyph_id,
+                scale: [input.u32()?, input.u32()?],
+                offset: [input.u32()?, input.u32()?],
+            };
+            let glyph = match input.u8()? {
+                0 => None,
+                _ => {
+                    let page = match input.u8()? {
+                        0 => Page::Main,
+                        1 => Page::Color,
+                        _ => return None,
+                    };
+                    let index = input.u32()?;
+                    let tex_rect = Rectangle {
+                        min: [input.u32()?, input.u32()?],
+                        max: [input.u32()?, input.u32()?],
+                    };
+                    let bounds = Rect {
+                        min: point(input.f32()?, input.f32()?),
+                        max: point(input.f32()?, input.f32()?),
+                    };
+                    let packer = &packers[page as usize];
+                    let (width, height) = packer.dimensions;
+                    if tex_rect.min[0] > tex_rect.max[0]
+                        || tex_rect.min[1] > tex_rect.max[1]
+                        || tex_rect.max[0] > width
+                        || tex_rect.max[1] > height
+                        || index as usize >= packer.pages.len()
+                    {
+                        return None;
+                    }
+                    Some(AtlasGlyph {
+                        page,
+                        index,
+                        tex_rect,
+                        bounds,
+                    })
+                }
+            };
+            if font_id >= font_count {
+                return None;
+            }
+            glyphs.insert(key, glyph);
+        }
+
+        let [main, color] = packers;
+        self.main = main;
+        self.color = color;
+        self.glyphs = glyphs;
+        Some(())
+    }
+
+    /// Everything which affects the rasterized glyphs and their keys.
+    fn encode_settings(&self, out: &mut Encoder) {
+        let (mode, size, spread, order) = match self.mode {
+            RenderMode::Coverage => (0, 0.0, 0.0, 0),
+            RenderMode::Sdf { size, spread } => (1, size, spread, 0),
+            RenderMode::Msdf { size, spread } => (2, size, spread, 0),
+            RenderMode::Subpixel(order) => (3, 0.0, 0.0, order as u8),
+        };
+        out.u8(mode);
+        out.f32(size);
+        out.f32(spread);
+        out.u8(order);
+        out.u8(match self.color_format {
+            None => 0,
+            Some(wgpu::TextureFormat::Rgba8UnormSrgb) => 2,
+            Some(_) => 1,
+        });
+        out.f32(self.scale_tolerance);
+        out.f32(self.position_tolerance);
+        out.u32(self.main.gap);
+        out.u32(self.main.alignment);
+    }
+
+    /// Whether glyphs are rasterized into the color page.
+    #[inline]
+    pub fn color_glyphs(&self) -> bool {
+        self.color_format.is_some()
+    }
+
+    /// Draws the glyphs of the font `font_id` from the pixels of the bitmap `font`.
+    #[inline]
+    pub fn add_bitmap_font(&mut self, font_id: usize, font: BitmapFont) {
+        self.bitmap_fonts.insert(font_id, font);
+    }
+
+    fn packer(&self, page: Page) -> &Packer {
+        match page {
+            Page::Main => &self.main,
+            Page::Color => &self.color,
+        }
+    }
+
+    fn packer_mut(&mut self, page: Page) -> &mut Packer {
+        match page {
+            Page::Main => &mut self.main,
+            Page::Color => &mut self.color,
+        }
+    }
+
+    /// Returns the pixel and the texture coordinates in texels of the `glyph`,
+    /// together with the page kind and index they refer to. Texels stay where
+    /// they are when the cache texture grows.
+    ///
+    /// Glyphs not yet in the atlas are rasterized and handed to `upload` together
+    /// with their page kind and index and region of the cache texture. Returns
+    /// `Ok(None)` for glyphs without anything to draw.
+    pub fn glyph<F, U>(
+        &mut self,
+        font: &F,
+        font_id: usize,
+        glyph: &Glyph,
+        upload: U,
+    ) -> Result<Option<(Rect, Rect, Page, u32)>, AtlasFull>
+    where
+        F: Font,
+        U: FnOnce(Page, u32, Rectangle<u32>, &[u8]),
+    {
+        let (key, raster_glyph, origin, factor) = self.key(font_id, glyph);
+
+        let atlas_glyph = match self.glyphs.get(&key) {
+            Some(atlas_glyph) => *atlas_glyph,
+            None => {
+                let raster = match self.bitmap_fonts.get(&font_id) {
+                    Some(bitmap_font) => {
+                        self.rasterize_bitmap(bitmap_font, &raster_glyph)
+                    }
+                    None => self.rasterize(font, raster_glyph),
+                };
+                let atlas_glyph = match raster {
+                    Some(raster) => {
+                        let packer = self.packer_mut(raster.page);
+                        let (index, cell) = packer
+                            .allocate(raster.width, raster.height)
+                            .ok_or(AtlasFull(raster.page))?;
+                        let padded = Rectangle {
+                            min: cell.min,
+                            max: [
+                                cell.min[0] + raster.width,
+                                cell.min[1] + raster.height,
+                            ],
+                        };
+                        if packer.alignment > 1 {
+                            // The mip levels of the whole cell are written, so
+                            // its gap can't keep pixels of evicted glyphs.
+                            let data = raster.expand(cell.width(), cell.height());
+                            upload(raster.page, index, cell, &data);
+                        } else {
+                            upload(raster.page, index, padded, &raster.data);
+                        }
+                        Some(AtlasGlyph {
+                            page: raster.page,
+                            index,
+                            tex_rect: Rectangle {
+                                min: [
+                                    padded.min[0] + raster.padding,
+                                    padded.min[1] + raster.padding,
+                                ],
+                                max: [
+                                    padded.max[0] - raster.padding,
+                                    padded.max[1] - raster.padding,
+                                ],
+                            },
+                            bounds: raster.bounds,
+                        })
+                    }
+                    None => None,
+                };
+                self.glyphs.insert(key, atlas_glyph);
+                atlas_glyph
+            }
+        };
+
+        Ok(atlas_glyph.map(
+            |AtlasGlyph {
+                 page,
+                 index,
+                 tex_rect,
+                 bounds,
+             }| {
+                let pixel_coords = Rect {
+                    min: point(
+                        origin.x + bounds.min.x * factor.0,
+                        origin.y + bounds.min.y * factor.1,
+                    ),
+                    max: point(
+                        origin.x + bounds.max.x * factor.0,
+                        origin.y + bounds.max.y * factor.1,
+                    ),
+                };
+                let tex_coords = Rect {
+                    min: point(tex_rect.min[0] as f32, tex_rect.min[1] as f32),
+                    max: point(tex_rect.max[0] as f32, tex_rect.max[1] as f32),
+                };
+                (pixel_coords, tex_coords, page, index)
+            },
+        ))
+    }
+
+    /// Returns the cache key, the glyph to rasterize, the screen origin of the
+    /// rasterized glyph and the factor scaling it to the requested size.
+    fn key(&self, font_id: usize, glyph: &Glyph) -> (GlyphKey, Glyph, Point, (f32, f32)) {
+        match self.mode {
+            RenderMode::Coverage | RenderMode::Subpixel(_) => {
+                let scale = [
+                    (glyph.scale.x / self.scale_tolerance).round() as u32,
+                    (glyph.scale.y / self.scale_tolerance).round() as u32,
+                ];
+                let origin = point(glyph.position.x.floor(), glyph.position.y.floor());
+                let offset = [
+                    ((glyph.position.x - origin.x) / self.position_tolerance).round()
+                        as u32,
+                    ((glyph.position.y - origin.y) / self.position_tolerance).round()
+                        as u32,
+                ];
+                let raster_glyph = Glyph {
+                    id: glyph.id,
+                    scale: PxScale {
+                        x: scale[0] as f32 * self.scale_tolerance,
+                        y: scale[1] as f32 * self.scale_tolerance,
+                    },
+                    position: point(
+                        offset[0] as f32 * self.position_tolerance,
+                        offset[1] as f32 * self.position_tolerance,
+                    ),
+                };
+                let key = GlyphKey {
+                    font_id,
+                    glyph_id: glyph.id,
+                    scale,
+                    offset,
+                };
+                (key, raster_glyph, origin, (1.0, 1.0))
+            }
+            RenderMode::Sdf { size, .. } | RenderMode::Msdf { size, .. } => {
+                let key = GlyphKey {
+                    font_id,
+                    glyph_id: glyph.id,
+                    scale: [0, 0],
+                    offset: [0, 0],
+                };
+                let raster_glyph = Glyph {
+                    id: glyph.id,
+                    scale: PxScale::from(size),
+                    position: point(0.0, 0.0),
+                };
+                let factor = (glyph.scale.x / size, glyph.scale.y / size);
+                (key, raster_glyph, glyph.position, factor)
+            }
+        }
+    }
+
+    fn rasterize<F: Font>(&self, font: &F, glyph: Glyph) -> Option<Raster> {
+        if let Some(format) = self.color_format {
+            if let Some(image) = crate::color::rasterize(font, &glyph, format.is_srgb()) {
+                // Pad with a transparent border, like coverage glyphs.
+                let width = image.width + 2;
+                let mut data = vec![0; (width * (image.height + 2) * 4) as usize];
+                for (y, row) in image
+                    .data
+                    .chunks_exact(image.width as usize * 4)
+                    .enumerate()
+                {
+                    let start = ((y as u32 + 1) * width + 1) as usize * 4;
+                    data[start..start + row.len()].copy_from_slice(row);
+                }
+                return Some(Raster {
+                    page: Page::Color,
+                    bounds: image.bounds,
+                    width,
+                    height: image.height + 2,
+                    padding: 1,
+                    data,
+                });
+            }
+        }
+
+        if let RenderMode::Subpixel(order) = self.mode {
+            return Self::rasterize_subpixel(font, glyph, order);
+        }
+
+        let outlined = font.outline_glyph(glyph.clone())?;
+        let bounds = outlined.px_bounds();
+        if bounds.width() == 0.0 || bounds.height() == 0.0 {
+            return None;
+        }
+
+        let padding = match self.mode {
+            RenderMode::Coverage | RenderMode::Subpixel(_) => 1,
+            RenderMode::Sdf { spread, .. } | RenderMode::Msdf { spread, .. } => {
+                spread.ceil() as u32
+            }
+        };
+        let width = bounds.width() as u32 + 2 * padding;
+        let height = bounds.height() as u32 + 2 * padding;
+
+        let mut coverage = vec![0.0; (width * height) as usize];
+        outlined.draw(|x, y, c| {
+            coverage[((y + padding) * width + x + padding) as usize] = c;
+        });
+
+        // The distance fields spill into the padding, so it's part of the quad.
+        let pad = padding as f32;
+        let padded_bounds = Rect {
+            min: point(bounds.min.x - pad, bounds.min.y - pad),
+            max: point(bounds.max.x + pad, bounds.max.y + pad),
+        };
+
+        Some(match self.mode {
+            RenderMode::Coverage | RenderMode::Subpixel(_) => Raster {
+                page: Page::Main,
+                bounds,
+                width,
+                height,
+                padding,
+                data: coverage
+                    .iter()
+                    .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+                    .collect(),
+            },
+            RenderMode::Sdf { spread, .. } => Raster {
+                page: Page::Main,
+                bounds: padded_bounds,
+                width,
+                height,
+                padding: 0,
+                data: crate::sdf::from_coverage(
+                    &coverage,
+                    width as usize,
+                    height as usize,
+                    spread,
+                ),
+            },
+            RenderMode::Msdf { spread, .. } => {
+                let outline = font.outline(glyph.id)?;
+                let scale_factor = font.as_scaled(glyph.scale).scale_factor();
+                // Same transformation `OutlinedGlyph::draw()` uses, plus the padding.
+                let to_bitmap = |p: Point| {
+                    point(
+                        p.x * scale_factor.horizontal - padded_bounds.min.x,
+                        p.y * -scale_factor.vertical - padded_bounds.min.y,
+                    )
+                };
+                let curves: Vec<OutlineCurve> = outline
+                    .curves
+                    .iter()
+                    .map(|curve| match *curve {
+                        OutlineCurve::Line(p0, p1) => {
+                            OutlineCurve::Line(to_bitmap(p0), to_bitmap(p1))
+                        }
+                        OutlineCurve::Quad(p0, p1, p2) => OutlineCurve::Quad(
+                            to_bitmap(p0),
+                            to_bitmap(p1),
+                            to_bitmap(p2),
+                        ),
+                        OutlineCurve::Cubic(p0, p1, p2, p3) => OutlineCurve::Cubic(
+                            to_bitmap(p0),
+                            to_bitmap(p1),
+                            to_bitmap(p2),
+                            to_bitmap(p3),
+                        ),
+                    })
+                    .collect();
+
+                Raster {
+                    page: Page::Main,
+                    bounds: padded_bounds,
+                    width,
+                    height,
+                    padding: 0,
+                    data: crate::msdf::generate(
+                        &curves,
+                        width as usize,
+                        height as usize,
+                        spread,
+                        &coverage,
+                    ),
+                }
+            }
+        })
+    }
+
+    /// Copies the pixels of the `glyph` of a bitmap `font`. They are stretched to
+    /// the requested scale by the quad, distance fields are generated from them.
+    fn rasterize_bitmap(&self, font: &BitmapFont, glyph: &Glyph) -> Option<Raster> {
+        let (bounds, image) = font.image(glyph)?;
+        if image.width == 0 || image.height == 0 {
+            return None;
+        }
+
+        if let (Pixels::Color(rgba), Some(format)) = (&image.pixels, self.color_format) {
+            let data = pad(rgba, image.width, image.height, 1)
+                .into_iter()
+                .flat_map(|p| crate::color::store(p, format.is_srgb()))
+                .collect();
+            return Some(Raster {
+                page: Page::Color,
+                bounds,
+                width: image.width + 2,
+                height: image.height + 2,
+                padding: 1,
+                data,
+            });
+        }
+
+        let padding = match self.mode {
+            RenderMode::Coverage | RenderMode::Subpixel(_) => 1,
+            RenderMode::Sdf { spread, .. } | RenderMode::Msdf { spread, .. } => {
+                spread.ceil() as u32
+            }
+        };
+        let width = image.width + 2 * padding;
+        let height = image.height + 2 * padding;
+        let coverage = pad(&image.coverage(), image.width, image.height, padding);
+        let to_u8 = |c: &f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        // Padding in pixels of the requested scale.
+        let pad = point(
+            padding as f32 * bounds.width() / image.width as f32,
+            padding as f32 * bounds.height() / image.height as f32,
+        );
+        let padded_bounds = Rect {
+            min: bounds.min - pad,
+            max: bounds.max + pad,
+        };
+
+        Some(match self.mode {
+            RenderMode::Coverage => Raster {
+                page: Page::Main,
+                bounds,
+                width,
+                height,
+                padding,
+                data: coverage.iter().map(to_u8).collect(),
+            },
+            // There are no subpixels to filter, all stripes have the same coverage.
+            RenderMode::Subpixel(_) => Raster {
+                page: Page::Main,
+                bounds,
+                width,
+                height,
+                padding,
+                data: coverage.iter().flat_map(|c| [to_u8(c); 4]).collect(),
+            },
+            RenderMode::Sdf { spread, .. } => Raster {
+                page: Page::Main,
+                bounds: padded_bounds,
+                width,
+                height,
+                padding: 0,
+                data: crate::sdf::from_coverage(
+                    &coverage,
+                    width as usize,
+                    height as usize,
+                    spread,
+                ),
+            },
+            // Without outlines all channels hold the same field.
+            RenderMode::Msdf { spread, .. } => Raster {
+                page: Page::Main,
+                bounds: padded_bounds,
+                width,
+                height,
+                padding: 0,
+                data: crate::sdf::from_coverage(
+                    &coverage,
+                    width as usize,
+                    height as usize,
+                    spread,
+                )
+                .into_iter()
+                .flat_map(|d| [d; 4])
+                .collect(),
+            },
+        })
+    }
+
+    /// Rasterizes the `glyph` at three times the horizontal resolution and
+    /// filters it into the color channels of the pixels.
+    fn rasterize_subpixel<F: Font>(
+        font: &F,
+        glyph: Glyph,
+        order: SubpixelOrder,
+    ) -> Option<Raster> {
+        let wide = Glyph {
+            id: glyph.id,
+            scale: PxScale {
+                x: glyph.scale.x * 3.0,
+                y: glyph.scale.y,
+            },
+            position: point(glyph.position.x * 3.0, glyph.position.y),
+        };
+        let outlined = font.outline_glyph(wide)?;
+        let bounds = outlined.px_bounds();
+        if bounds.width() == 0.0 || bounds.height() == 0.0 {
+            return None;
+        }
+
+        // Whole pixels, plus one on each side the filter spreads into.
+        let left = (bounds.min.x / 3.0).floor() - 1.0;
+        let right = (bounds.max.x / 3.0).ceil() + 1.0;
+        let width = (right - left) as u32;
+        let height = bounds.height() as u32;
+        let offset = (bounds.min.x - left * 3.0) as u32;
+
+        let mut coverage = vec![0.0; (width * 3 * height) as usize];
+        outlined.draw(|x, y, c| {
+            coverage[(y * width * 3 + x + offset) as usize] = c;
+        });
+
+        Some(Raster {
+            page: Page::Main,
+            bounds: Rect {
+                min: point(left, bounds.min.y),
+                max: point(right, bounds.max.y),
+            },
+            width,
+            height,
+            padding: 0,
+            data: crate::subpixel::filter(
+                &coverage,
+                width as usize,
+                height as usize,
+                order,
+            ),
+        })
+    }
+}
+
+/// Surrounds the `width` x `height` `pixels` with a border of `padding` default
+/// pixels.
+fn pad<T: Copy + Default>(pixels: &[T], width: u32, height: u32, padding: u32) -> Vec<T> {
+    let padded_width = (width + 2 * padding) as usize;
+    let mut padded = vec![T::default(); padded_width * (height + 2 * padding) as usize];
+    for (y, row) in pixels.chunks_exact(width as usize).enumerate() {
+        let start = (y + padding as usize) * padded_width + padding as usize;
+        padded[start..start + row.len()].copy_from_slice(row);
+    }
+    padded
+}
+
+impl Packer {
+    fn new(dimensions: (u32, u32), gap: u32, alignment: u32) -> Self {
+        Self {
+            dimensions,
+            pages: vec![Vec::new()],
+            gap,
+            alignment,
+        }
+    }
+
+    /// Finds space for a `width` x `height` region in the first page it fits
+    /// into. Returns the index of the page together with the cell of the region,
+    /// which includes the gap to its neighbours.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, Rectangle<u32>)> {
+        let align = |size: u32| size.div_ceil(self.alignment) * self.alignment;
+        let cell = (align(width + self.gap), align(height + self.gap));
+        // Cells must not reach past the last aligned texel.
+        let dimensions = (
+            self.dimensions.0 / self.alignment * self.alignment,
+            self.dimensions.1 / self.alignment * self.alignment,
+        );
+        let row_alignment = self.alignment.max(8);
+        self.pages.iter_mut().enumerate().find_map(|(index, rows)| {
+            Some((
+                index as u32,
+                Self::allocate_in(rows, dimensions, cell, row_alignment)?,
+            ))
+        })
+    }
+
+    fn allocate_in(
+        rows: &mut Vec<Row>,
+        (tex_width, tex_height): (u32, u32),
+        (width, height): (u32, u32),
+        row_alignment: u32,
+    ) -> Option<Rectangle<u32>> {
+        if width > tex_width || height > tex_height {
+            return None;
+        }
+
+        let rect = |x: u32, y: u32| Rectangle {
+            min: [x, y],
+            max: [x + width, y + height],
+        };
+
+        if let Some(row) = rows
+            .iter_mut()
+            .filter(|row| height <= row.height && row.width + width <= tex_width)
+            .min_by_key(|row| row.height)
+        {
+            let x = row.width;
+            row.width += width;
+            return Some(rect(x, row.y));
+        }
+
+        // Round row heights up so glyphs of similar sizes can share them.
+        let y = rows.last().map_or(0, |row| row.y + row.height);
+        let row_height = height
+            .div_ceil(row_alignment)
+            .saturating_mul(row_alignment)
+            .min(tex_height - y.min(tex_height));
+        if height > row_height {
+            return None;
+        }
+        rows.push(Row {
+            y,
+            height: row_height,
+            width,
+        });
+        Some(rect(0, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glyph_brush::ab_glyph::FontRef;
+
+    use super::*;
+
+    const FONT: &[u8] = include_bytes!("../examples/fonts/DejaVuSans.ttf");
+
+    fn atlas(dimensions: (u32, u32)) -> Atlas {
+        let settings = CacheSettings::default();
+        Atlas::new(RenderMode::Coverage, None, dimensions, 0.5, 0.1, &settings)
+    }
+
+    fn encode(atlas: &Atlas) -> Vec<u8> {
+        let mut out = Encoder::default();
+        atlas.encode(&mut out, |font_id| Some(font_id as u32));
+        out.data
+    }
+
+    fn decode(data: &[u8]) -> Option<Atlas> {
+        let mut decoded = atlas((256, 256));
+        let mut input = Decoder::new(data);
+        decoded.decode(&mut input, 1)?;
+        input.is_empty().then_some(decoded)
+    }
+
+    #[test]
+    fn round_trip() {
+        let font = FontRef::try_from_slice(FONT).unwrap();
+        let mut original = atlas((128, 64));
+        for c in ['a', 'b', ' ', 'W'] {
+            let glyph = font
+                .glyph_id(c)
+                .with_scale_and_position(24.0, point(0.0, 20.0));
+            original.glyph(&font, 0, &glyph, |_, _, _, _| ()).unwrap();
+        }
+        original.add_page(Page::Main);
+
+        let data = encode(&original);
+        let decoded = decode(&data).unwrap();
+        assert_eq!(decoded.glyph_count(), 4);
+        assert_eq!(
+            decoded.texture_size(Page::Main),
+            original.texture_size(Page::Main)
+        );
+        assert_eq!(encode(&decoded), data);
+    }
+
+    #[test]
+    fn empty_pages_fail() {
+        assert!(decode(&encode(&atlas((256, 256)))).is_some());
+        assert!(decode(&encode(&atlas((0, 256)))).is_none());
+        assert!(decode(&encode(&atlas((256, 0)))).is_none());
+    }
+
+    #[test]
+    fn inverted_rectangle_fails() {
+        let mut original = atlas((256, 256));
+        let key = GlyphKey {
+            font_id: 0,
+            glyph_id: GlyphId(1),
+            scale: [0; 2],
+            offset: [0; 2],
+        };
+        let glyph = AtlasGlyph {
+            page: Page::Main,
+            index: 0,
+            tex_rect: Rectangle {
+                min: [8, 8],
+                max: [4, 12],
+            },
+            bounds: Rect::default(),
+        };
+        original.glyphs.insert(key, Some(glyph));
+        assert!(decode(&encode(&original)).is_none());
+    }
+}
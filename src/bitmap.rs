@@ -0,0 +1,844 @@
+//! Bitmap fonts described by AngelCode BMFont `.fnt` files, either in the text
+//! or in the binary format, together with their PNG page images.
+
+use std::{collections::HashMap, error::Error, fmt::Display, path::Path, sync::Arc};
+
+use glyph_brush::ab_glyph::{
+    point, v2, CodepointIdIter, Font, FontArc, FontVec, Glyph, GlyphId, Outline, Rect,
+};
+
+/// Errors of loading a [`BitmapFont`].
+#[derive(Debug)]
+pub enum BitmapFontError {
+    /// Reading the descriptor or a page image failed.
+    Io(std::io::Error),
+    /// The descriptor is neither a valid text nor binary BMFont file.
+    InvalidDescriptor(String),
+    /// The page image with the file name couldn't be decoded, only PNG images are
+    /// supported.
+    UnsupportedPage(String),
+}
+
+impl Error for BitmapFontError {}
+
+impl Display for BitmapFontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "wgpu-text: ")?;
+        match self {
+            BitmapFontError::Io(error) => {
+                write!(f, "Failed to read the bitmap font: {}", error)
+            }
+            BitmapFontError::InvalidDescriptor(reason) => {
+                write!(f, "Invalid BMFont descriptor: {}", reason)
+            }
+            BitmapFontError::UnsupportedPage(file) => write!(
+                f,
+                "Can't decode the bitmap font page '{}', only PNG images are \
+                supported.",
+                file
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for BitmapFontError {
+    fn from(error: std::io::Error) -> Self {
+        BitmapFontError::Io(error)
+    }
+}
+
+/// Hand-drawn or pre-rendered font loaded from an
+/// [AngelCode BMFont](https://www.angelcode.com/products/bmfont/) descriptor and
+/// its page images.
+///
+/// Glyphs are drawn from their pixels instead of being rasterized, text at the
+/// scale of [`line_height()`](Self::line_height) shows them at the size they
+/// were made for. Other scales stretch them, which looks best with
+/// [`BrushBuilder::with_cache_filter()`](crate::BrushBuilder::with_cache_filter)
+/// set to [`wgpu::FilterMode::Nearest`] for pixel art. Kerning pairs of the
+/// descriptor are applied by the layout.
+///
+/// White glyphs and glyphs packed into single color channels are tinted with
+/// the text color like regular glyphs. Glyphs with colors of their own are drawn
+/// in them, like color glyphs, unless color glyphs are disabled.
+///
+/// Bitmap fonts are added to a brush with
+/// [`BrushBuilder::using_bitmap_font()`](crate::BrushBuilder::using_bitmap_font)
+/// or [`BrushBuilder::add_bitmap_font()`](crate::BrushBuilder::add_bitmap_font),
+/// next to regular fonts as a [`FontArc`]. Cloning it is cheap.
+///
+/// # Example
+/// ```no_run
+/// use wgpu_text::{BitmapFont, BrushBuilder};
+///
+/// let font = BitmapFont::open("assets/retro.fnt")?;
+/// let size = font.line_height();
+/// let builder = BrushBuilder::using_bitmap_font(font);
+/// # Ok::<(), wgpu_text::BitmapFontError>(())
+/// ```
+#[derive(Clone)]
+pub struct BitmapFont {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// The descriptor, which identifies the font inside of an atlas.
+    descriptor: Vec<u8>,
+    line_height: f32,
+    /// Distance from the top of a line to the baseline.
+    base: f32,
+    ids: HashMap<char, GlyphId>,
+    /// Glyphs by id, the first one is drawn for missing characters.
+    glyphs: Vec<BitmapGlyph>,
+    kerning: HashMap<(GlyphId, GlyphId), f32>,
+    /// Font with only a character map, for [`Font::codepoint_ids()`].
+    cmap: FontVec,
+}
+
+pub(crate) struct BitmapGlyph {
+    /// Offset of the top left corner from the top of the line.
+    offset: (f32, f32),
+    advance: f32,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Pixels,
+}
+
+pub(crate) enum Pixels {
+    /// Coverage of every pixel, tinted with the text color.
+    Coverage(Vec<u8>),
+    /// Straight RGBA pixels drawn in their own colors.
+    Color(Vec<[u8; 4]>),
+}
+
+impl BitmapGlyph {
+    const EMPTY: BitmapGlyph = BitmapGlyph {
+        offset: (0.0, 0.0),
+        advance: 0.0,
+        width: 0,
+        height: 0,
+        pixels: Pixels::Coverage(Vec::new()),
+    };
+
+    /// Coverage of every pixel, the alpha of glyphs with colors.
+    pub fn coverage(&self) -> Vec<f32> {
+        match &self.pixels {
+            Pixels::Coverage(coverage) => {
+                coverage.iter().map(|&c| c as f32 / 255.0).collect()
+            }
+            Pixels::Color(rgba) => rgba.iter().map(|p| p[3] as f32 / 255.0).collect(),
+        }
+    }
+}
+
+impl BitmapFont {
+    /// Reads the descriptor at the `path` and the page images next to it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BitmapFontError> {
+        let path = path.as_ref();
+        let descriptor = std::fs::read(path)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Self::from_fnt(&descriptor, |file| std::fs::read(dir.join(file)))
+    }
+
+    /// Parses a text or binary BMFont `descriptor`. The page images it refers
+    /// to are read with `load_page`, which gets their file names.
+    pub fn from_fnt<L>(
+        descriptor: &[u8],
+        mut load_page: L,
+    ) -> Result<Self, BitmapFontError>
+    where
+        L: FnMut(&str) -> std::io::Result<Vec<u8>>,
+    {
+        let desc = if descriptor.starts_with(b"BMF") {
+            Descriptor::parse_binary(descriptor)?
+        } else {
+            let text = std::str::from_utf8(descriptor).map_err(|_| {
+                BitmapFontError::InvalidDescriptor("not a text or binary file".into())
+            })?;
+            Descriptor::parse_text(text)?
+        };
+        if desc.line_height <= 0.0 {
+            return Err(BitmapFontError::InvalidDescriptor(
+                "missing 'common' line height".into(),
+            ));
+        }
+
+        let pages = desc
+            .pages
+            .iter()
+            .map(|file| {
+                let data = load_page(file)?;
+                let (width, height, pixels) = crate::color::decode_png(&data)
+                    .ok_or_else(|| BitmapFontError::UnsupportedPage(file.clone()))?;
+                Ok(PageImage {
+                    width,
+                    height,
+                    pixels,
+                })
+            })
+            .collect::<Result<Vec<_>, BitmapFontError>>()?;
+
+        let mut glyphs = vec![BitmapGlyph::EMPTY];
+        let mut ids = HashMap::new();
+        let mut char_ids = HashMap::new();
+        for info in &desc.chars {
+            let glyph = BitmapGlyph {
+                offset: (info.xoffset, info.yoffset),
+                advance: info.xadvance,
+                width: info.width,
+                height: info.height,
+                pixels: match pages.get(info.page) {
+                    Some(page) if info.width > 0 && info.height > 0 => {
+                        page.glyph_pixels(info)?
+                    }
+                    Some(_) => Pixels::Coverage(Vec::new()),
+                    None => {
+                        return Err(BitmapFontError::InvalidDescriptor(format!(
+                            "character {} is on the missing page {}",
+                            info.id, info.page
+                        )))
+                    }
+                },
+            };
+            // The id -1 marks the glyph of missing characters.
+            let c = match u32::try_from(info.id).ok().and_then(char::from_u32) {
+                Some(c) => c,
+                None => {
+                    glyphs[0] = glyph;
+                    continue;
+                }
+            };
+            if glyphs.len() > u16::MAX as usize {
+                return Err(BitmapFontError::InvalidDescriptor(
+                    "more than 65535 characters".into(),
+                ));
+            }
+            let id = GlyphId(glyphs.len() as u16);
+            glyphs.push(glyph);
+            ids.insert(c, id);
+            char_ids.insert(info.id, id);
+        }
+
+        let kerning = desc
+            .kernings
+            .iter()
+            .filter_map(|&(first, second, amount)| {
+                Some(((*char_ids.get(&first)?, *char_ids.get(&second)?), amount))
+            })
+            .collect();
+
+        let mut chars: Vec<(char, GlyphId)> =
+            ids.iter().map(|(&c, &id)| (c, id)).collect();
+        chars.sort_unstable();
+        let cmap = FontVec::try_from_vec(cmap_font(&chars, glyphs.len() as u16))
+            .expect("invalid character map font");
+
+        Ok(BitmapFont {
+            inner: Arc::new(Inner {
+                descriptor: descriptor.to_vec(),
+                line_height: desc.line_height,
+                base: desc.base,
+                ids,
+                glyphs,
+                kerning,
+                cmap,
+            }),
+        })
+    }
+
+    /// Distance between two lines of text in pixels, the text scale which draws
+    /// the glyphs at the size they were made for.
+    #[inline]
+    pub fn line_height(&self) -> f32 {
+        self.inner.line_height
+    }
+
+    /// Returns the bounds of the `glyph` in pixels together with its pixels.
+    pub(crate) fn image(&self, glyph: &Glyph) -> Option<(Rect, &BitmapGlyph)> {
+        let image = self.glyph(glyph.id)?;
+        let scale = (
+            glyph.scale.x / self.inner.line_height,
+            glyph.scale.y / self.inner.line_height,
+        );
+        let min = point(
+            glyph.position.x + image.offset.0 * scale.0,
+            glyph.position.y + (image.offset.1 - self.inner.base) * scale.1,
+        );
+        let bounds = Rect {
+            min,
+            max: point(
+                min.x + image.width as f32 * scale.0,
+                min.y + image.height as f32 * scale.1,
+            ),
+        };
+        Some((bounds, image))
+    }
+
+    fn glyph(&self, id: GlyphId) -> Option<&BitmapGlyph> {
+        self.inner.glyphs.get(id.0 as usize)
+    }
+}
+
+impl std::fmt::Debug for BitmapFont {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BitmapFont")
+            .field("line_height", &self.inner.line_height)
+            .field("glyphs", &self.inner.glyphs.len())
+            .finish()
+    }
+}
+
+impl Font for BitmapFont {
+    #[inline]
+    fn units_per_em(&self) -> Option<f32> {
+        Some(self.inner.line_height)
+    }
+
+    #[inline]
+    fn ascent_unscaled(&self) -> f32 {
+        self.inner.base
+    }
+
+    #[inline]
+    fn descent_unscaled(&self) -> f32 {
+        self.inner.base - self.inner.line_height
+    }
+
+    #[inline]
+    fn line_gap_unscaled(&self) -> f32 {
+        0.0
+    }
+
+    #[inline]
+    fn glyph_id(&self, c: char) -> GlyphId {
+        self.inner.ids.get(&c).copied().unwrap_or(GlyphId(0))
+    }
+
+    #[inline]
+    fn h_advance_unscaled(&self, id: GlyphId) -> f32 {
+        self.glyph(id).map_or(0.0, |glyph| glyph.advance)
+    }
+
+    #[inline]
+    fn h_side_bearing_unscaled(&self, id: GlyphId) -> f32 {
+        self.glyph(id).map_or(0.0, |glyph| glyph.offset.0)
+    }
+
+    #[inline]
+    fn v_advance_unscaled(&self, _: GlyphId) -> f32 {
+        self.inner.line_height
+    }
+
+    #[inline]
+    fn v_side_bearing_unscaled(&self, id: GlyphId) -> f32 {
+        self.glyph(id).map_or(0.0, |glyph| glyph.offset.1)
+    }
+
+    #[inline]
+    fn kern_unscaled(&self, first: GlyphId, second: GlyphId) -> f32 {
+        self.inner
+            .kerning
+            .get(&(first, second))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Bitmap glyphs have no outlines, they are drawn from their pixels.
+    #[inline]
+    fn outline(&self, _: GlyphId) -> Option<Outline> {
+        None
+    }
+
+    #[inline]
+    fn glyph_count(&self) -> usize {
+        self.inner.glyphs.len()
+    }
+
+    #[inline]
+    fn codepoint_ids(&self) -> CodepointIdIter<'_> {
+        self.inner.cmap.codepoint_ids()
+    }
+
+    #[inline]
+    fn glyph_raster_image2(&self, _: GlyphId, _: u16) -> Option<v2::GlyphImage<'_>> {
+        None
+    }
+
+    #[inline]
+    fn font_data(&self) -> &[u8] {
+        &self.inner.descriptor
+    }
+}
+
+impl From<BitmapFont> for FontArc {
+    #[inline]
+    fn from(font: BitmapFont) -> Self {
+        FontArc::new(font)
+    }
+}
+
+/// Number of pages a descriptor may have, the binary format stores page ids in
+/// a byte.
+const MAX_PAGES: usize = 256;
+
+/// Character of the descriptor.
+struct CharInfo {
+    /// Unicode code point, `-1` for the glyph of missing characters.
+    id: i64,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    xoffset: f32,
+    yoffset: f32,
+    xadvance: f32,
+    page: usize,
+    /// Color channels holding the glyph, `1` blue, `2` green, `4` red and `8`
+    /// alpha.
+    chnl: u8,
+}
+
+#[derive(Default)]
+struct Descriptor {
+    line_height: f32,
+    base: f32,
+    pages: Vec<String>,
+    chars: Vec<CharInfo>,
+    kernings: Vec<(i64, i64, f32)>,
+}
+
+impl Descriptor {
+    fn parse_text(text: &str) -> Result<Self, BitmapFontError> {
+        let mut desc = Descriptor::default();
+        for (number, line) in text.lines().enumerate() {
+            let Some((tag, pairs)) = split_line(line) else {
+                continue;
+            };
+            let invalid = |key: &str| {
+                BitmapFontError::InvalidDescriptor(format!(
+                    "invalid or missing '{}' in line {}",
+                    key,
+                    number + 1
+                ))
+            };
+            let value = |key: &str| {
+                pairs
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.as_str())
+            };
+            let int = |key: &str| -> Result<i64, BitmapFontError> {
+                value(key)
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| invalid(key))
+            };
+            let uint = |key: &str| -> Result<u32, BitmapFontError> {
+                u32::try_from(int(key)?).map_err(|_| invalid(key))
+            };
+
+            match tag {
+                "common" => {
+                    desc.line_height = int("lineHeight")? as f32;
+                    desc.base = int("base")? as f32;
+                }
+                "page" => {
+                    let id = uint("id")? as usize;
+                    if id >= MAX_PAGES {
+                        return Err(invalid("id"));
+                    }
+                    let file = value("file").ok_or_else(|| invalid("file"))?;
+                    if desc.pages.len() <= id {
+                        desc.pages.resize(id + 1, String::new());
+                    }
+                    desc.pages[id] = file.to_string();
+                }
+                "char" => desc.chars.push(CharInfo {
+                    id: int("id")?,
+                    x: uint("x")?,
+                    y: uint("y")?,
+                    width: uint("width")?,
+                    height: uint("height")?,
+                    xoffset: int("xoffset")? as f32,
+                    yoffset: int("yoffset")? as f32,
+                    xadvance: int("xadvance")? as f32,
+                    page: value("page").map_or(Ok(0), |_| uint("page"))? as usize,
+                    chnl: value("chnl").map_or(Ok(15), |_| uint("chnl"))? as u8,
+                }),
+                "kerning" => desc.kernings.push((
+                    int("first")?,
+                    int("second")?,
+                    int("amount")? as f32,
+                )),
+                _ => (),
+            }
+        }
+        Ok(desc)
+    }
+
+    fn parse_binary(data: &[u8]) -> Result<Self, BitmapFontError> {
+        let invalid = |reason: &str| BitmapFontError::InvalidDescriptor(reason.into());
+        if data.get(3) != Some(&3) {
+            return Err(invalid("only version 3 of the binary format is supported"));
+        }
+
+        let u16_at =
+            |block: &[u8], at: usize| u16::from_le_bytes([block[at], block[at + 1]]);
+        let i16_at =
+            |block: &[u8], at: usize| i16::from_le_bytes([block[at], block[at + 1]]);
+        let u32_at = |block: &[u8], at: usize| {
+            u32::from_le_bytes([block[at], block[at + 1], block[at + 2], block[at + 3]])
+        };
+
+        let mut desc = Descriptor::default();
+        let mut rest = &data[4..];
+        while !rest.is_empty() {
+            if rest.len() < 5 {
+                return Err(invalid("truncated block header"));
+            }
+            let kind = rest[0];
+            let end = (u32_at(rest, 1) as usize)
+                .checked_add(5)
+                .filter(|&end| end <= rest.len())
+                .ok_or_else(|| invalid("truncated block"))?;
+            let block = &rest[5..end];
+            let size = block.len();
+            rest = &rest[end..];
+
+            match kind {
+                2 if size >= 4 => {
+                    desc.line_height = u16_at(block, 0) as f32;
+                    desc.base = u16_at(block, 2) as f32;
+                }
+                // File names, each terminated by a zero.
+                3 => {
+                    desc.pages = block
+                        .split(|&b| b == 0)
+                        .filter(|name| !name.is_empty())
+                        .map(|name| String::from_utf8_lossy(name).into_owned())
+                        .collect();
+                    if desc.pages.len() > MAX_PAGES {
+                        return Err(invalid("too many pages"));
+                    }
+                }
+                4 => {
+                    desc.chars = block
+                        .chunks_exact(20)
+                        .map(|c| CharInfo {
+                            // `-1` is stored as a 32-bit unsigned integer.
+                            id: u32_at(c, 0) as i32 as i64,
+                            x: u16_at(c, 4) as u32,
+                            y: u16_at(c, 6) as u32,
+                            width: u16_at(c, 8) as u32,
+                            height: u16_at(c, 10) as u32,
+                            xoffset: i16_at(c, 12) as f32,
+                            yoffset: i16_at(c, 14) as f32,
+                            xadvance: i16_at(c, 16) as f32,
+                            page: c[18] as usize,
+                            chnl: c[19],
+                        })
+                        .collect();
+                }
+                5 => {
+                    desc.kernings = block
+                        .chunks_exact(10)
+                        .map(|k| {
+                            (
+                                u32_at(k, 0) as i32 as i64,
+                                u32_at(k, 4) as i32 as i64,
+                                i16_at(k, 8) as f32,
+                            )
+                        })
+                        .collect();
+                }
+                _ => (),
+            }
+        }
+        Ok(desc)
+    }
+}
+
+/// Splits a line of a text descriptor into its tag and `key=value` pairs, values
+/// may be quoted.
+fn split_line(line: &str) -> Option<(&str, Vec<(&str, String)>)> {
+    let line = line.trim();
+    let (tag, mut rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    if tag.is_empty() {
+        return None;
+    }
+
+    let mut pairs = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        let Some((key, after)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(char::is_whitespace).unwrap_or((after, "")),
+        };
+        pairs.push((key.trim(), value.to_string()));
+        rest = after;
+    }
+    Some((tag, pairs))
+}
+
+struct PageImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 4]>,
+}
+
+impl PageImage {
+    /// Cuts the pixels of the character `info` out of the page.
+    fn glyph_pixels(&self, info: &CharInfo) -> Result<Pixels, BitmapFontError> {
+        let inside = |start: u32, size: u32, page: u32| {
+            start.checked_add(size).is_some_and(|end| end <= page)
+        };
+        if !inside(info.x, info.width, self.width)
+            || !inside(info.y, info.height, self.height)
+        {
+            return Err(BitmapFontError::InvalidDescriptor(format!(
+                "character {} reaches outside of page {}",
+                info.id, info.page
+            )));
+        }
+        let pixels: Vec<[u8; 4]> = (info.y..info.y + info.height)
+            .flat_map(|y| {
+                let start = (y * self.width + info.x) as usize;
+                self.pixels[start..start + info.width as usize]
+                    .iter()
+                    .copied()
+            })
+            .collect();
+
+        // Packed fonts keep a glyph in each channel.
+        let channel = match info.chnl {
+            1 => Some(2),
+            2 => Some(1),
+            4 => Some(0),
+            8 => Some(3),
+            _ => None,
+        };
+        let gray = |p: &[u8; 4]| p[0] == p[1] && p[1] == p[2];
+        Ok(if let Some(channel) = channel {
+            Pixels::Coverage(pixels.iter().map(|p| p[channel]).collect())
+        } else if pixels.iter().all(|p| p[3] == 0 || p[..3] == [255; 3]) {
+            Pixels::Coverage(pixels.iter().map(|p| p[3]).collect())
+        } else if pixels.iter().all(|p| p[3] == 255 && gray(p)) {
+            // Images without transparency, white on black.
+            Pixels::Coverage(pixels.iter().map(|p| p[0]).collect())
+        } else {
+            Pixels::Color(pixels)
+        })
+    }
+}
+
+/// TrueType font without glyphs, only mapping the `chars` to their glyph ids.
+/// [`Font::codepoint_ids()`] can't be implemented otherwise.
+fn cmap_font(chars: &[(char, GlyphId)], glyph_count: u16) -> Vec<u8> {
+    fn table(data: &mut Vec<u8>, values: &[u32], sizes: &[usize]) {
+        for (&value, &size) in values.iter().zip(sizes) {
+            data.extend_from_slice(&value.to_be_bytes()[4 - size..]);
+        }
+    }
+
+    let mut cmap = Vec::new();
+    // A single Unicode encoding record, pointing at a segmented coverage subtable
+    // with a group of each character.
+    table(&mut cmap, &[0, 1, 3, 10, 12], &[2, 2, 2, 2, 4]);
+    let length = 16 + 12 * chars.len() as u32;
+    table(
+        &mut cmap,
+        &[12, 0, length, 0, chars.len() as u32],
+        &[2, 2, 4, 4, 4],
+    );
+    for &(c, id) in chars {
+        table(&mut cmap, &[c as u32, c as u32, id.0 as u32], &[4, 4, 4]);
+    }
+
+    let mut head = Vec::new();
+    table(
+        &mut head,
+        &[0x0001_0000, 0x0001_0000, 0, 0x5F0F_3CF5, 0, 1000],
+        &[4, 4, 4, 4, 2, 2],
+    );
+    head.resize(54, 0);
+
+    let mut hhea = Vec::new();
+    table(&mut hhea, &[0x0001_0000], &[4]);
+    hhea.resize(34, 0);
+    table(&mut hhea, &[1], &[2]);
+
+    let mut maxp = Vec::new();
+    table(&mut maxp, &[0x0000_5000, glyph_count as u32], &[4, 2]);
+
+    // Table records have to be sorted by their tags.
+    let tables = [
+        (b"cmap", cmap),
+        (b"head", head),
+        (b"hhea", hhea),
+        (b"maxp", maxp),
+    ];
+    let mut font = Vec::new();
+    table(&mut font, &[0x0001_0000, 4, 64, 2, 0], &[4, 2, 2, 2, 2]);
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, data) in &tables {
+        font.extend_from_slice(*tag);
+        table(
+            &mut font,
+            &[0, offset as u32, data.len() as u32],
+            &[4, 4, 4],
+        );
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in &tables {
+        font.extend_from_slice(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    font
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "info face=\"Test\" size=8\n\
+        common lineHeight=8 base=6 scaleW=4 scaleH=2 pages=1\n\
+        page id=0 file=\"test_0.png\"\n\
+        chars count=2\n\
+        char id=65 x=0 y=0 width=2 height=2 xoffset=0 yoffset=1 xadvance=3 page=0 chnl=15\n\
+        char id=66 x=2 y=0 width=2 height=2 xoffset=1 yoffset=2 xadvance=4 page=0 chnl=15\n\
+        kernings count=1\n\
+        kerning first=65 second=66 amount=-1\n";
+
+    /// White page of 4x2 pixels, with the left glyph opaque and the right one
+    /// half transparent.
+    fn page() -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, 4, 2);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let pixels: Vec<u8> = (0..2)
+            .flat_map(|_| [255, 255, 128, 128])
+            .flat_map(|alpha| [255, 255, 255, alpha])
+            .collect();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+        data
+    }
+
+    fn load(descriptor: &[u8]) -> Result<BitmapFont, BitmapFontError> {
+        BitmapFont::from_fnt(descriptor, |file| {
+            assert_eq!(file, "test_0.png");
+            Ok(page())
+        })
+    }
+
+    /// The binary version of [`TEXT`].
+    fn binary() -> Vec<u8> {
+        let mut data = b"BMF\x03".to_vec();
+        let mut block = |kind: u8, content: &[u8]| {
+            data.push(kind);
+            data.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            data.extend_from_slice(content);
+        };
+        let mut common = [0; 15];
+        common[..4].copy_from_slice(&[8, 0, 6, 0]);
+        block(2, &common);
+        block(3, b"test_0.png\0");
+        let char_info = |id: u32, x: u16, offset: (i16, i16), advance: i16| {
+            let mut info = id.to_le_bytes().to_vec();
+            for value in [x, 0, 2, 2] {
+                info.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in [offset.0, offset.1, advance] {
+                info.extend_from_slice(&value.to_le_bytes());
+            }
+            info.extend_from_slice(&[0, 15]);
+            info
+        };
+        let mut chars = char_info(65, 0, (0, 1), 3);
+        chars.extend(char_info(66, 2, (1, 2), 4));
+        block(4, &chars);
+        let mut kerning = 65u32.to_le_bytes().to_vec();
+        kerning.extend_from_slice(&66u32.to_le_bytes());
+        kerning.extend_from_slice(&(-1i16).to_le_bytes());
+        block(5, &kerning);
+        data
+    }
+
+    fn assert_test_font(font: &BitmapFont) {
+        assert_eq!(font.line_height(), 8.0);
+        assert_eq!(font.ascent_unscaled(), 6.0);
+        let (a, b) = (font.glyph_id('A'), font.glyph_id('B'));
+        assert_ne!(a, b);
+        assert_eq!(font.glyph_id('C'), GlyphId(0));
+        assert_eq!(font.h_advance_unscaled(a), 3.0);
+        assert_eq!(font.h_side_bearing_unscaled(b), 1.0);
+        assert_eq!(font.kern_unscaled(a, b), -1.0);
+        assert_eq!(font.kern_unscaled(b, a), 0.0);
+
+        let ids: Vec<_> = font.codepoint_ids().collect();
+        assert!(ids.contains(&(a, 'A')) && ids.contains(&(b, 'B')));
+
+        let coverage = font.glyph(b).unwrap().coverage();
+        assert_eq!(coverage.len(), 4);
+        assert!(coverage.iter().all(|&c| (c - 128.0 / 255.0).abs() < 1e-6));
+        assert!(font.glyph(a).unwrap().coverage().iter().all(|&c| c == 1.0));
+    }
+
+    fn assert_invalid(result: Result<BitmapFont, BitmapFontError>) {
+        assert!(matches!(result, Err(BitmapFontError::InvalidDescriptor(_))));
+    }
+
+    #[test]
+    fn parses_text_descriptor() {
+        assert_test_font(&load(TEXT.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn parses_binary_descriptor() {
+        assert_test_font(&load(&binary()).unwrap());
+    }
+
+    #[test]
+    fn rejects_huge_page_id() {
+        let text = TEXT.replace("page id=0", "page id=4294967295");
+        assert_invalid(load(text.as_bytes()));
+    }
+
+    #[test]
+    fn rejects_characters_outside_of_page() {
+        let text = TEXT.replace("char id=66 x=2", "char id=66 x=3");
+        assert_invalid(load(text.as_bytes()));
+        let text = TEXT.replace("char id=66 x=2", "char id=66 x=4294967295");
+        assert_invalid(load(text.as_bytes()));
+        let text = TEXT.replace(
+            "y=0 width=2 height=2 xoffset=1",
+            "y=4294967295 width=2 height=2 xoffset=1",
+        );
+        assert_invalid(load(text.as_bytes()));
+    }
+
+    #[test]
+    fn rejects_missing_line_height() {
+        let text = TEXT.replace("common lineHeight=8", "common");
+        assert_invalid(load(text.as_bytes()));
+    }
+
+    #[test]
+    fn rejects_malformed_binary() {
+        let mut data = binary();
+        data[3] = 2;
+        assert_invalid(load(&data));
+
+        let data = binary();
+        assert_invalid(load(&data[..data.len() - 1]));
+
+        // A block claiming to be bigger than the whole file.
+        let mut data = binary();
+        data.extend_from_slice(&[4, 255, 255, 255, 255]);
+        assert_invalid(load(&data));
+    }
+}
@@ -1,21 +1,100 @@
-use std::num::NonZeroU32;
+use std::{borrow::Cow, num::NonZeroU32, ops::Range, sync::Arc};
 
 use crate::{
+    atlas::{AtlasFull, Page, RenderMode},
+    bitmap::BitmapFont,
+    cache::CacheSettings,
+    debug::{self, DebugOverlay},
     error::BrushError,
+    headless::Target,
+    layout::LayoutCache,
+    params::{Effects, Params},
     pipeline::{Pipeline, Vertex},
-    Matrix,
+    retained::{Changes, Entry, Retained, TextHandle},
+    shared::AtlasState,
+    stats::{BrushStats, FrameStats},
+    subpixel,
+    vertex_buffer::VertexBufferSettings,
+    AtlasBuilder, AtlasEvent, Image, LayeredSection, Matrix, SharedAtlas, TextExtra,
+    Transform,
 };
 use glyph_brush::{
-    ab_glyph::{Font, FontArc, FontRef, InvalidFont, Rect},
-    BrushAction, DefaultSectionHasher, Extra, GlyphCruncher, Section, SectionGlyphIter,
+    ab_glyph::{point, Font, FontArc, FontRef, Glyph, InvalidFont, Point, Rect},
+    DefaultSectionHasher, FontId, GlyphPositioner, GlyphVertex, Layout, Section,
+    SectionGeometry, SectionGlyph, SectionGlyphIter, Text,
 };
 
-/// Wrapper over [`glyph_brush::GlyphBrush`]. In charge of drawing text.
+/// Lays out text with [`glyph_brush`] and is in charge of drawing it.
 ///
 /// Used for queuing and rendering text with [`TextBrush::draw`].
 pub struct TextBrush<F = FontArc, H = DefaultSectionHasher> {
-    inner: glyph_brush::GlyphBrush<Vertex, Extra, F, H>,
+    fonts: Vec<F>,
+    layouts: LayoutCache<H>,
     pipeline: Pipeline,
+    atlas: SharedAtlas,
+    /// Atlas font ids of the fonts, by `FontId`.
+    font_ids: Vec<usize>,
+    /// Atlas epoch the vertices were created at.
+    epoch: u64,
+
+    /// Skip building the vertices of queued sections which didn't change, see
+    /// [`cache_redraws()`](glyph_brush::GlyphBrushBuilder::cache_redraws).
+    cache_redraws: bool,
+    /// Hash of the sections queued last, `None` if their vertices have to be
+    /// built again anyway. See [`LayoutCache::hash_sections()`].
+    queued_hash: Option<u64>,
+    /// Vertices of the queued sections, before they are moved behind the
+    /// retained ones.
+    queued: Vec<Vertex>,
+    /// Parameters the queued vertices index into.
+    queued_params: Params,
+    /// Offset of the queued vertices behind the retained ones.
+    queued_offset: usize,
+    /// Offset of the queued parameters behind the retained ones.
+    params_offset: u32,
+    /// Instance ranges of the queued layers, sorted by layer.
+    layers: Vec<(u32, Range<u32>)>,
+    retained: Retained,
+    /// Instance ranges of the retained and queued layers in the vertex buffer,
+    /// sorted by layer.
+    ranges: Vec<(u32, Range<u32>)>,
+    /// Starts of the runs of glyphs blended together, see
+    /// [`Pipeline::draw_range()`].
+    runs: Vec<u32>,
+    /// The runs of the queued vertices.
+    queued_runs: Vec<u32>,
+
+    frame_stats: FrameStats,
+    total_stats: FrameStats,
+    frames: u64,
+
+    debug_overlay: DebugOverlay,
+    /// Outlines of the debug overlay, collected while processing sections.
+    debug_outlines: Vec<Vertex>,
+    /// Size of the view in pixels, where the debug overlay places the atlas.
+    view_size: (f32, f32),
+
+    pixel_snapping: bool,
+    /// Physical pixels per pixel of the view.
+    scale_factor: f32,
+}
+
+/// Result of [`TextBrush::prewarm()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrewarmReport {
+    /// Number of glyphs which had to be rasterized. Includes glyphs without
+    /// anything to draw, like spaces.
+    pub rasterized: usize,
+    /// Size of the cache texture holding all the glyphs.
+    pub cache_size: (u32, u32),
+    /// Number of pages of this size they need, more than one if the cache texture
+    /// reached the largest size of the device.
+    pub cache_pages: u32,
+    /// Size of the cache texture holding color glyphs, `(1, 1)` if they are
+    /// disabled.
+    pub color_cache_size: (u32, u32),
+    /// Number of pages of color glyphs.
+    pub color_cache_pages: u32,
 }
 
 impl<F, H> TextBrush<F, H>
@@ -24,8 +103,8 @@ where
     H: std::hash::BuildHasher,
 {
     /// Queues section for drawing, processes all queued text and updates the
-    /// inner vertex buffer, unless the text vertices remain unmodified when
-    /// compared to the last frame.
+    /// inner vertex buffer, unless the sections remain unmodified when compared
+    /// to the last frame.
     ///
     /// If utilizing *depth*, the `sections` list should have `Section`s ordered from
     /// furthest to closest. They will be drawn in the order they are given.
@@ -35,106 +114,873 @@ where
     /// If not called when required, the draw functions will continue drawing data from the
     /// inner vertex buffer meaning they will redraw old vertices.
     ///
+    /// Sections may use the plain [`glyph_brush::Extra`] or [`TextExtra`] for
+    /// text effects like outlines.
+    ///
+    /// Sections can be given with a layer, as `(layer, section)` tuples, to draw
+    /// them at different points of the render order with
+    /// [`draw_layer()`](#method.draw_layer). Sections without one go into the
+    /// layer `0`. See [`LayeredSection`].
+    ///
+    /// Also lays out and uploads the sections retained with
+    /// [`insert()`](#method.insert) which changed since the last call.
+    ///
     /// To learn about GPU texture caching, see
     /// [`caching behaviour`](https://docs.rs/glyph_brush/latest/glyph_brush/struct.GlyphBrush.html#caching-behaviour)
     #[inline]
-    pub fn queue<'a, S>(
+    pub fn queue<'a, X, S>(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         sections: Vec<S>,
     ) -> Result<(), BrushError>
     where
-        S: Into<std::borrow::Cow<'a, Section<'a>>>,
+        X: Clone + Into<TextExtra> + 'a,
+        S: Into<LayeredSection<'a, X>>,
     {
-        // Queue sections:
-        for s in sections {
-            self.inner.queue(s);
-        }
-
-        // Process sections:
-        loop {
-            // Contains BrushAction enum which marks for
-            // drawing or redrawing (using old data).
-            let brush_action = self.inner.process_queued(
-                |rect, data| self.pipeline.update_texture(rect, data, queue),
-                Vertex::to_vertex,
-            );
+        let mut sections: Vec<LayeredSection<'a, X>> =
+            sections.into_iter().map(Into::into).collect();
+        // Stable, keeps the order of sections within a layer.
+        sections.sort_by_key(|section| section.layer);
+        let atlas = self.atlas.clone();
+        let mut state = atlas.lock();
+        let mut stats = FrameStats {
+            cache_upload_bytes: state.upload_pending(),
+            ..FrameStats::default()
+        };
+        let mut cleared = false;
+        let hash = self.layouts.hash_sections(&sections);
+        if !self.cache_redraws || self.queued_hash != Some(hash) {
+            self.queued_hash = None;
+        }
+
+        // Process sections, the queued ones only if they or the cache texture
+        // changed:
+        let laid_out = loop {
+            let result = self
+                .process_retained(&mut state, &mut stats)
+                .and_then(|()| {
+                    let current =
+                        self.queued_hash.is_some() && self.epoch == state.epoch();
+                    if current {
+                        return Ok(false);
+                    }
+                    self.process_sections(&sections, &mut state, &mut stats)
+                        .map(|()| true)
+                });
+            match result {
+                Ok(laid_out) => break laid_out,
 
-            match brush_action {
-                Ok(action) => {
-                    break match action {
-                        BrushAction::Draw(vertices) => {
-                            self.pipeline.update_vertex_buffer(vertices, device, queue)
+                // Texture resizing, cached glyphs are kept:
+                Err(AtlasFull(page)) => match state.grow(device, queue, page) {
+                    Ok(()) => {
+                        stats.cache_grows += 1;
+                        if log::log_enabled!(log::Level::Warn) {
+                            log::warn!(
+                                "Resizing cache texture! This should be avoided \
+                                by building TextBrush with BrushBuilder::initial_cache_size() \
+                                and providing bigger cache texture dimensions."
+                            );
                         }
-                        BrushAction::ReDraw => (),
                     }
+
+                    // Make room by dropping glyphs which weren't needed this frame.
+                    Err(_) if !cleared => {
+                        state.clear();
+                        stats.cache_clears += 1;
+                        cleared = true;
+                    }
+
+                    Err(error) => return Err(error),
+                },
+            }
+        };
+        stats.cache_uploads += state.cache.flush_uploads(device, queue);
+        self.epoch = state.epoch();
+        self.pipeline.update_textures(&state.cache);
+        drop(state);
+        self.queued_hash = Some(hash);
+        self.layouts.trim(laid_out);
+
+        // Retained sections come first in the vertex buffer, followed by the
+        // queued ones. Only the parts which changed are written.
+        let changes = self.retained.join();
+        let retained = self.retained.vertices.as_slice();
+        let offset = retained.len();
+        stats.glyphs = offset + self.queued.len();
+        let reallocated = self.pipeline.reserve_vertices(stats.glyphs, device);
+        if reallocated {
+            stats.vertex_buffer_reallocations += 1;
+            self.pipeline.write_vertices(0, retained, device);
+            stats.vertex_upload_bytes += std::mem::size_of_val(retained) as u64;
+        } else if let Some(Changes { vertices, .. }) = &changes {
+            let changed = &retained[vertices.clone()];
+            self.pipeline
+                .write_vertices(vertices.start, changed, device);
+            stats.vertex_upload_bytes += std::mem::size_of_val(changed) as u64;
+        }
+
+        // The parameters of the queued sections follow the retained ones, they
+        // move together with the queued vertices.
+        let params_offset = self.retained.params.len() as u32 - 1;
+        let moved = offset != self.queued_offset || params_offset != self.params_offset;
+        self.queued_offset = offset;
+        self.params_offset = params_offset;
+        match self.write_params(changes.as_ref(), laid_out || moved, device, queue) {
+            Ok(bytes) => stats.vertex_upload_bytes += bytes,
+            Err(error) => {
+                // Nothing is drawn until the sections are queued again and their
+                // parameters fit.
+                self.queued_hash = None;
+                self.retained.invalidate();
+                self.ranges.clear();
+                self.pipeline.finish_frame(queue);
+                return Err(error);
+            }
+        }
+        if laid_out || moved || reallocated {
+            let mut vertices = self.queued.clone();
+            self.queued_runs.clear();
+            if self.pipeline.blends_in_two_passes() {
+                for (_, range) in &self.layers {
+                    let layer = &mut vertices[range.start as usize..range.end as usize];
+                    let start = offset as u32 + range.start;
+                    let params = &self.queued_params;
+                    subpixel::sort_runs(layer, params, start, &mut self.queued_runs);
                 }
+            }
+            for vertex in &mut vertices {
+                *vertex = vertex.rebased(params_offset);
+            }
+            self.pipeline.write_vertices(offset, &vertices, device);
+            stats.vertex_upload_bytes +=
+                std::mem::size_of_val(vertices.as_slice()) as u64;
+        }
+        if changes.is_some() || laid_out || moved {
+            self.runs.clone_from(&self.retained.runs);
+            self.runs.extend_from_slice(&self.queued_runs);
+        }
+        self.pipeline.finish_frame(queue);
 
-                Err(glyph_brush::BrushError::TextureTooSmall { suggested }) => {
-                    if log::log_enabled!(log::Level::Warn) {
-                        log::warn!(
-                            "Resizing cache texture! This should be avoided \
-                            by building TextBrush with BrushBuilder::initial_cache_size() \
-                            and providing bigger cache texture dimensions."
-                        );
+        // Stable, retained sections are drawn before the queued ones of a layer.
+        self.ranges.clone_from(&self.retained.layers_ranges);
+        self.ranges.extend(self.layers.iter().map(|(layer, range)| {
+            (
+                *layer,
+                range.start + offset as u32..range.end + offset as u32,
+            )
+        }));
+        self.ranges.sort_by_key(|(layer, _)| *layer);
+
+        if self.debug_overlay.is_enabled() {
+            let mut vertices: Vec<Vertex> = self
+                .retained
+                .outlines()
+                .chain(
+                    self.debug_outlines
+                        .iter()
+                        .map(|outline| outline.rebased(self.params_offset)),
+                )
+                .collect();
+            let outlines_len = vertices.len();
+            if self.debug_overlay.atlas {
+                vertices.extend(debug::atlas_pages(&atlas.lock().atlas, self.view_size));
+            }
+            self.pipeline
+                .update_debug(&vertices, outlines_len, device, queue);
+        }
+
+        self.frame_stats = stats;
+        self.total_stats += stats;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Writes the parameters of the retained sections which `changed`, and the
+    /// ones of the queued sections if they were laid out again or `moved`.
+    fn write_params(
+        &mut self,
+        changes: Option<&Changes>,
+        queued: bool,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<u64, BrushError> {
+        let mut bytes = 0;
+        if let Some(Changes { texels, .. }) = changes {
+            let at = texels.start;
+            let texels = &self.retained.params.texels()[texels.clone()];
+            bytes += self.pipeline.write_params(at, texels, device, queue)?;
+        }
+        if queued {
+            let at = self.params_offset as usize + 1;
+            let texels = &self.queued_params.texels()[1..];
+            bytes += self.pipeline.write_params(at, texels, device, queue)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Lays out the retained sections which changed, or all of them if the cache
+    /// texture was cleared or resized since they were laid out.
+    fn process_retained(
+        &mut self,
+        state: &mut AtlasState,
+        stats: &mut FrameStats,
+    ) -> Result<(), AtlasFull> {
+        if self.retained.epoch != state.epoch() {
+            self.retained.invalidate();
+            self.retained.epoch = state.epoch();
+        }
+        let mut sections = std::mem::take(&mut self.retained.sections);
+        let mut result = Ok(());
+        for (&(layer, _), entry) in sections.iter_mut().filter(|(_, entry)| entry.dirty) {
+            let start = self.debug_outlines.len();
+            entry.vertices.clear();
+            entry.params.clear();
+            let section = LayeredSection {
+                layer,
+                section: Cow::Owned(entry.section.to_borrowed()),
+                transform: entry.transform,
+                billboard: entry.billboard,
+            };
+            result = self.process_section(
+                &section,
+                state,
+                stats,
+                &mut entry.vertices,
+                &mut entry.params,
+            );
+            entry.outlines = self.debug_outlines.split_off(start);
+            if result.is_err() {
+                break;
+            }
+            entry.dirty = false;
+            entry.pending = true;
+            self.retained.changed = true;
+        }
+        self.retained.sections = sections;
+        result
+    }
+
+    /// Lays out the `sections`, rasterizes glyphs missing from the cache texture
+    /// and returns the vertices of all visible glyphs with the parameters they
+    /// index into. Records the vertex range of every layer, `sections` have to be
+    /// sorted by layer. Counts the uploaded glyphs into `stats`.
+    fn process_sections<X>(
+        &mut self,
+        sections: &[LayeredSection<X>],
+        state: &mut AtlasState,
+        stats: &mut FrameStats,
+    ) -> Result<(), AtlasFull>
+    where
+        X: Clone + Into<TextExtra>,
+    {
+        let mut vertices = std::mem::take(&mut self.queued);
+        let mut params = std::mem::take(&mut self.queued_params);
+        vertices.clear();
+        params.clear();
+        self.layers.clear();
+        self.debug_outlines.clear();
+        let mut result = Ok(());
+        for section in sections {
+            let start = vertices.len() as u32;
+            match self.layers.last_mut() {
+                Some((last, _)) if *last == section.layer => (),
+                _ => self.layers.push((section.layer, start..start)),
+            }
+            result =
+                self.process_section(section, state, stats, &mut vertices, &mut params);
+            if result.is_err() {
+                break;
+            }
+            if let Some((_, range)) = self.layers.last_mut() {
+                range.end = vertices.len() as u32;
+            }
+        }
+        self.queued = vertices;
+        self.queued_params = params;
+        result
+    }
+
+    /// Lays out the `section` and appends the vertices of its visible glyphs to
+    /// `vertices`, the section transform and text effects they index into to
+    /// `params` and its debug outlines to the ones of the brush.
+    fn process_section<X>(
+        &mut self,
+        layered: &LayeredSection<X>,
+        state: &mut AtlasState,
+        stats: &mut FrameStats,
+        vertices: &mut Vec<Vertex>,
+        params: &mut Params,
+    ) -> Result<(), AtlasFull>
+    where
+        X: Clone + Into<TextExtra>,
+    {
+        let section: &Section<X> = &layered.section;
+        let billboard = layered.billboard;
+        let identity: Matrix = Transform::default().into();
+        let transform = if layered.transform == identity {
+            0
+        } else {
+            params.push_transform(layered.transform)
+        };
+        let overlay = self.debug_overlay;
+        // Positions are rounded to physical pixels while snapping.
+        let scale_factor = self.scale_factor;
+        let snap = self.pixel_snapping;
+        let mut bounds = section.layout.bounds_rect(&SectionGeometry::from(section));
+        if snap {
+            bounds.min.x = (bounds.min.x * scale_factor).floor() / scale_factor;
+            bounds.min.y = (bounds.min.y * scale_factor).floor() / scale_factor;
+            bounds.max.x = (bounds.max.x * scale_factor).ceil() / scale_factor;
+            bounds.max.y = (bounds.max.y * scale_factor).ceil() / scale_factor;
+        }
+        if overlay.section_bounds || overlay.layout_bounds {
+            if let Some(text) = section.text.first() {
+                let extra: TextExtra = text.extra.clone().into();
+                if overlay.section_bounds {
+                    self.debug_outlines.push(
+                        debug::outline(bounds, &extra, debug::SECTION_BOUNDS_COLOR)
+                            .with_section(transform, billboard),
+                    );
+                }
+                if let Some(layout_bounds) = overlay
+                    .layout_bounds
+                    .then(|| self.layouts.glyph_bounds(&self.fonts, section))
+                    .flatten()
+                {
+                    self.debug_outlines.push(
+                        debug::outline(layout_bounds, &extra, debug::LAYOUT_BOUNDS_COLOR)
+                            .with_section(transform, billboard),
+                    );
+                }
+            }
+        }
+        let glyphs = self.layouts.glyphs(&self.fonts, section).to_vec();
+        let mut text_effects: Vec<(Effects, u32)> = Vec::new();
+
+        for SectionGlyph {
+            section_index,
+            mut glyph,
+            font_id,
+            ..
+        } in glyphs
+        {
+            self.snap(&mut glyph);
+            let font = &self.fonts[font_id.0];
+            let AtlasState { atlas, cache, .. } = state;
+            let coords = atlas.glyph(
+                font,
+                self.font_ids[font_id.0],
+                &glyph,
+                |page, index, rect, data| {
+                    stats.glyphs_rasterized += 1;
+                    stats.cache_upload_bytes += data.len() as u64;
+                    cache.update_texture(page, index, rect, data)
+                },
+            )?;
+
+            if let Some((pixel_coords, tex_coords, page, index)) = coords {
+                let mut extra: TextExtra =
+                    section.text[section_index].extra.clone().into();
+                // The quad grown by the effects.
+                let mut quad = pixel_coords;
+                let mut effects_index = 0;
+
+                if page != Page::Color
+                    && (extra.outline_width > 0.0 || extra.shadow_color[3] > 0.0)
+                {
+                    let mut grow =
+                        effect_units(atlas.render_mode(), glyph.scale.y, &mut extra);
+                    if snap {
+                        // Keeps the top left corner of the quad on a pixel edge.
+                        grow = (grow * scale_factor).ceil() / scale_factor;
                     }
-                    // Texture resizing:
-                    let max_image_dimension = device.limits().max_texture_dimension_2d;
-                    let (width, height) = if suggested.0 > max_image_dimension
-                        || suggested.1 > max_image_dimension
+                    // Texels per pixel.
+                    let texel = point(
+                        tex_coords.width() / pixel_coords.width(),
+                        tex_coords.height() / pixel_coords.height(),
+                    );
+                    let effects = Effects {
+                        outline_color: extra.outline_color,
+                        shadow_color: extra.shadow_color,
+                        outline_width: extra.outline_width,
+                        shadow_blur: extra.shadow_blur,
+                        shadow_offset: [
+                            extra.shadow_offset[0] * texel.x,
+                            extra.shadow_offset[1] * texel.y,
+                        ],
+                        grow,
+                        tex_grow: [grow * texel.x, grow * texel.y],
+                        bounds,
+                    };
+                    // Glyphs of a text mostly share their effects.
+                    effects_index = match text_effects.iter().find(|(e, _)| *e == effects)
                     {
-                        if self.inner.texture_dimensions().0 < max_image_dimension
-                            || self.inner.texture_dimensions().1 < max_image_dimension
-                        {
-                            (max_image_dimension, max_image_dimension)
-                        } else {
-                            return Err(BrushError::TooBigCacheTexture(
-                                max_image_dimension,
-                            ));
+                        Some(&(_, index)) => index,
+                        None => {
+                            let index = params.push_effects(&effects);
+                            text_effects.push((effects, index));
+                            index
                         }
-                    } else {
-                        suggested
                     };
-                    self.pipeline.resize_texture(device, (width, height));
-                    self.inner.resize_texture(width, height);
+                    quad.min -= point(grow, grow);
+                    quad.max += point(grow, grow);
                 }
+
+                // Skip glyphs which are totally outside the bounds.
+                if quad.min.x > bounds.max.x
+                    || quad.min.y > bounds.max.y
+                    || bounds.min.x > quad.max.x
+                    || bounds.min.y > quad.max.y
+                {
+                    continue;
+                }
+                let to_vertex = |pixel_coords, bounds| {
+                    Vertex::to_vertex(GlyphVertex {
+                        tex_coords,
+                        pixel_coords,
+                        bounds,
+                        extra: &extra,
+                    })
+                    .with_section(transform, billboard)
+                };
+                if overlay.glyph_quads {
+                    self.debug_outlines.push(
+                        to_vertex(quad, bounds).as_outline(debug::GLYPH_QUAD_COLOR),
+                    );
+                }
+                // The shader clips the quads of glyphs with effects once it grew
+                // them.
+                let vertex = if effects_index == 0 {
+                    to_vertex(pixel_coords, bounds)
+                } else {
+                    to_vertex(pixel_coords, UNBOUNDED).with_effects(effects_index)
+                };
+                vertices.push(vertex.with_page(page, index));
             }
         }
+        stats.sections += 1;
         Ok(())
     }
 
+    /// Keeps the `section` for drawing in every frame until it is removed, and
+    /// returns a handle to update or remove it with. Like the sections given to
+    /// [`queue()`](#method.queue), it can be given with a layer.
+    ///
+    /// Retained sections are laid out and uploaded once, by the next
+    /// [`queue()`](#method.queue), and again only when they are updated or the
+    /// cache texture was cleared or resized. This saves the work of queuing
+    /// large amounts of static text every frame. They are drawn before the
+    /// queued sections of the same layer, in the order they were inserted.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use wgpu_text::{glyph_brush::{Section, Text}, TextBrush};
+    /// # let (device, queue): (wgpu::Device, wgpu::Queue) = todo!();
+    /// # let mut brush: TextBrush = todo!();
+    /// let score = brush.insert(Section::default().add_text(Text::new("Score: 0")));
+    ///
+    /// // Later, only this section is laid out again:
+    /// brush.update(score, Section::default().add_text(Text::new("Score: 10")));
+    /// brush.queue(&device, &queue, Vec::<Section>::new()).unwrap();
+    /// ```
+    pub fn insert<'a, X, S>(&mut self, section: S) -> TextHandle
+    where
+        X: Clone + Into<TextExtra> + 'a,
+        S: Into<LayeredSection<'a, X>>,
+    {
+        let (layer, entry) = Entry::new(&section.into());
+        self.retained.insert(layer, entry)
+    }
+
+    /// Replaces the retained section of the `handle`, see
+    /// [`insert()`](#method.insert). Returns `false` if it was removed already.
+    pub fn update<'a, X, S>(&mut self, handle: TextHandle, section: S) -> bool
+    where
+        X: Clone + Into<TextExtra> + 'a,
+        S: Into<LayeredSection<'a, X>>,
+    {
+        let (layer, entry) = Entry::new(&section.into());
+        self.retained.update(handle, layer, entry)
+    }
+
+    /// Stops drawing the retained section of the `handle` from the next
+    /// [`queue()`](#method.queue) on. Returns `false` if it was removed already.
+    pub fn remove(&mut self, handle: TextHandle) -> bool {
+        self.retained.remove(handle)
+    }
+
+    /// Rasterizes the `chars` of the `fonts` at the `sizes` in pixels and uploads
+    /// them into the cache texture ahead of time, so the first frame showing
+    /// them doesn't stutter. Returns the cache texture size they need, which can
+    /// be given to [`BrushBuilder::initial_cache_size()`] to avoid resizing the
+    /// cache texture while drawing.
+    ///
+    /// With [`RenderMode::Coverage`] and [`RenderMode::Subpixel`], glyphs are
+    /// cached at the subpixel positions they get when the `chars` are laid out
+    /// in a single line. Text placing them at other subpixel positions, as told
+    /// apart by [`BrushBuilder::draw_cache_position_tolerance()`], still
+    /// rasterizes them when it is first drawn, unless pixel snapping is on.
+    /// Distance field glyphs are cached only once, regardless of the `sizes`.
+    ///
+    /// The cache texture grows until all glyphs fit, keeping the glyphs cached
+    /// so far. This brush keeps drawing its text, other brushes sharing the
+    /// atlas draw nothing until they are queued again after it grew.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use wgpu_text::{glyph_brush::FontId, TextBrush};
+    /// # let (device, queue): (wgpu::Device, wgpu::Queue) = todo!();
+    /// # let mut brush: TextBrush = todo!();
+    /// let ascii: String = (' '..='~').collect();
+    /// let report = brush
+    ///     .prewarm(&device, &queue, &[FontId(0)], &[16.0, 24.0], &ascii)
+    ///     .unwrap();
+    /// println!("Cache texture size needed: {:?}", report.cache_size);
+    /// ```
+    ///
+    /// # Panics
+    /// If any of the `fonts` isn't a font of the brush.
+    pub fn prewarm(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        fonts: &[FontId],
+        sizes: &[f32],
+        chars: &str,
+    ) -> Result<PrewarmReport, BrushError> {
+        let atlas = self.atlas.clone();
+        let mut state = atlas.lock();
+        state.upload_pending();
+        let cached = state.atlas.glyph_count();
+        // Growing keeps the texels of cached glyphs, vertices created since the
+        // last clear or resize stay valid.
+        let current = self.epoch == state.epoch();
+        let retained_current = self.retained.epoch == state.epoch();
+
+        for &font_id in fonts {
+            for &size in sizes {
+                let section = Section::default()
+                    .with_layout(Layout::default_single_line())
+                    .add_text(Text::new(chars).with_scale(size).with_font_id(font_id));
+                let glyphs = self.layouts.glyphs(&self.fonts, &section).to_vec();
+
+                for SectionGlyph { mut glyph, .. } in glyphs {
+                    self.snap(&mut glyph);
+                    let font = &self.fonts[font_id.0];
+                    loop {
+                        let AtlasState { atlas, cache, .. } = &mut *state;
+                        let result = atlas.glyph(
+                            font,
+                            self.font_ids[font_id.0],
+                            &glyph,
+                            |page, index, rect, data| {
+                                cache.update_texture(page, index, rect, data)
+                            },
+                        );
+                        match result {
+                            Ok(_) => break,
+                            Err(AtlasFull(page)) => state.grow(device, queue, page)?,
+                        }
+                    }
+                }
+            }
+        }
+        state.cache.flush_uploads(device, queue);
+        if current {
+            self.epoch = state.epoch();
+        }
+        if retained_current {
+            self.retained.epoch = state.epoch();
+        }
+        self.pipeline.update_textures(&state.cache);
+
+        Ok(PrewarmReport {
+            rasterized: state.atlas.glyph_count() - cached,
+            cache_size: state.atlas.dimensions(Page::Main),
+            cache_pages: state.atlas.page_count(Page::Main),
+            color_cache_size: state.atlas.dimensions(Page::Color),
+            color_cache_pages: state.atlas.page_count(Page::Color),
+        })
+    }
+
+    /// Returns counters of the work done by [`queue()`](#method.queue) in the last
+    /// frame and in total, together with the usage of the cache textures and the
+    /// size of the vertex buffer.
+    ///
+    /// Helps choosing [`BrushBuilder::initial_cache_size()`], a well sized cache
+    /// texture isn't grown or cleared after the first frames.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use wgpu_text::TextBrush;
+    /// # let brush: TextBrush = todo!();
+    /// let stats = brush.stats();
+    /// println!(
+    ///     "{} glyphs rasterized, cache texture {:?} x {} is {:.0}% full",
+    ///     stats.frame.glyphs_rasterized,
+    ///     stats.cache_size,
+    ///     stats.cache_pages,
+    ///     stats.cache_usage * 100.0,
+    /// );
+    /// ```
+    pub fn stats(&self) -> BrushStats {
+        let state = self.atlas.lock();
+        BrushStats {
+            frame: self.frame_stats,
+            total: self.total_stats,
+            frames: self.frames,
+            cached_glyphs: state.atlas.glyph_count(),
+            cache_size: state.atlas.dimensions(Page::Main),
+            cache_pages: state.atlas.page_count(Page::Main),
+            cache_usage: state.atlas.usage(Page::Main),
+            color_cache_size: state.atlas.dimensions(Page::Color),
+            color_cache_pages: state.atlas.page_count(Page::Color),
+            color_cache_usage: state.atlas.usage(Page::Color),
+            vertex_buffer_size: self.pipeline.vertex_buffer_size(),
+        }
+    }
+
     /// Returns a bounding box for the section glyphs calculated using each
     /// glyph's vertical & horizontal metrics. For more info, read about
-    /// [`GlyphCruncher::glyph_bounds`].
+    /// [`GlyphCruncher::glyph_bounds`](glyph_brush::GlyphCruncher::glyph_bounds).
     #[inline]
-    pub fn glyph_bounds<'a, S>(&mut self, section: S) -> Option<Rect>
+    pub fn glyph_bounds<'a, X, S>(&mut self, section: S) -> Option<Rect>
     where
-        S: Into<std::borrow::Cow<'a, Section<'a>>>,
+        X: Clone + 'a,
+        S: Into<Cow<'a, Section<'a, X>>>,
     {
-        self.inner.glyph_bounds(section)
+        self.layouts.glyph_bounds(&self.fonts, &section.into())
     }
 
     /// Returns an iterator over the `PositionedGlyph`s of the given section.
     #[inline]
-    pub fn glyphs_iter<'a, 'b, S>(&'b mut self, section: S) -> SectionGlyphIter<'b>
+    pub fn glyphs_iter<'a, 'b, X, S>(&'b mut self, section: S) -> SectionGlyphIter<'b>
     where
-        S: Into<std::borrow::Cow<'a, Section<'a>>>,
+        X: Clone + 'a,
+        S: Into<Cow<'a, Section<'a, X>>>,
     {
-        self.inner.glyphs(section)
+        self.layouts.glyphs(&self.fonts, &section.into()).iter()
     }
 
     /// Returns the available fonts.
     ///
     /// The `FontId` corresponds to the index of the font data.
     pub fn fonts(&self) -> &[F] {
-        self.inner.fonts()
+        &self.fonts
     }
 
-    /// Draws all sections queued with [`queue`](#method.queue) function.
+    /// Draws all sections queued with [`queue`](#method.queue) function and the
+    /// retained ones, layer after layer in ascending order, followed by the debug
+    /// overlay if enabled.
     #[inline]
     pub fn draw<'pass>(&'pass self, rpass: &mut wgpu::RenderPass<'pass>) {
-        self.pipeline.draw(rpass)
+        if self.atlas.epoch() != self.epoch {
+            return;
+        }
+        // Adjacent ranges are drawn together.
+        let mut instances = 0..0;
+        for (_, range) in &self.ranges {
+            if range.start == instances.end {
+                instances.end = range.end;
+            } else {
+                self.pipeline.draw_range(rpass, instances, &self.runs);
+                instances = range.clone();
+            }
+        }
+        self.pipeline.draw_range(rpass, instances, &self.runs);
+        self.pipeline.draw_debug(rpass);
+    }
+
+    /// Draws only the debug overlay, for brushes drawn with
+    /// [`draw_layer()`](#method.draw_layer). See
+    /// [`set_debug_overlay()`](#method.set_debug_overlay).
+    #[inline]
+    pub fn draw_debug_overlay<'pass>(&'pass self, rpass: &mut wgpu::RenderPass<'pass>) {
+        if self.atlas.epoch() == self.epoch {
+            self.pipeline.draw_debug(rpass);
+        }
+    }
+
+    /// Chooses the parts of the debug overlay drawn on top of the text, which
+    /// shows the pages of the cache textures and outlines the bounds, the laid
+    /// out glyphs and the glyph quads of the sections. Takes effect with the
+    /// next [`queue()`](#method.queue), turning it off takes effect at once.
+    ///
+    /// Outlines are placed like the text, including the transform of the
+    /// section. The pages are placed in the top right corner of the view size
+    /// given to [`BrushBuilder::build()`] or [`resize_view()`](#method.resize_view).
+    ///
+    /// The overlay costs nothing while it is off, its pipelines are created when
+    /// it is first enabled.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use wgpu_text::{DebugOverlay, TextBrush};
+    /// # let mut brush: TextBrush = todo!();
+    /// # let show_debug = true;
+    /// brush.set_debug_overlay(if show_debug {
+    ///     DebugOverlay::ALL
+    /// } else {
+    ///     DebugOverlay::default()
+    /// });
+    /// ```
+    pub fn set_debug_overlay(&mut self, overlay: DebugOverlay) {
+        if overlay != self.debug_overlay {
+            // Sections collect their outlines while being laid out.
+            self.retained.invalidate();
+            self.queued_hash = None;
+        }
+        self.debug_overlay = overlay;
+        if !overlay.is_enabled() {
+            self.debug_outlines = Vec::new();
+            self.pipeline.clear_debug();
+        }
+    }
+
+    /// Returns the parts of the debug overlay which are drawn.
+    #[inline]
+    pub fn debug_overlay(&self) -> DebugOverlay {
+        self.debug_overlay
+    }
+
+    /// Turns pixel snapping on or off, see [`BrushBuilder::with_pixel_snapping()`].
+    /// Quads are snapped at once, glyph positions are rounded from the next
+    /// [`queue()`](#method.queue) on.
+    ///
+    /// Keep it off for text which moves smoothly, e.g. while animating it, and
+    /// turn it back on once the text rests.
+    pub fn set_pixel_snapping(&mut self, pixel_snapping: bool, queue: &wgpu::Queue) {
+        if pixel_snapping != self.pixel_snapping {
+            self.retained.invalidate();
+            self.queued_hash = None;
+        }
+        self.pixel_snapping = pixel_snapping;
+        self.pipeline
+            .update_snap_viewport(self.snap_viewport(), queue);
+    }
+
+    /// Returns whether glyph quads are snapped to pixels.
+    #[inline]
+    pub fn pixel_snapping(&self) -> bool {
+        self.pixel_snapping
+    }
+
+    /// Sets the number of physical pixels per pixel of the view, see
+    /// [`BrushBuilder::with_scale_factor()`]. Call it whenever the window moves
+    /// to a monitor with another scale factor.
+    pub fn set_scale_factor(&mut self, scale_factor: f32, queue: &wgpu::Queue) {
+        self.scale_factor = scale_factor;
+        if self.pixel_snapping {
+            self.retained.invalidate();
+            self.queued_hash = None;
+            self.pipeline
+                .update_snap_viewport(self.snap_viewport(), queue);
+        }
+    }
+
+    /// Returns the number of physical pixels per pixel of the view.
+    #[inline]
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    /// Rounds the position of the `glyph` to physical pixels while snapping, so
+    /// it is rasterized without a subpixel offset.
+    fn snap(&self, glyph: &mut Glyph) {
+        if self.pixel_snapping {
+            let scale_factor = self.scale_factor;
+            glyph.position.x = (glyph.position.x * scale_factor).round() / scale_factor;
+            glyph.position.y = (glyph.position.y * scale_factor).round() / scale_factor;
+        }
+    }
+
+    /// Size of the render target in physical pixels while snapping, zero otherwise.
+    fn snap_viewport(&self) -> [f32; 2] {
+        if self.pixel_snapping {
+            [
+                self.view_size.0 * self.scale_factor,
+                self.view_size.1 * self.scale_factor,
+            ]
+        } else {
+            [0.0; 2]
+        }
+    }
+
+    /// Draws only the queued and retained sections of the `layer`, nothing if
+    /// there are none.
+    ///
+    /// All layers share the cache texture and the vertex buffer, so the other
+    /// layers can be drawn in other render passes of the same frame, e.g. text
+    /// below and above a panel drawn by you.
+    #[inline]
+    pub fn draw_layer<'pass>(
+        &'pass self,
+        layer: u32,
+        rpass: &mut wgpu::RenderPass<'pass>,
+    ) {
+        if self.atlas.epoch() != self.epoch {
+            return;
+        }
+        for (_, range) in self.ranges.iter().filter(|(l, _)| *l == layer) {
+            self.pipeline.draw_range(rpass, range.clone(), &self.runs);
+        }
+    }
+
+    /// Saves the glyphs cached so far and the contents of the cache textures, to
+    /// be restored at startup with [`BrushBuilder::with_cache_data()`].
+    /// See [`SharedAtlas::export()`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use wgpu_text::{BrushBuilder, TextBrush};
+    /// # let (device, queue): (wgpu::Device, wgpu::Queue) = todo!();
+    /// # let brush: TextBrush = todo!();
+    /// # let font: &[u8] = &[];
+    /// std::fs::write("glyphs.cache", brush.export_cache(&device, &queue).unwrap()).unwrap();
+    ///
+    /// // On the next start:
+    /// let mut builder = BrushBuilder::using_font_bytes(font).unwrap();
+    /// if let Ok(data) = std::fs::read("glyphs.cache") {
+    ///     builder = builder.with_cache_data(data);
+    /// }
+    /// ```
+    pub fn export_cache(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<u8>, BrushError> {
+        self.atlas.export(device, queue)
+    }
+
+    /// Draws all queued sections into a new offscreen texture of `width` and
+    /// `height` cleared with the `clear` color, and reads it back as an [`Image`].
+    ///
+    /// The texture has the format, sample count and depth format the brush was
+    /// built with, only 8-bit RGBA and BGRA formats can be read back. Blocks
+    /// until the GPU has finished, which makes it suited for tests rather than
+    /// for every frame. Works without a display, e.g. with a software adapter
+    /// requested with `force_fallback_adapter`.
+    ///
+    /// Brushes built with [`BrushBuilder::with_multiview()`] aren't supported.
+    pub fn render_to_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        clear: wgpu::Color,
+    ) -> Result<Image, BrushError> {
+        let target = Target::new(device, self.pipeline.target(), width, height)?;
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("wgpu-text Offscreen Encoder"),
+            });
+        {
+            let mut rpass = target.begin_render_pass(&mut encoder, clear);
+            self.draw(&mut rpass);
+        }
+        target.read(device, queue, encoder)
     }
 
     /// Resizes the view matrix. Updates the default orthographic view matrix with
@@ -156,7 +1002,12 @@ where
     /// ```
     #[inline]
     pub fn resize_view(&mut self, width: f32, height: f32, queue: &wgpu::Queue) {
+        self.view_size = (width, height);
         self.update_matrix(crate::ortho(width, height), queue);
+        if self.pixel_snapping {
+            self.pipeline
+                .update_snap_viewport(self.snap_viewport(), queue);
+        }
     }
 
     /// Resizes the view. Updates text rendering matrix with the provided one.
@@ -165,6 +1016,10 @@ where
     /// with a default orthographic matrix.
     ///
     /// Feel free to use [`ortho()`] to create more complex matrices by yourself.
+    ///
+    /// For text placed in world space with
+    /// [`LayeredSection::with_world_position()`],
+    /// provide the view-projection matrix of the camera.
     #[inline]
     pub fn update_matrix<M>(&mut self, matrix: M, queue: &wgpu::Queue)
     where
@@ -182,6 +1037,19 @@ pub struct BrushBuilder<F, H = DefaultSectionHasher> {
     multisample: wgpu::MultisampleState,
     multiview: Option<NonZeroU32>,
     matrix: Option<Matrix>,
+    render_mode: RenderMode,
+    color_glyphs: bool,
+    atlas: Option<SharedAtlas>,
+    cache_data: Option<Vec<u8>>,
+    memory_budget: Option<u64>,
+    on_atlas_event: Option<Arc<dyn Fn(AtlasEvent) + Send + Sync>>,
+    cache: CacheSettings,
+    pixel_snapping: bool,
+    scale_factor: f32,
+    /// Bitmap fonts by their index in the fonts.
+    bitmap_fonts: Vec<(usize, BitmapFont)>,
+    vertex_buffer: VertexBufferSettings,
+    frames_in_flight: usize,
 }
 
 impl BrushBuilder<()> {
@@ -191,7 +1059,9 @@ impl BrushBuilder<()> {
     }
 
     /// Creates a [`BrushBuilder`] with font byte data.
-    pub fn using_font_bytes(data: &[u8]) -> Result<BrushBuilder<FontRef>, InvalidFont> {
+    pub fn using_font_bytes(
+        data: &[u8],
+    ) -> Result<BrushBuilder<FontRef<'_>>, InvalidFont> {
         let font = FontRef::try_from_slice(data)?;
         Ok(BrushBuilder::using_fonts(vec![font]))
     }
@@ -199,7 +1069,7 @@ impl BrushBuilder<()> {
     /// Creates a [`BrushBuilder`] with multiple fonts byte data.
     pub fn using_font_bytes_vec(
         data: &[u8],
-    ) -> Result<BrushBuilder<FontRef>, InvalidFont> {
+    ) -> Result<BrushBuilder<FontRef<'_>>, InvalidFont> {
         let font = FontRef::try_from_slice(data)?;
         Ok(BrushBuilder::using_fonts(vec![font]))
     }
@@ -212,8 +1082,28 @@ impl BrushBuilder<()> {
             multisample: wgpu::MultisampleState::default(),
             multiview: None,
             matrix: None,
+            render_mode: RenderMode::default(),
+            color_glyphs: true,
+            atlas: None,
+            cache_data: None,
+            memory_budget: None,
+            on_atlas_event: None,
+            cache: CacheSettings::default(),
+            pixel_snapping: false,
+            scale_factor: 1.0,
+            bitmap_fonts: Vec::new(),
+            vertex_buffer: VertexBufferSettings::default(),
+            frames_in_flight: 1,
         }
     }
+
+    /// Creates a [`BrushBuilder`] with a [`BitmapFont`]. More fonts of any kind
+    /// can be added as [`FontArc`], see [`Self::add_bitmap_font()`].
+    pub fn using_bitmap_font(font: BitmapFont) -> BrushBuilder<FontArc> {
+        let mut builder = BrushBuilder::using_font(FontArc::from(font.clone()));
+        builder.bitmap_fonts.push((0, font));
+        builder
+    }
 }
 
 impl<F, H> BrushBuilder<F, H>
@@ -221,8 +1111,77 @@ where
     F: Font,
     H: std::hash::BuildHasher,
 {
-    // Default `BrushBuilder` functions:
-    glyph_brush::delegate_glyph_brush_builder_fns!(inner);
+    // Default `BrushBuilder` functions, see
+    // `glyph_brush::delegate_glyph_brush_builder_fns`. Glyphs are drawn into our
+    // own cache texture, so the draw cache ones only size it.
+
+    /// Adds additional fonts to the one added in [`using_font`](#method.using_font).
+    /// Returns a [`FontId`] to reference this font.
+    pub fn add_font(&mut self, font_data: F) -> FontId {
+        self.inner.add_font(font_data)
+    }
+
+    /// Initial size of 2D texture used as a gpu cache, pixels (width, height).
+    /// The GPU cache will dynamically quadruple in size whenever the current size
+    /// is insufficient.
+    ///
+    /// Defaults to `(256, 256)`
+    pub fn initial_cache_size(mut self, size: (u32, u32)) -> Self {
+        self.inner = self.inner.initial_cache_size(size);
+        self
+    }
+
+    /// Sets the maximum allowed difference in scale used for judging whether to reuse an
+    /// existing glyph in the GPU cache.
+    ///
+    /// Defaults to `0.5`
+    pub fn draw_cache_scale_tolerance(mut self, tolerance: f32) -> Self {
+        self.inner = self.inner.draw_cache_scale_tolerance(tolerance);
+        self
+    }
+
+    /// Sets the maximum allowed difference in subpixel position used for judging whether
+    /// to reuse an existing glyph in the GPU cache. Anything greater than or equal to
+    /// 1.0 means "don't care".
+    ///
+    /// Defaults to `0.1`
+    pub fn draw_cache_position_tolerance(mut self, tolerance: f32) -> Self {
+        self.inner = self.inner.draw_cache_position_tolerance(tolerance);
+        self
+    }
+
+    /// Had glyphs aligned to 4x4 texel boundaries in the glyph_brush draw cache,
+    /// which isn't drawn into anymore. Has no effect, glyphs are spaced by
+    /// [`with_glyph_padding`](#method.with_glyph_padding).
+    #[deprecated(note = "has no effect, glyphs are drawn from the brush's own cache")]
+    pub fn draw_cache_align_4x4(self, _align: bool) -> Self {
+        self
+    }
+
+    /// Sets whether perform the calculation of glyph positioning according to the layout
+    /// every time, or use a cached result if the input `Section` and `GlyphPositioner` are the
+    /// same hash as a previous call.
+    ///
+    /// Improves performance. Should only disable if using a custom GlyphPositioner that is
+    /// impure according to it's inputs, so caching a previous call is not desired. Disabling
+    /// also disables [`cache_redraws`](#method.cache_redraws).
+    ///
+    /// Defaults to `true`
+    pub fn cache_glyph_positioning(mut self, cache: bool) -> Self {
+        self.inner = self.inner.cache_glyph_positioning(cache);
+        self
+    }
+
+    /// Sets optimising drawing by reusing the last draw requesting an identical draw queue.
+    ///
+    /// Improves performance. Is disabled if
+    /// [`cache_glyph_positioning`](#method.cache_glyph_positioning) is disabled.
+    ///
+    /// Defaults to `true`
+    pub fn cache_redraws(mut self, cache: bool) -> Self {
+        self.inner = self.inner.cache_redraws(cache);
+        self
+    }
 
     /// Uses the provided `matrix` when rendering.
     ///
@@ -251,6 +1210,209 @@ where
         self
     }
 
+    /// Provide the [`RenderMode`] which decides how glyphs are rasterized into
+    /// the cache texture and drawn.
+    ///
+    /// Defaults to [`RenderMode::Coverage`]. Use [`RenderMode::Sdf`] for text
+    /// which gets scaled by the render matrix.
+    /// [`RenderMode::Subpixel`] sharpens small text on LCD monitors.
+    pub fn with_render_mode(mut self, render_mode: RenderMode) -> Self {
+        self.render_mode = render_mode;
+        self
+    }
+
+    /// Enables drawing color glyphs, like emoji, in their own colors.
+    ///
+    /// Embedded `CBDT`/`sbix` bitmaps and `COLR`/`CPAL` layers are rasterized into
+    /// a separate RGBA cache texture and drawn untinted, except for the alpha of
+    /// the text color. Disabling this draws them like any other glyph.
+    ///
+    /// `COLR` glyphs are read through [`Font::font_data()`], custom [`Font`]
+    /// implementations which don't provide it should disable color glyphs.
+    /// Their layers which use the text color are painted white, as the cached
+    /// image is shared by all sections drawing the glyph.
+    ///
+    /// Defaults to `true`.
+    pub fn with_color_glyphs(mut self, color_glyphs: bool) -> Self {
+        self.color_glyphs = color_glyphs;
+        self
+    }
+
+    /// Draws from the shared `atlas`, which caches the glyphs of every brush built
+    /// with it. See [`SharedAtlas`].
+    ///
+    /// The render mode, color glyphs and cache settings of the atlas are used
+    /// instead of the ones of this builder.
+    pub fn with_atlas(mut self, atlas: SharedAtlas) -> Self {
+        self.atlas = Some(atlas);
+        self
+    }
+
+    /// Restores glyphs saved with [`TextBrush::export_cache()`], instead of
+    /// rasterizing them again, see [`AtlasBuilder::with_cache_data()`].
+    ///
+    /// Ignored when a shared atlas is provided with [`Self::with_atlas()`].
+    pub fn with_cache_data(mut self, data: Vec<u8>) -> Self {
+        self.cache_data = Some(data);
+        self
+    }
+
+    /// Largest number of bytes the cache textures may take, see
+    /// [`AtlasBuilder::cache_memory_budget()`].
+    ///
+    /// Ignored when a shared atlas is provided with [`Self::with_atlas()`].
+    pub fn cache_memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Filters of the cache texture sampler, see
+    /// [`AtlasBuilder::with_cache_filter()`].
+    ///
+    /// Ignored when a shared atlas is provided with [`Self::with_atlas()`].
+    pub fn with_cache_filter(
+        mut self,
+        mag_filter: wgpu::FilterMode,
+        min_filter: wgpu::FilterMode,
+    ) -> Self {
+        self.cache.mag_filter = mag_filter;
+        self.cache.min_filter = min_filter;
+        self
+    }
+
+    /// Mip levels of the cache textures, see
+    /// [`AtlasBuilder::with_cache_mipmaps()`].
+    ///
+    /// Ignored when a shared atlas is provided with [`Self::with_atlas()`].
+    pub fn with_cache_mipmaps(
+        mut self,
+        levels: u32,
+        mipmap_filter: wgpu::FilterMode,
+    ) -> Self {
+        self.cache.mip_levels = levels.max(1);
+        self.cache.mipmap_filter = mipmap_filter;
+        self
+    }
+
+    /// Largest anisotropy of the cache texture sampler, see
+    /// [`AtlasBuilder::with_cache_anisotropy()`].
+    ///
+    /// Ignored when a shared atlas is provided with [`Self::with_atlas()`].
+    pub fn with_cache_anisotropy(mut self, anisotropy: u16) -> Self {
+        self.cache.anisotropy = anisotropy;
+        self
+    }
+
+    /// Empty pixels between glyphs in the cache texture, see
+    /// [`AtlasBuilder::with_glyph_padding()`].
+    ///
+    /// Ignored when a shared atlas is provided with [`Self::with_atlas()`].
+    pub fn with_glyph_padding(mut self, padding: u32) -> Self {
+        self.cache.padding = padding;
+        self
+    }
+
+    /// Snaps glyph quads to the physical pixel grid of the render target, which
+    /// keeps small UI text sharp when sections sit at fractional positions or
+    /// the render matrix doesn't map pixels of the view onto whole pixels.
+    ///
+    /// Glyphs are rasterized without a subpixel offset and every quad is moved
+    /// onto the closest pixel edge after the transform and the render matrix
+    /// are applied. The render target is assumed to have the view size given to
+    /// [`Self::build()`] or [`TextBrush::resize_view()`] times the
+    /// [`scale factor`](Self::with_scale_factor).
+    ///
+    /// Snapping makes moving text jump from pixel to pixel, so it is off by
+    /// default. It can be toggled with [`TextBrush::set_pixel_snapping()`].
+    pub fn with_pixel_snapping(mut self, pixel_snapping: bool) -> Self {
+        self.pixel_snapping = pixel_snapping;
+        self
+    }
+
+    /// Number of physical pixels per pixel of the view, e.g. the scale factor of
+    /// the window for views measured in logical pixels. Only used by
+    /// [`Self::with_pixel_snapping()`].
+    ///
+    /// Defaults to `1.0`.
+    pub fn with_scale_factor(mut self, scale_factor: f32) -> Self {
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    /// Number of glyphs the vertex buffer has room for from the start. Text-heavy
+    /// views can avoid recreating it in the first frames with a capacity fitting
+    /// their usual amount of text, see [`TextBrush::stats()`].
+    ///
+    /// The vertex buffer doubles its capacity whenever it is too small. Defaults
+    /// to `0`.
+    pub fn with_vertex_capacity(mut self, glyphs: usize) -> Self {
+        self.vertex_buffer.initial_capacity = glyphs;
+        self
+    }
+
+    /// Shrinks the vertex buffer after it needed no more than a quarter of its
+    /// capacity for `frames` frames in a row, e.g. after a one-off spike of text.
+    /// It shrinks to twice the glyphs of the last frame, but not below the
+    /// [`initial capacity`](Self::with_vertex_capacity).
+    ///
+    /// By default the vertex buffer never shrinks.
+    pub fn with_vertex_buffer_shrink(mut self, frames: u32) -> Self {
+        self.vertex_buffer.shrink_after = Some(frames.max(1));
+        self
+    }
+
+    /// Number of frames the GPU may still be drawing while the next one is
+    /// queued. The vertex buffer, the matrix uniform and the texture of section
+    /// transforms and effects are kept that many times and every frame writes
+    /// into the next copy, so uploads never have to wait for the GPU to finish
+    /// drawing the last frames.
+    ///
+    /// The vertices of the last frame are copied into the next vertex buffer on
+    /// the GPU, only the changed ones are uploaded. The matrix moves on to the
+    /// next copy with its first update after each [`TextBrush::queue()`], so
+    /// update it before queuing. Each frame in flight costs another vertex
+    /// buffer and parameters texture.
+    ///
+    /// Defaults to `1`.
+    pub fn with_frames_in_flight(mut self, frames: u32) -> Self {
+        self.frames_in_flight = frames.max(1) as usize;
+        self
+    }
+
+    /// Adds a [`BitmapFont`], to be used by sections through the returned
+    /// [`FontId`]. Works with brushes of [`FontArc`], which also hold regular
+    /// fonts.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use wgpu_text::{BitmapFont, BrushBuilder, glyph_brush::ab_glyph::FontArc};
+    /// # let ttf: FontArc = todo!();
+    /// let (builder, retro) = BrushBuilder::using_font(ttf)
+    ///     .add_bitmap_font(BitmapFont::open("assets/retro.fnt")?);
+    /// # Ok::<(), wgpu_text::BitmapFontError>(())
+    /// ```
+    pub fn add_bitmap_font(mut self, font: BitmapFont) -> (Self, FontId)
+    where
+        F: From<BitmapFont>,
+    {
+        let id = self.inner.add_font(font.clone());
+        self.bitmap_fonts.push((id.0, font));
+        (self, id)
+    }
+
+    /// Calls the `handler` whenever the cache texture grows, gets another page or is
+    /// cleared, see
+    /// [`AtlasBuilder::on_event()`].
+    ///
+    /// Ignored when a shared atlas is provided with [`Self::with_atlas()`].
+    pub fn on_atlas_event(
+        mut self,
+        handler: impl Fn(AtlasEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_atlas_event = Some(Arc::new(handler));
+        self
+    }
+
     /// Provide the *depth_stencil* if you are planning to utilize depth testing.
     ///
     /// For each section, depth can be set by modifying the z coordinate
@@ -265,7 +1427,13 @@ where
         self.depth_stencil = depth_stencil;
         self
     }
+}
 
+impl<F, H> BrushBuilder<F, H>
+where
+    F: Font + Sync,
+    H: std::hash::BuildHasher,
+{
     /// Builds a [`TextBrush`] while consuming [`BrushBuilder`], for later drawing text
     /// onto a texture of the specified `render_width`, `render_height` and [`wgpu::TextureFormat`].
     ///
@@ -278,22 +1446,148 @@ where
         render_height: u32,
         render_format: wgpu::TextureFormat,
     ) -> TextBrush<F, H> {
-        let inner = self.inner.build();
+        // Glyphs are drawn into our own cache texture, the settings of the
+        // glyph_brush draw cache only size it.
+        let inner = self.inner;
+        let draw_cache = inner.draw_cache_builder.build();
+        let fonts = inner.font_data;
+
+        let atlas = self.atlas.unwrap_or_else(|| {
+            let builder = AtlasBuilder::new()
+                .with_render_mode(self.render_mode)
+                .with_color_glyphs(self.color_glyphs)
+                .initial_cache_size(draw_cache.dimensions())
+                .draw_cache_scale_tolerance(draw_cache.scale_tolerance())
+                .draw_cache_position_tolerance(draw_cache.position_tolerance())
+                .with_cache_settings(self.cache);
+            let builder = match self.cache_data {
+                Some(data) => builder.with_cache_data(data),
+                None => builder,
+            };
+            let builder = match self.memory_budget {
+                Some(bytes) => builder.cache_memory_budget(bytes),
+                None => builder,
+            };
+            let builder = match self.on_atlas_event {
+                Some(handler) => builder.on_event(move |event| handler(event)),
+                None => builder,
+            };
+            builder.build(device, render_format)
+        });
 
         let matrix = self
             .matrix
             .unwrap_or_else(|| crate::ortho(render_width as f32, render_height as f32));
 
+        let mut state = atlas.lock();
+        let font_ids: Vec<usize> = fonts
+            .iter()
+            .map(|font| state.font_id(font.font_data()))
+            .collect();
+        for (index, font) in self.bitmap_fonts {
+            state.atlas.add_bitmap_font(font_ids[index], font);
+        }
         let pipeline = Pipeline::new(
             device,
             render_format,
             self.depth_stencil,
             self.multisample,
             self.multiview,
-            inner.texture_dimensions(),
+            state.atlas.render_mode(),
+            &state.cache,
             matrix,
+            if self.pixel_snapping {
+                [
+                    render_width as f32 * self.scale_factor,
+                    render_height as f32 * self.scale_factor,
+                ]
+            } else {
+                [0.0; 2]
+            },
+            self.vertex_buffer,
+            self.frames_in_flight,
         );
+        let epoch = state.epoch();
+        drop(state);
+        let split_runs = pipeline.blends_in_two_passes();
+
+        TextBrush {
+            fonts,
+            layouts: LayoutCache::new(
+                inner.section_hasher,
+                inner.cache_glyph_positioning,
+            ),
+            pipeline,
+            atlas,
+            font_ids,
+            epoch,
+            cache_redraws: inner.cache_redraws,
+            queued_hash: None,
+            queued: Vec::new(),
+            queued_params: Params::default(),
+            queued_offset: 0,
+            params_offset: 0,
+            layers: Vec::new(),
+            retained: Retained::new(split_runs),
+            ranges: Vec::new(),
+            runs: Vec::new(),
+            queued_runs: Vec::new(),
+            frame_stats: FrameStats::default(),
+            total_stats: FrameStats::default(),
+            frames: 0,
+            debug_overlay: DebugOverlay::default(),
+            debug_outlines: Vec::new(),
+            view_size: (render_width as f32, render_height as f32),
+            pixel_snapping: self.pixel_snapping,
+            scale_factor: self.scale_factor,
+        }
+    }
+}
+
+/// Bounds which don't clip anything.
+const UNBOUNDED: Rect = Rect {
+    min: Point {
+        x: f32::NEG_INFINITY,
+        y: f32::NEG_INFINITY,
+    },
+    max: Point {
+        x: f32::INFINITY,
+        y: f32::INFINITY,
+    },
+};
 
-        TextBrush { inner, pipeline }
+/// Converts the effect sizes of `extra` into the units the shader for the
+/// `render_mode` expects and returns how many pixels the glyph quad has to grow
+/// on each side to make room for them.
+fn effect_units(render_mode: RenderMode, scale: f32, extra: &mut TextExtra) -> f32 {
+    let shadow = extra.shadow_color[3] > 0.0;
+    let offset = extra.shadow_offset[0]
+        .abs()
+        .max(extra.shadow_offset[1].abs());
+
+    match render_mode {
+        // Coverage is dilated and blurred in texels, which are pixels.
+        RenderMode::Coverage | RenderMode::Subpixel(_) => {
+            let mut grow: f32 = 0.0;
+            if extra.outline_width > 0.0 {
+                grow = extra.outline_width.ceil() + 1.0;
+            }
+            if shadow {
+                grow = grow.max((offset + extra.shadow_blur).ceil() + 1.0);
+            }
+            grow
+        }
+        // Distance field effects move the edge into the spread, which the quad
+        // already includes.
+        RenderMode::Sdf { size, spread } | RenderMode::Msdf { size, spread } => {
+            let to_distance = size / scale / (2.0 * spread);
+            extra.outline_width = (extra.outline_width * to_distance).min(0.5);
+            extra.shadow_blur = (extra.shadow_blur * to_distance).min(0.5);
+            if shadow {
+                offset.ceil()
+            } else {
+                0.0
+            }
+        }
     }
 }
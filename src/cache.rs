@@ -1,193 +1,683 @@
+use std::{borrow::Cow, ops::Range, sync::Arc};
+
 use glyph_brush::Rectangle;
-use wgpu::util::DeviceExt;
 
-use crate::Matrix;
+use crate::atlas::Page;
+
+/// Sampling, mipmap and packing settings of the cache textures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheSettings {
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    /// Number of mip levels including the full size one, `1` without mipmaps.
+    pub mip_levels: u32,
+    pub anisotropy: u16,
+    /// Empty pixels between neighbouring glyphs.
+    pub padding: u32,
+}
+
+impl CacheSettings {
+    /// Glyphs start at multiples of it, so every mip level of a glyph covers
+    /// whole texels.
+    #[inline]
+    pub fn alignment(&self) -> u32 {
+        1 << (self.mip_levels.clamp(1, 16) - 1)
+    }
+
+    /// Empty pixels between neighbouring glyphs, enough that the glyphs don't
+    /// bleed into each other in the smallest mip level.
+    #[inline]
+    pub fn gap(&self) -> u32 {
+        self.padding.max(self.alignment())
+    }
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            mip_levels: 1,
+            anisotropy: 1,
+            padding: 1,
+        }
+    }
+}
 
-/// Responsible for texture caching and the global matrix.
+/// Responsible for the cache textures, shared by every brush drawing from them.
+///
+/// Both are texture arrays with a layer for every page.
 #[derive(Debug)]
 pub struct Cache {
     pub bind_group_layout: wgpu::BindGroupLayout,
-    pub bind_group: wgpu::BindGroup,
+    /// Brushes keep a reference to it, so it outlives recreated textures until
+    /// they queue again.
+    pub bind_group: Arc<wgpu::BindGroup>,
 
-    matrix_buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
     texture: wgpu::Texture,
+    color_format: wgpu::TextureFormat,
+    color_texture: wgpu::Texture,
     sampler: wgpu::Sampler,
+
+    /// Regions written since the last [`Cache::flush_uploads()`].
+    uploads: Vec<Upload>,
+    /// Pixels of the `uploads`, one after the other.
+    pixels: Vec<u8>,
+    /// Pixels of the copies of a flush, every row padded to
+    /// [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`].
+    staging: Vec<u8>,
+    /// Buffer the copies are made from, kept between flushes and recreated
+    /// only when it is too small.
+    staging_buffer: Option<wgpu::Buffer>,
+}
+
+/// Region of a cache texture waiting in the staged pixels.
+#[derive(Debug)]
+struct Upload {
+    page: Page,
+    mip_level: u32,
+    origin: wgpu::Origin3d,
+    width: u32,
+    height: u32,
+    /// Start of the pixels in the staged ones.
+    offset: usize,
+}
+
+/// Neighbouring uploads copied into a cache texture together.
+#[derive(Debug)]
+struct Batch {
+    page: Page,
+    mip_level: u32,
+    origin: wgpu::Origin3d,
+    width: u32,
+    height: u32,
+    uploads: Range<usize>,
 }
 
 impl Cache {
     pub fn new(
         device: &wgpu::Device,
-        tex_dimensions: (u32, u32),
-        matrix: Matrix,
+        format: wgpu::TextureFormat,
+        tex_size: wgpu::Extent3d,
+        color_format: wgpu::TextureFormat,
+        color_tex_size: wgpu::Extent3d,
+        settings: &CacheSettings,
     ) -> Self {
-        let texture = Self::create_cache_texture(device, tex_dimensions);
+        let mip_level_count = |size: wgpu::Extent3d| {
+            settings
+                .mip_levels
+                .clamp(1, size.max_mips(wgpu::TextureDimension::D2))
+        };
+        let texture = Self::create_cache_texture(
+            device,
+            format,
+            tex_size,
+            mip_level_count(tex_size),
+        );
+        let color_texture = Self::create_cache_texture(
+            device,
+            color_format,
+            color_tex_size,
+            mip_level_count(color_tex_size),
+        );
+
+        let linear = [
+            settings.mag_filter,
+            settings.min_filter,
+            settings.mipmap_filter,
+        ]
+        .iter()
+        .all(|filter| *filter == wgpu::FilterMode::Linear);
+        let anisotropy = if settings.anisotropy > 1 && !linear {
+            log::warn!(
+                "Ignoring the anisotropy of the cache texture sampler, it needs \
+                linear filtering."
+            );
+            1
+        } else {
+            settings.anisotropy.max(1)
+        };
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some("wgpu-text Cache Texture Sampler"),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: settings.mag_filter,
+            min_filter: settings.min_filter,
+            mipmap_filter: settings.mipmap_filter,
+            anisotropy_clamp: anisotropy,
             ..Default::default()
         });
 
-        let matrix_buffer =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("wgpu-text Matrix Buffer"),
-                contents: bytemuck::cast_slice(&matrix),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
-
         let bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("wgpu-text Matrix, Texture and Sampler Bind Group Layout"),
+                label: Some("wgpu-text Textures and Sampler Bind Group Layout"),
                 entries: &[
                     wgpu::BindGroupLayoutEntry {
                         binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        // The vertex shader normalizes texture coordinates by
+                        // the texture sizes.
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                         ty: wgpu::BindingType::Texture {
                             sample_type: wgpu::TextureSampleType::Float {
                                 filterable: true,
                             },
-                            view_dimension: wgpu::TextureViewDimension::D2,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
                             multisampled: false,
                         },
                         count: None,
                     },
                     wgpu::BindGroupLayoutEntry {
-                        binding: 2,
+                        binding: 1,
                         visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Sampler(
                             wgpu::SamplerBindingType::Filtering,
                         ),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float {
+                                filterable: true,
+                            },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("wgpu-text Bind Group"),
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: matrix_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(
-                        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &texture,
+            &color_texture,
+            &sampler,
+        );
 
         Self {
-            matrix_buffer,
+            format,
             texture,
+            color_format,
+            color_texture,
             sampler,
             bind_group,
             bind_group_layout,
+            uploads: Vec::new(),
+            pixels: Vec::new(),
+            staging: Vec::new(),
+            staging_buffer: None,
         }
     }
 
-    pub fn recreate_texture(
+    /// Recreates the cache texture of the `page` kind with a bigger `tex_size`,
+    /// or more pages, and copies the old pages into the top left corner of the
+    /// first ones, every mip level on its own.
+    pub fn grow_texture(
         &mut self,
         device: &wgpu::Device,
-        tex_dimensions: (u32, u32),
+        queue: &wgpu::Queue,
+        page: Page,
+        tex_size: wgpu::Extent3d,
     ) {
-        self.texture = Self::create_cache_texture(device, tex_dimensions);
-        self.bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("wgpu-text Bind Group"),
-            layout: &self.bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: self.matrix_buffer.as_entire_binding(),
+        let format = match page {
+            Page::Main => self.format,
+            Page::Color => self.color_format,
+        };
+        let old = self.texture(page);
+        let texture =
+            Self::create_cache_texture(device, format, tex_size, old.mip_level_count());
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("wgpu-text Cache Grow Encoder"),
+            });
+        for mip_level in 0..old.mip_level_count() {
+            encoder.copy_texture_to_texture(
+                wgpu::ImageCopyTexture {
+                    mip_level,
+                    ..old.as_image_copy()
                 },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(
-                        &self
-                            .texture
-                            .create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
+                wgpu::ImageCopyTexture {
+                    mip_level,
+                    ..texture.as_image_copy()
                 },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&self.sampler),
-                },
-            ],
-        });
+                old.size()
+                    .mip_level_size(mip_level, wgpu::TextureDimension::D2),
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+
+        match page {
+            Page::Main => self.texture = texture,
+            Page::Color => self.color_texture = texture,
+        }
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.texture,
+            &self.color_texture,
+            &self.sampler,
+        );
+    }
+
+    #[inline]
+    pub fn texture(&self, page: Page) -> &wgpu::Texture {
+        match page {
+            Page::Main => &self.texture,
+            Page::Color => &self.color_texture,
+        }
     }
 
-    pub fn update_matrix(&mut self, matrix: Matrix, queue: &wgpu::Queue) {
-        queue.write_buffer(&self.matrix_buffer, 0, bytemuck::cast_slice(&matrix));
+    /// Writes the pixels of the first `pages` pages of the `page` kind at once,
+    /// together with their mip levels.
+    pub fn write_pages(&mut self, page: Page, pages: u32, data: &[u8]) {
+        let size = self.texture(page).size();
+        let texel_bytes = self.texture(page).format().block_size(None).unwrap_or(1);
+        let page_bytes = (size.width * size.height * texel_bytes) as usize;
+
+        for index in 0..pages {
+            let start = index as usize * page_bytes;
+            self.write_mip_levels(
+                page,
+                wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: index,
+                },
+                (size.width, size.height),
+                Cow::Borrowed(&data[start..start + page_bytes]),
+            );
+        }
     }
 
+    /// Writes the pixels of the `size` region of the page at the `index`, which
+    /// has to be aligned to [`CacheSettings::alignment()`] for mipmapped textures.
     pub fn update_texture(
         &mut self,
+        page: Page,
+        index: u32,
         size: Rectangle<u32>,
         data: &[u8],
-        queue: &wgpu::Queue,
     ) {
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &self.texture,
-                mip_level: 0,
+        self.write_mip_levels(
+            page,
+            wgpu::Origin3d {
+                x: size.min[0],
+                y: size.min[1],
+                z: index,
+            },
+            (size.width(), size.height()),
+            Cow::Borrowed(data),
+        );
+    }
+
+    /// Writes the `data` of a `width` x `height` region at the `origin` of the
+    /// first mip level, followed by its halved copies into the other ones.
+    ///
+    /// The pixels are only staged, they reach the texture with the next
+    /// [`Self::flush_uploads()`].
+    fn write_mip_levels(
+        &mut self,
+        page: Page,
+        origin: wgpu::Origin3d,
+        (mut width, mut height): (u32, u32),
+        mut data: Cow<[u8]>,
+    ) {
+        let texture = self.texture(page);
+        let texel_bytes = texture.format().block_size(None).unwrap_or(1);
+        for mip_level in 0..texture.mip_level_count() {
+            if mip_level > 0 {
+                data = Cow::Owned(downsample(&data, width, height, texel_bytes));
+                width = (width / 2).max(1);
+                height = (height / 2).max(1);
+            }
+            let offset = self.pixels.len();
+            let len = (width * height * texel_bytes) as usize;
+            self.pixels.extend_from_slice(&data[..len]);
+            self.uploads.push(Upload {
+                page,
+                mip_level,
                 origin: wgpu::Origin3d {
-                    x: size.min[0],
-                    y: size.min[1],
-                    z: 0,
+                    x: origin.x >> mip_level,
+                    y: origin.y >> mip_level,
+                    z: origin.z,
                 },
-                aspect: wgpu::TextureAspect::All,
-            },
-            data,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(size.width()),
-                rows_per_image: Some(size.height()),
-            },
-            wgpu::Extent3d {
-                width: size.width(),
-                height: size.height(),
-                depth_or_array_layers: 1,
-            },
-        )
+                width,
+                height,
+                offset,
+            });
+        }
+    }
+
+    /// Copies all regions written since the last call into the cache textures,
+    /// through a single staging buffer and submission. Returns the number of
+    /// copies.
+    ///
+    /// Regions next to each other on a shelf of a page are copied together,
+    /// together with the empty pixels between them. The packer never places a
+    /// glyph left of the ones already on its shelf until the cache is cleared,
+    /// which discards the uploads, so these pixels are never in use.
+    pub fn flush_uploads(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> u32 {
+        if self.uploads.is_empty() {
+            return 0;
+        }
+        let copies = batch(&mut self.uploads);
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("wgpu-text Cache Upload Encoder"),
+            });
+        let mut layouts = Vec::with_capacity(copies.len());
+        for copy in &copies {
+            let texel_bytes = self
+                .texture(copy.page)
+                .format()
+                .block_size(None)
+                .unwrap_or(1);
+            let bytes_per_row = (copy.width * texel_bytes)
+                .next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+            let offset = self.staging.len();
+            self.staging
+                .resize(offset + (bytes_per_row * copy.height) as usize, 0);
+            for upload in &self.uploads[copy.uploads.clone()] {
+                let row_bytes = (upload.width * texel_bytes) as usize;
+                let pixels = &self.pixels[upload.offset..];
+                let x = ((upload.origin.x - copy.origin.x) * texel_bytes) as usize;
+                for (y, row) in pixels
+                    .chunks_exact(row_bytes)
+                    .take(upload.height as usize)
+                    .enumerate()
+                {
+                    let start = offset + y * bytes_per_row as usize + x;
+                    self.staging[start..start + row_bytes].copy_from_slice(row);
+                }
+            }
+            layouts.push(wgpu::ImageDataLayout {
+                offset: offset as u64,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(copy.height),
+            });
+        }
+
+        let size = self.staging.len() as u64;
+        let buffer = match self.staging_buffer.take() {
+            Some(buffer) if buffer.size() >= size => buffer,
+            _ => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("wgpu-text Cache Staging Buffer"),
+                size: size.next_power_of_two(),
+                usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        };
+        // Written before the copies of the submission below are executed.
+        queue.write_buffer(&buffer, 0, &self.staging);
+        for (copy, layout) in copies.iter().zip(layouts) {
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout,
+                },
+                wgpu::ImageCopyTexture {
+                    texture: self.texture(copy.page),
+                    mip_level: copy.mip_level,
+                    origin: copy.origin,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::Extent3d {
+                    width: copy.width,
+                    height: copy.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+        self.staging_buffer = Some(buffer);
+        self.discard_uploads();
+        copies.len() as u32
+    }
+
+    /// Forgets the regions written since the last [`Self::flush_uploads()`].
+    #[inline]
+    pub fn discard_uploads(&mut self) {
+        self.uploads.clear();
+        self.pixels.clear();
+        self.staging.clear();
+    }
+
+    fn create_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        texture: &wgpu::Texture,
+        color_texture: &wgpu::Texture,
+        sampler: &wgpu::Sampler,
+    ) -> Arc<wgpu::BindGroup> {
+        Arc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("wgpu-text Textures Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&Self::create_view(
+                        texture,
+                    )),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&Self::create_view(
+                        color_texture,
+                    )),
+                },
+            ],
+        }))
+    }
+
+    /// Number of layers of a cache texture of the `size` with
+    /// `size.depth_or_array_layers` pages.
+    ///
+    /// The GL backend binds textures with a single layer as plain 2D textures and
+    /// square ones with a multiple of six layers as cube maps, which can't be
+    /// sampled as arrays, so they get a spare layer.
+    pub fn layer_count(size: wgpu::Extent3d) -> u32 {
+        let layers = size.depth_or_array_layers.max(2);
+        if size.width == size.height && layers.is_multiple_of(6) {
+            layers + 1
+        } else {
+            layers
+        }
     }
 
+    /// Creates a cache texture of the `size` with `size.depth_or_array_layers`
+    /// pages.
     fn create_cache_texture(
         device: &wgpu::Device,
-        dimensions: (u32, u32),
+        format: wgpu::TextureFormat,
+        size: wgpu::Extent3d,
+        mip_level_count: u32,
     ) -> wgpu::Texture {
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
-        };
         device.create_texture(&wgpu::TextureDescriptor {
             label: Some("wgpu-text Cache Texture"),
-            size,
-            mip_level_count: 1,
+            size: wgpu::Extent3d {
+                depth_or_array_layers: Self::layer_count(size),
+                ..size
+            },
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::R8Unorm,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         })
     }
 }
+
+/// Sorts the `uploads` and groups the ones next to each other on a shelf of a
+/// page into copies.
+fn batch(uploads: &mut [Upload]) -> Vec<Batch> {
+    // Stable, a region written twice keeps its order.
+    uploads.sort_by_key(|upload| {
+        let wgpu::Origin3d { x, y, z } = upload.origin;
+        (upload.page as u32, z, upload.mip_level, y, x)
+    });
+    let mut copies: Vec<Batch> = Vec::new();
+    for (index, upload) in uploads.iter().enumerate() {
+        match copies.last_mut() {
+            Some(copy)
+                if copy.page == upload.page
+                    && copy.mip_level == upload.mip_level
+                    && copy.origin.z == upload.origin.z
+                    && copy.origin.y == upload.origin.y
+                    && copy.origin.x + copy.width <= upload.origin.x =>
+            {
+                copy.width = upload.origin.x + upload.width - copy.origin.x;
+                copy.height = copy.height.max(upload.height);
+                copy.uploads.end = index + 1;
+            }
+            _ => copies.push(Batch {
+                page: upload.page,
+                mip_level: upload.mip_level,
+                origin: upload.origin,
+                width: upload.width,
+                height: upload.height,
+                uploads: index..index + 1,
+            }),
+        }
+    }
+    copies
+}
+
+/// Halves the `width` x `height` pixels with `texel_bytes` channels by
+/// averaging every 2x2 block, channels are averaged as they are stored. An odd
+/// last row or column is dropped.
+fn downsample(data: &[u8], width: u32, height: u32, texel_bytes: u32) -> Vec<u8> {
+    let (width, height, texel_bytes) =
+        (width as usize, height as usize, texel_bytes as usize);
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut half = Vec::with_capacity(half_width * half_height * texel_bytes);
+
+    for y in 0..half_height {
+        let rows = [(y * 2).min(height - 1), (y * 2 + 1).min(height - 1)];
+        for x in 0..half_width {
+            let columns = [(x * 2).min(width - 1), (x * 2 + 1).min(width - 1)];
+            for channel in 0..texel_bytes {
+                let sum: u32 = rows
+                    .iter()
+                    .flat_map(|row| columns.iter().map(move |column| (row, column)))
+                    .map(|(row, column)| {
+                        data[(row * width + column) * texel_bytes + channel] as u32
+                    })
+                    .sum();
+                half.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+    half
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downsample_averages_blocks() {
+        #[rustfmt::skip]
+        let data = [
+            0, 4, 10, 10,
+            8, 4, 20, 21,
+        ];
+        assert_eq!(downsample(&data, 4, 2, 1), [4, 15]);
+    }
+
+    #[test]
+    fn downsample_averages_channels_separately() {
+        let data = [[255, 0], [255, 0], [0, 255], [0, 254]].concat();
+        assert_eq!(downsample(&data, 2, 2, 2), [128, 127]);
+    }
+
+    #[test]
+    fn downsample_odd_and_thin_sizes() {
+        // The odd last column is dropped.
+        assert_eq!(downsample(&[0, 8, 100, 4, 12, 100], 3, 2, 1), [6]);
+        // A single row or column is averaged with itself.
+        assert_eq!(downsample(&[10, 20, 30, 41], 4, 1, 1), [15, 36]);
+        assert_eq!(downsample(&[10, 20], 1, 2, 1), [15]);
+        assert_eq!(downsample(&[7], 1, 1, 1), [7]);
+    }
+
+    fn upload(page: Page, [x, y, z]: [u32; 3], width: u32, height: u32) -> Upload {
+        Upload {
+            page,
+            mip_level: 0,
+            origin: wgpu::Origin3d { x, y, z },
+            width,
+            height,
+            offset: 0,
+        }
+    }
+
+    #[test]
+    fn neighbours_on_a_shelf_are_copied_together() {
+        let mut uploads = [
+            upload(Page::Main, [0, 0, 0], 8, 10),
+            upload(Page::Main, [0, 16, 0], 8, 8),
+            upload(Page::Main, [10, 0, 0], 6, 12),
+            upload(Page::Main, [0, 0, 1], 4, 4),
+            upload(Page::Color, [0, 0, 0], 4, 4),
+        ];
+        let copies = batch(&mut uploads);
+
+        let regions: Vec<_> = copies
+            .iter()
+            .map(|copy| {
+                let wgpu::Origin3d { x, y, z } = copy.origin;
+                (
+                    copy.page,
+                    [x, y, z],
+                    copy.width,
+                    copy.height,
+                    copy.uploads.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            regions,
+            [
+                (Page::Main, [0, 0, 0], 16, 12, 0..2),
+                (Page::Main, [0, 16, 0], 8, 8, 2..3),
+                (Page::Main, [0, 0, 1], 4, 4, 3..4),
+                (Page::Color, [0, 0, 0], 4, 4, 4..5),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_uploads_are_copied_in_order() {
+        // A restored page followed by a glyph on it.
+        let mut uploads = [
+            upload(Page::Main, [0, 0, 0], 64, 64),
+            upload(Page::Main, [0, 0, 0], 8, 8),
+        ];
+        let copies = batch(&mut uploads);
+        assert_eq!(copies.len(), 2);
+        assert_eq!((uploads[0].width, uploads[1].width), (64, 8));
+    }
+}
@@ -0,0 +1,836 @@
+//! Color glyphs, either embedded bitmaps (`CBDT`, `sbix`) or layered outlines
+//! (`COLR`/`CPAL`), rasterized into premultiplied RGBA images.
+
+use ab_glyph_rasterizer::Rasterizer;
+use glyph_brush::ab_glyph::{
+    point, Font, Glyph, GlyphImageFormat, Point, Rect, ScaleFont,
+};
+use ttf_parser::{
+    colr::{ClipBox, CompositeMode, GradientExtend, Paint, Painter},
+    RgbaColor, Transform,
+};
+
+/// Layers using the text color are painted with this color, as the image is
+/// cached independently of the section it's drawn in. They show up white
+/// instead of in the text color.
+const FOREGROUND: RgbaColor = RgbaColor {
+    red: 255,
+    green: 255,
+    blue: 255,
+    alpha: 255,
+};
+
+/// Rasterized color glyph.
+pub struct ColorImage {
+    /// Pixel bounds relative to the glyph origin.
+    pub bounds: Rect,
+    pub width: u32,
+    pub height: u32,
+    /// Premultiplied RGBA pixels, encoded as sRGB when `srgb` was requested.
+    pub data: Vec<u8>,
+}
+
+/// Returns the color image of the `glyph` if the font has one.
+///
+/// `COLR` glyphs are read through [`Font::font_data()`]. When `srgb` is set the
+/// pixels are stored for an sRGB texture, meaning they are premultiplied in
+/// linear space.
+pub fn rasterize<F: Font>(font: &F, glyph: &Glyph, srgb: bool) -> Option<ColorImage> {
+    bitmap(font, glyph, srgb).or_else(|| colr(font, glyph, srgb))
+}
+
+/// Decodes the embedded bitmap closest to the requested size.
+fn bitmap<F: Font>(font: &F, glyph: &Glyph, srgb: bool) -> Option<ColorImage> {
+    let units_per_em = font.units_per_em()?;
+    let pixels_per_em = glyph.scale.y * units_per_em / font.height_unscaled();
+    let image = font.glyph_raster_image2(glyph.id, pixels_per_em.ceil() as u16)?;
+
+    let (width, height, rgba) = match image.format {
+        GlyphImageFormat::Png => decode_png(image.data)?,
+        GlyphImageFormat::BitmapPremulBgra32 => {
+            let (width, height) = (image.width as u32, image.height as u32);
+            let rgba = image
+                .data
+                .chunks_exact(4)
+                .map(|p| unpremultiply([p[2], p[1], p[0], p[3]]))
+                .collect::<Vec<_>>();
+            (width, height, rgba)
+        }
+        // Grayscale strikes are handled like regular outlined glyphs.
+        _ => return None,
+    };
+    if width == 0 || height == 0 || rgba.len() < (width * height) as usize {
+        return None;
+    }
+
+    // The image origin is its bottom left corner, in pixels of the image strike.
+    let scale = pixels_per_em / image.pixels_per_em as f32;
+    let min = point(
+        glyph.position.x + image.origin.x * scale,
+        glyph.position.y - (image.origin.y + height as f32) * scale,
+    );
+    Some(ColorImage {
+        bounds: Rect {
+            min,
+            max: point(min.x + width as f32 * scale, min.y + height as f32 * scale),
+        },
+        width,
+        height,
+        data: rgba.into_iter().flat_map(|p| store(p, srgb)).collect(),
+    })
+}
+
+/// Returns straight RGBA pixels of the PNG.
+pub(crate) fn decode_png(data: &[u8]) -> Option<(u32, u32, Vec<[u8; 4]>)> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).ok()?;
+    let buf = &buf[..info.buffer_size()];
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf
+            .chunks_exact(4)
+            .map(|p| [p[0], p[1], p[2], p[3]])
+            .collect(),
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return None,
+    };
+    Some((info.width, info.height, rgba))
+}
+
+/// Paints the `COLR` layers of the glyph.
+fn colr<F: Font>(font: &F, glyph: &Glyph, srgb: bool) -> Option<ColorImage> {
+    let face = face(font)?;
+    let glyph_id = ttf_parser::GlyphId(glyph.id.0);
+    if !face.is_color_glyph(glyph_id) {
+        return None;
+    }
+
+    // Same transformation `OutlinedGlyph::draw()` uses.
+    let scale_factor = font.as_scaled(glyph.scale).scale_factor();
+    let to_pixels = Transform::new(
+        scale_factor.horizontal,
+        0.0,
+        0.0,
+        -scale_factor.vertical,
+        glyph.position.x,
+        glyph.position.y,
+    );
+
+    let mut extent = Extent {
+        face: &face,
+        transforms: vec![to_pixels],
+        bounds: None,
+    };
+    face.paint_color_glyph(glyph_id, 0, FOREGROUND, &mut extent)?;
+    let bounds = extent.bounds?;
+    let min = point(bounds.min.x.floor(), bounds.min.y.floor());
+    let (width, height) = (
+        (bounds.max.x.ceil() - min.x) as u32,
+        (bounds.max.y.ceil() - min.y) as u32,
+    );
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    let to_canvas =
+        Transform::combine(Transform::new_translate(-min.x, -min.y), to_pixels);
+    let mut canvas = Canvas::new(&face, width, height, to_canvas);
+    face.paint_color_glyph(glyph_id, 0, FOREGROUND, &mut canvas)?;
+
+    let data = canvas.layers.pop()?.1.into_iter().flat_map(|p| {
+        let straight = if p[3] > 0.0 {
+            [p[0] / p[3], p[1] / p[3], p[2] / p[3], p[3]]
+        } else {
+            [0.0; 4]
+        };
+        store(
+            straight.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+            srgb,
+        )
+    });
+    Some(ColorImage {
+        bounds: Rect {
+            min,
+            max: point(min.x + width as f32, min.y + height as f32),
+        },
+        width,
+        height,
+        data: data.collect(),
+    })
+}
+
+/// Parses the face of the `font`. [`Font`] doesn't expose the index of the face
+/// in a font collection, so the one with the same glyph count and metrics is
+/// picked.
+fn face<F: Font>(font: &F) -> Option<ttf_parser::Face<'_>> {
+    let data = font.font_data();
+    let Some(count) = ttf_parser::fonts_in_collection(data) else {
+        return ttf_parser::Face::parse(data, 0).ok();
+    };
+    (0..count)
+        .filter_map(|index| ttf_parser::Face::parse(data, index).ok())
+        .find(|face| {
+            face.number_of_glyphs() as usize == font.glyph_count()
+                && Some(face.units_per_em() as f32) == font.units_per_em()
+                && face.ascender() as f32 == font.ascent_unscaled()
+                && face.descender() as f32 == font.descent_unscaled()
+        })
+}
+
+/// Collects the pixel bounds of every outline the glyph paints.
+struct Extent<'f, 'a> {
+    face: &'f ttf_parser::Face<'a>,
+    transforms: Vec<Transform>,
+    bounds: Option<Rect>,
+}
+
+impl<'a> Painter<'a> for Extent<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: ttf_parser::GlyphId) {
+        let Some(rect) = self.face.glyph_bounding_box(glyph_id) else {
+            return;
+        };
+        let transform = *self.transforms.last().unwrap();
+        for (x, y) in [
+            (rect.x_min, rect.y_min),
+            (rect.x_max, rect.y_min),
+            (rect.x_min, rect.y_max),
+            (rect.x_max, rect.y_max),
+        ] {
+            let p = apply(transform, x as f32, y as f32);
+            let bounds = self.bounds.get_or_insert(Rect { min: p, max: p });
+            bounds.min = point(bounds.min.x.min(p.x), bounds.min.y.min(p.y));
+            bounds.max = point(bounds.max.x.max(p.x), bounds.max.y.max(p.y));
+        }
+    }
+
+    fn paint(&mut self, _: Paint<'a>) {}
+
+    fn push_clip(&mut self) {}
+
+    fn push_clip_box(&mut self, _: ClipBox) {}
+
+    fn pop_clip(&mut self) {}
+
+    fn push_layer(&mut self, _: CompositeMode) {}
+
+    fn pop_layer(&mut self) {}
+
+    fn push_transform(&mut self, transform: Transform) {
+        let current = *self.transforms.last().unwrap();
+        self.transforms.push(Transform::combine(current, transform));
+    }
+
+    fn pop_transform(&mut self) {
+        self.transforms.pop();
+    }
+}
+
+/// Software painter compositing the glyph layers in premultiplied RGBA.
+struct Canvas<'f, 'a> {
+    face: &'f ttf_parser::Face<'a>,
+    width: u32,
+    height: u32,
+    transforms: Vec<Transform>,
+    /// Coverage of the last outlined glyph, consumed by the next paint or clip.
+    outline: Option<Vec<f32>>,
+    clips: Vec<Vec<f32>>,
+    layers: Vec<(CompositeMode, Vec<[f32; 4]>)>,
+}
+
+impl<'f, 'a> Canvas<'f, 'a> {
+    fn new(
+        face: &'f ttf_parser::Face<'a>,
+        width: u32,
+        height: u32,
+        transform: Transform,
+    ) -> Self {
+        let len = (width * height) as usize;
+        Self {
+            face,
+            width,
+            height,
+            transforms: vec![transform],
+            outline: None,
+            clips: Vec::new(),
+            layers: vec![(CompositeMode::SourceOver, vec![[0.0; 4]; len])],
+        }
+    }
+
+    fn transform(&self) -> Transform {
+        *self.transforms.last().unwrap()
+    }
+
+    /// Intersects the `mask` with the current clip.
+    fn clip(&self, mut mask: Vec<f32>) -> Vec<f32> {
+        if let Some(clip) = self.clips.last() {
+            mask.iter_mut().zip(clip).for_each(|(m, c)| *m *= c);
+        }
+        mask
+    }
+
+    fn rasterize(&self, draw: impl FnOnce(&mut OutlineRasterizer)) -> Vec<f32> {
+        let mut rasterizer = OutlineRasterizer {
+            rasterizer: Rasterizer::new(self.width as usize, self.height as usize),
+            transform: self.transform(),
+            start: point(0.0, 0.0),
+            last: point(0.0, 0.0),
+        };
+        draw(&mut rasterizer);
+        let mut mask = vec![0.0; (self.width * self.height) as usize];
+        rasterizer
+            .rasterizer
+            .for_each_pixel(|i, c| mask[i] = c.clamp(0.0, 1.0));
+        mask
+    }
+}
+
+impl<'a> Painter<'a> for Canvas<'_, 'a> {
+    fn outline_glyph(&mut self, glyph_id: ttf_parser::GlyphId) {
+        let face = self.face;
+        let mask = self.rasterize(|rasterizer| {
+            face.outline_glyph(glyph_id, rasterizer);
+        });
+        self.outline = Some(mask);
+    }
+
+    fn paint(&mut self, paint: Paint<'a>) {
+        let mask = match self.outline.take() {
+            Some(outline) => self.clip(outline),
+            None => self.clip(vec![1.0; (self.width * self.height) as usize]),
+        };
+        let source = Source::new(paint, self.transform());
+        let width = self.width as usize;
+        let layer = &mut self.layers.last_mut().unwrap().1;
+
+        for (i, (pixel, &coverage)) in layer.iter_mut().zip(&mask).enumerate() {
+            if coverage <= 0.0 {
+                continue;
+            }
+            let (x, y) = ((i % width) as f32 + 0.5, (i / width) as f32 + 0.5);
+            let color = source.color(x, y).map(|c| c * coverage);
+            *pixel = composite(CompositeMode::SourceOver, color, *pixel);
+        }
+    }
+
+    fn push_clip(&mut self) {
+        let outline = self
+            .outline
+            .take()
+            .unwrap_or_else(|| vec![0.0; (self.width * self.height) as usize]);
+        let clip = self.clip(outline);
+        self.clips.push(clip);
+    }
+
+    fn push_clip_box(&mut self, clip_box: ClipBox) {
+        // Clip boxes usually reach past the canvas, so test pixels against them
+        // instead of rasterizing them.
+        let inverse = invert(self.transform());
+        let width = self.width as usize;
+        let mask = (0..(self.width * self.height) as usize)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32 + 0.5, (i / width) as f32 + 0.5);
+                let p = apply(inverse, x, y);
+                let inside = (clip_box.x_min..=clip_box.x_max).contains(&p.x)
+                    && (clip_box.y_min..=clip_box.y_max).contains(&p.y);
+                if inside {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let clip = self.clip(mask);
+        self.clips.push(clip);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn push_layer(&mut self, mode: CompositeMode) {
+        let len = (self.width * self.height) as usize;
+        self.layers.push((mode, vec![[0.0; 4]; len]));
+    }
+
+    fn pop_layer(&mut self) {
+        if self.layers.len() < 2 {
+            return;
+        }
+        let (mode, source) = self.layers.pop().unwrap();
+        let backdrop = &mut self.layers.last_mut().unwrap().1;
+        for (dst, src) in backdrop.iter_mut().zip(source) {
+            *dst = composite(mode, src, *dst);
+        }
+    }
+
+    fn push_transform(&mut self, transform: Transform) {
+        self.transforms
+            .push(Transform::combine(self.transform(), transform));
+    }
+
+    fn pop_transform(&mut self) {
+        self.transforms.pop();
+    }
+}
+
+/// Feeds glyph outlines in font units into the rasterizer.
+struct OutlineRasterizer {
+    rasterizer: Rasterizer,
+    transform: Transform,
+    start: Point,
+    last: Point,
+}
+
+impl ttf_parser::OutlineBuilder for OutlineRasterizer {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = apply(self.transform, x, y);
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = apply(self.transform, x, y);
+        self.rasterizer.draw_line(self.last, p);
+        self.last = p;
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p1, p) = (apply(self.transform, x1, y1), apply(self.transform, x, y));
+        self.rasterizer.draw_quad(self.last, p1, p);
+        self.last = p;
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p1, p2, p) = (
+            apply(self.transform, x1, y1),
+            apply(self.transform, x2, y2),
+            apply(self.transform, x, y),
+        );
+        self.rasterizer.draw_cubic(self.last, p1, p2, p);
+        self.last = p;
+    }
+
+    fn close(&mut self) {
+        if self.last != self.start {
+            self.rasterizer.draw_line(self.last, self.start);
+        }
+        self.last = self.start;
+    }
+}
+
+/// Paint evaluated per pixel.
+enum Source {
+    Solid([f32; 4]),
+    Gradient {
+        kind: GradientKind,
+        extend: GradientExtend,
+        stops: Vec<(f32, [f32; 4])>,
+        /// Maps canvas pixels back into the gradient space.
+        inverse: Transform,
+    },
+}
+
+enum GradientKind {
+    /// Start point and the vector along which colors change.
+    Linear {
+        start: (f32, f32),
+        direction: (f32, f32),
+    },
+    Radial {
+        c0: (f32, f32),
+        r0: f32,
+        c1: (f32, f32),
+        r1: f32,
+    },
+    Sweep {
+        center: (f32, f32),
+        start: f32,
+        end: f32,
+    },
+}
+
+impl Source {
+    fn new(paint: Paint, transform: Transform) -> Self {
+        let (kind, extend, stops) = match paint {
+            Paint::Solid(color) => return Source::Solid(premultiply(color)),
+            Paint::LinearGradient(gradient) => {
+                // Colors change along p0 -> p1, projected onto the normal of p0 -> p2.
+                let normal = (gradient.y0 - gradient.y2, gradient.x2 - gradient.x0);
+                let to_p1 = (gradient.x1 - gradient.x0, gradient.y1 - gradient.y0);
+                let length_sq = normal.0 * normal.0 + normal.1 * normal.1;
+                let direction = if length_sq > 0.0 {
+                    let t = (to_p1.0 * normal.0 + to_p1.1 * normal.1) / length_sq;
+                    (normal.0 * t, normal.1 * t)
+                } else {
+                    to_p1
+                };
+                let kind = GradientKind::Linear {
+                    start: (gradient.x0, gradient.y0),
+                    direction,
+                };
+                (
+                    kind,
+                    gradient.extend,
+                    gradient.stops(0, &[]).collect::<Vec<_>>(),
+                )
+            }
+            Paint::RadialGradient(gradient) => {
+                let kind = GradientKind::Radial {
+                    c0: (gradient.x0, gradient.y0),
+                    r0: gradient.r0,
+                    c1: (gradient.x1, gradient.y1),
+                    r1: gradient.r1,
+                };
+                (kind, gradient.extend, gradient.stops(0, &[]).collect())
+            }
+            Paint::SweepGradient(gradient) => {
+                // Stored angles are biased by -180 degrees.
+                let kind = GradientKind::Sweep {
+                    center: (gradient.center_x, gradient.center_y),
+                    start: gradient.start_angle + 1.0,
+                    end: gradient.end_angle + 1.0,
+                };
+                (kind, gradient.extend, gradient.stops(0, &[]).collect())
+            }
+        };
+
+        let mut stops: Vec<_> = stops
+            .into_iter()
+            .map(|stop| (stop.stop_offset, premultiply(stop.color)))
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if stops.is_empty() {
+            return Source::Solid([0.0; 4]);
+        }
+        Source::Gradient {
+            kind,
+            extend,
+            stops,
+            inverse: invert(transform),
+        }
+    }
+
+    fn color(&self, x: f32, y: f32) -> [f32; 4] {
+        let (kind, extend, stops, inverse) = match self {
+            Source::Solid(color) => return *color,
+            Source::Gradient {
+                kind,
+                extend,
+                stops,
+                inverse,
+            } => (kind, *extend, stops, *inverse),
+        };
+        let p = apply(inverse, x, y);
+
+        let t = match *kind {
+            GradientKind::Linear { start, direction } => {
+                let length_sq = direction.0 * direction.0 + direction.1 * direction.1;
+                if length_sq == 0.0 {
+                    return [0.0; 4];
+                }
+                ((p.x - start.0) * direction.0 + (p.y - start.1) * direction.1)
+                    / length_sq
+            }
+            GradientKind::Radial { c0, r0, c1, r1 } => {
+                // Largest t for which p lies on the circle interpolated between both.
+                let cd = (c1.0 - c0.0, c1.1 - c0.1);
+                let pd = (p.x - c0.0, p.y - c0.1);
+                let dr = r1 - r0;
+                let a = cd.0 * cd.0 + cd.1 * cd.1 - dr * dr;
+                let b = pd.0 * cd.0 + pd.1 * cd.1 + r0 * dr;
+                let c = pd.0 * pd.0 + pd.1 * pd.1 - r0 * r0;
+                let t = if a.abs() < 1e-6 {
+                    if b == 0.0 {
+                        return [0.0; 4];
+                    }
+                    c / (2.0 * b)
+                } else {
+                    let discriminant = b * b - a * c;
+                    if discriminant < 0.0 {
+                        return [0.0; 4];
+                    }
+                    let (t0, t1) =
+                        ((b + discriminant.sqrt()) / a, (b - discriminant.sqrt()) / a);
+                    match (r0 + t0.max(t1) * dr >= 0.0, r0 + t0.min(t1) * dr >= 0.0) {
+                        (true, _) => t0.max(t1),
+                        (false, true) => t0.min(t1),
+                        (false, false) => return [0.0; 4],
+                    }
+                };
+                if r0 + t * dr < 0.0 {
+                    return [0.0; 4];
+                }
+                t
+            }
+            GradientKind::Sweep { center, start, end } => {
+                // Angles are counter-clockwise, in units of 180 degrees.
+                let angle = (p.y - center.1).atan2(p.x - center.0);
+                let angle =
+                    angle.rem_euclid(std::f32::consts::TAU) / std::f32::consts::PI;
+                if end == start {
+                    return [0.0; 4];
+                }
+                (angle - start) / (end - start)
+            }
+        };
+
+        let t = match extend {
+            GradientExtend::Pad => t.clamp(0.0, 1.0),
+            GradientExtend::Repeat => t.rem_euclid(1.0),
+            GradientExtend::Reflect => 1.0 - (t.rem_euclid(2.0) - 1.0).abs(),
+        };
+        gradient_color(stops, t)
+    }
+}
+
+fn gradient_color(stops: &[(f32, [f32; 4])], t: f32) -> [f32; 4] {
+    let first = stops[0];
+    let last = stops[stops.len() - 1];
+    if t <= first.0 {
+        return first.1;
+    }
+    if t >= last.0 {
+        return last.1;
+    }
+    let i = stops
+        .iter()
+        .position(|stop| stop.0 > t)
+        .unwrap_or(stops.len() - 1);
+    let (a, b) = (stops[i - 1], stops[i]);
+    let f = if b.0 > a.0 {
+        (t - a.0) / (b.0 - a.0)
+    } else {
+        0.0
+    };
+    [0, 1, 2, 3].map(|c| a.1[c] + (b.1[c] - a.1[c]) * f)
+}
+
+/// Composites premultiplied `src` onto `dst`. Separable blend modes other than
+/// the Porter-Duff operators fall back to `SourceOver`.
+fn composite(mode: CompositeMode, src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+    let (sa, da) = (src[3], dst[3]);
+    let (fa, fb) = match mode {
+        CompositeMode::Clear => (0.0, 0.0),
+        CompositeMode::Source => (1.0, 0.0),
+        CompositeMode::Destination => (0.0, 1.0),
+        CompositeMode::DestinationOver => (1.0 - da, 1.0),
+        CompositeMode::SourceIn => (da, 0.0),
+        CompositeMode::DestinationIn => (0.0, sa),
+        CompositeMode::SourceOut => (1.0 - da, 0.0),
+        CompositeMode::DestinationOut => (0.0, 1.0 - sa),
+        CompositeMode::SourceAtop => (da, 1.0 - sa),
+        CompositeMode::DestinationAtop => (1.0 - da, sa),
+        CompositeMode::Xor => (1.0 - da, 1.0 - sa),
+        CompositeMode::Plus => (1.0, 1.0),
+        _ => (1.0, 1.0 - sa),
+    };
+    [0, 1, 2, 3].map(|c| (src[c] * fa + dst[c] * fb).min(1.0))
+}
+
+fn premultiply(color: RgbaColor) -> [f32; 4] {
+    let a = color.alpha as f32 / 255.0;
+    [
+        color.red as f32 / 255.0 * a,
+        color.green as f32 / 255.0 * a,
+        color.blue as f32 / 255.0 * a,
+        a,
+    ]
+}
+
+fn unpremultiply([r, g, b, a]: [u8; 4]) -> [u8; 4] {
+    if a == 0 {
+        return [0; 4];
+    }
+    let f = |c: u8| ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8;
+    [f(r), f(g), f(b), a]
+}
+
+/// Premultiplies a straight sRGB pixel for the color page texture.
+pub(crate) fn store([r, g, b, a]: [u8; 4], srgb: bool) -> [u8; 4] {
+    let alpha = a as f32 / 255.0;
+    let channel = |c: u8| {
+        let c = c as f32 / 255.0;
+        let c = if srgb {
+            // The texture decodes to linear values, premultiply those.
+            linear_to_srgb(srgb_to_linear(c) * alpha)
+        } else {
+            c * alpha
+        };
+        (c * 255.0).round() as u8
+    };
+    [channel(r), channel(g), channel(b), a]
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn apply(transform: Transform, x: f32, y: f32) -> Point {
+    point(
+        transform.a * x + transform.c * y + transform.e,
+        transform.b * x + transform.d * y + transform.f,
+    )
+}
+
+fn invert(t: Transform) -> Transform {
+    let det = t.a * t.d - t.b * t.c;
+    if det == 0.0 {
+        return Transform::new(0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+    }
+    let (a, b, c, d) = (t.d / det, -t.b / det, -t.c / det, t.a / det);
+    Transform::new(a, b, c, d, -(a * t.e + c * t.f), -(b * t.e + d * t.f))
+}
+
+#[cfg(test)]
+mod tests {
+    use glyph_brush::ab_glyph::FontRef;
+
+    use super::*;
+
+    const FONT: &[u8] = include_bytes!("../examples/fonts/DejaVuSans.ttf");
+
+    /// Font collection of the `fonts`, moving their tables behind the header.
+    fn collection(fonts: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"ttcf".to_vec();
+        data.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        data.extend_from_slice(&(fonts.len() as u32).to_be_bytes());
+        let mut base = 12 + 4 * fonts.len();
+        for font in fonts {
+            data.extend_from_slice(&(base as u32).to_be_bytes());
+            base += font.len().next_multiple_of(4);
+        }
+        for font in fonts {
+            let base = data.len() as u32;
+            let mut font = font.clone();
+            let tables = u16::from_be_bytes([font[4], font[5]]) as usize;
+            for record in 0..tables {
+                let at = 12 + 16 * record + 8;
+                let offset = u32::from_be_bytes(font[at..at + 4].try_into().unwrap());
+                font[at..at + 4].copy_from_slice(&(offset + base).to_be_bytes());
+            }
+            data.extend_from_slice(&font);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+        data
+    }
+
+    /// Copy of the font `data` claiming to have one glyph less.
+    fn fewer_glyphs(data: &[u8]) -> Vec<u8> {
+        let face = ttf_parser::RawFace::parse(data, 0).unwrap();
+        let maxp = face.table(ttf_parser::Tag::from_bytes(b"maxp")).unwrap();
+        let at = maxp.as_ptr() as usize - data.as_ptr() as usize + 4;
+        let count = u16::from_be_bytes([data[at], data[at + 1]]) - 1;
+        let mut data = data.to_vec();
+        data[at..at + 2].copy_from_slice(&count.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn face_of_collection() {
+        let data = collection(&[FONT.to_vec(), fewer_glyphs(FONT)]);
+        for index in [0, 1] {
+            let font = FontRef::try_from_slice_and_index(&data, index).unwrap();
+            let face = face(&font).unwrap();
+            assert_eq!(face.number_of_glyphs() as usize, font.glyph_count());
+        }
+        let font = FontRef::try_from_slice(FONT).unwrap();
+        assert!(face(&font).is_some());
+    }
+
+    #[test]
+    fn premultiplied_round_trip() {
+        let color = RgbaColor::new(255, 128, 0, 128);
+        let [r, g, b, a] = premultiply(color).map(|c| (c * 255.0).round() as u8);
+        assert_eq!([r, g, b, a], [128, 64, 0, 128]);
+        assert_eq!(unpremultiply([r, g, b, a]), [255, 128, 0, 128]);
+        assert_eq!(unpremultiply([10, 20, 30, 0]), [0; 4]);
+    }
+
+    #[test]
+    fn store_premultiplies_in_linear_space() {
+        assert_eq!(store([255, 128, 0, 255], true), [255, 128, 0, 255]);
+        assert_eq!(store([255, 255, 255, 128], false), [128, 128, 128, 128]);
+        // Half of linear white is brighter than half of its sRGB encoding.
+        let [r, ..] = store([255, 255, 255, 128], true);
+        assert_eq!(r, 188);
+        for c in [0.0, 0.002, 0.5, 1.0] {
+            assert!((srgb_to_linear(linear_to_srgb(c)) - c).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn gradient_color_interpolates_between_stops() {
+        let stops = [(0.25, [0.0; 4]), (0.75, [1.0, 0.5, 0.0, 1.0])];
+        assert_eq!(gradient_color(&stops, 0.0), [0.0; 4]);
+        assert_eq!(gradient_color(&stops, 0.5), [0.5, 0.25, 0.0, 0.5]);
+        assert_eq!(gradient_color(&stops, 1.0), [1.0, 0.5, 0.0, 1.0]);
+        assert_eq!(gradient_color(&stops[..1], 0.5), [0.0; 4]);
+    }
+
+    #[test]
+    fn composite_operators() {
+        let src = [0.5, 0.0, 0.0, 0.5];
+        let dst = [0.0, 1.0, 0.0, 1.0];
+        assert_eq!(
+            composite(CompositeMode::SourceOver, src, dst),
+            [0.5, 0.5, 0.0, 1.0]
+        );
+        assert_eq!(composite(CompositeMode::Clear, src, dst), [0.0; 4]);
+        assert_eq!(
+            composite(CompositeMode::DestinationIn, src, dst),
+            [0.0, 0.5, 0.0, 0.5]
+        );
+        assert_eq!(
+            composite(CompositeMode::Plus, src, dst),
+            [0.5, 1.0, 0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn invert_undoes_transform() {
+        let transform = Transform::new(2.0, 0.5, -1.0, 3.0, 10.0, -4.0);
+        let p = apply(transform, 3.0, 7.0);
+        let p = apply(invert(transform), p.x, p.y);
+        assert!((p.x - 3.0).abs() < 1e-4 && (p.y - 7.0).abs() < 1e-4);
+        let singular = Transform::new(1.0, 2.0, 2.0, 4.0, 0.0, 0.0);
+        assert_eq!(apply(invert(singular), 1.0, 1.0), point(0.0, 0.0));
+    }
+
+    #[test]
+    fn decodes_png() {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, 2, 1);
+        encoder.set_color(png::ColorType::GrayscaleAlpha);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&[10, 255, 200, 0])
+            .unwrap();
+        assert_eq!(
+            decode_png(&data),
+            Some((2, 1, vec![[10, 10, 10, 255], [200, 200, 200, 0]]))
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Debug overlay showing the cache textures and what text is laid out into.
+
+use glyph_brush::{
+    ab_glyph::{point, Rect},
+    GlyphVertex,
+};
+
+use crate::{
+    atlas::{Atlas, Page},
+    pipeline::Vertex,
+    TextExtra,
+};
+
+pub(crate) const SECTION_BOUNDS_COLOR: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+pub(crate) const LAYOUT_BOUNDS_COLOR: [f32; 4] = [0.2, 1.0, 0.2, 1.0];
+pub(crate) const GLYPH_QUAD_COLOR: [f32; 4] = [0.2, 0.6, 1.0, 1.0];
+const PAGE_FRAME_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+/// Largest width and height of a cache texture page in the overlay, in pixels.
+const PAGE_PREVIEW_SIZE: f32 = 256.0;
+const PAGE_PREVIEW_MARGIN: f32 = 8.0;
+
+/// Parts of the debug overlay, drawn on top of the text by
+/// [`TextBrush::draw()`](crate::TextBrush::draw), see
+/// [`TextBrush::set_debug_overlay()`](crate::TextBrush::set_debug_overlay).
+///
+/// # Example
+/// ```
+/// use wgpu_text::DebugOverlay;
+///
+/// let clipping = DebugOverlay {
+///     section_bounds: true,
+///     glyph_quads: true,
+///     ..DebugOverlay::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DebugOverlay {
+    /// Every page of the cache textures, in the top right corner of the view.
+    pub atlas: bool,
+    /// Red outlines of the section bounds, which glyphs are clipped to.
+    pub section_bounds: bool,
+    /// Green outlines around the laid out glyphs of each section.
+    pub layout_bounds: bool,
+    /// Blue outlines of every glyph quad.
+    pub glyph_quads: bool,
+}
+
+impl DebugOverlay {
+    /// Overlay with all of its parts.
+    pub const ALL: DebugOverlay = DebugOverlay {
+        atlas: true,
+        section_bounds: true,
+        layout_bounds: true,
+        glyph_quads: true,
+    };
+
+    /// Whether any part of the overlay is drawn.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        *self != DebugOverlay::default()
+    }
+}
+
+/// Outline of the `rect` of a section with the `extra` of its first text.
+/// Unbounded sides are moved far outside of the view.
+pub(crate) fn outline(rect: Rect, extra: &TextExtra, color: [f32; 4]) -> Vertex {
+    const FAR: f32 = 1e6;
+
+    let rect = Rect {
+        min: point(rect.min.x.max(-FAR), rect.min.y.max(-FAR)),
+        max: point(rect.max.x.min(FAR), rect.max.y.min(FAR)),
+    };
+    Vertex::to_vertex(GlyphVertex {
+        tex_coords: Rect::default(),
+        pixel_coords: rect,
+        bounds: rect,
+        extra,
+    })
+    .as_outline(color)
+}
+
+/// Quads of all cache texture pages in clip space, laid out from the top right
+/// corner of a view of `width` and `height` pixels.
+pub(crate) fn atlas_pages(atlas: &Atlas, (width, height): (f32, f32)) -> Vec<Vertex> {
+    let mut pages = Vec::new();
+    let kinds: &[Page] = if atlas.color_glyphs() {
+        &[Page::Main, Page::Color]
+    } else {
+        &[Page::Main]
+    };
+
+    let mut right = width - PAGE_PREVIEW_MARGIN;
+    let mut top = PAGE_PREVIEW_MARGIN;
+    let mut row_height: f32 = 0.0;
+    for &page in kinds {
+        let (page_width, page_height) = atlas.dimensions(page);
+        let scale = (PAGE_PREVIEW_SIZE / page_width.max(page_height) as f32).min(1.0);
+        let (page_width, page_height) =
+            (page_width as f32 * scale, page_height as f32 * scale);
+
+        for index in 0..atlas.page_count(page) {
+            // Continue below once the row is full.
+            if right - page_width < PAGE_PREVIEW_MARGIN
+                && right < width - PAGE_PREVIEW_MARGIN
+            {
+                right = width - PAGE_PREVIEW_MARGIN;
+                top += row_height + PAGE_PREVIEW_MARGIN;
+                row_height = 0.0;
+            }
+            let to_clip =
+                |x: f32, y: f32| point(x / width * 2.0 - 1.0, 1.0 - y / height * 2.0);
+            let quad = Rect {
+                min: to_clip(right - page_width, top),
+                max: to_clip(right, top + page_height),
+            };
+            let vertex = Vertex::to_vertex(GlyphVertex {
+                tex_coords: Rect::default(),
+                pixel_coords: quad,
+                bounds: quad,
+                extra: &TextExtra::default(),
+            });
+            pages.push(vertex.as_outline(PAGE_FRAME_COLOR).with_page(page, index));
+
+            right -= page_width + PAGE_PREVIEW_MARGIN;
+            row_height = row_height.max(page_height);
+        }
+    }
+    pages
+}
@@ -3,8 +3,20 @@ use std::{error::Error, fmt::Display};
 /// Result of `TextBrush` errors and problems.
 #[derive(Debug)]
 pub enum BrushError {
-    /// Cache texture exceeded the limitations stated in `wgpu::Device`.
+    /// A glyph doesn't fit into a cache texture of the largest size stated in
+    /// `wgpu::Device`, or the cache texture can't have more pages.
     TooBigCacheTexture(u32),
+    /// Growing the cache textures would exceed their memory budget in bytes, see
+    /// [`BrushBuilder::cache_memory_budget()`](crate::BrushBuilder::cache_memory_budget).
+    CacheBudgetExceeded(u64),
+    /// The transforms and effects of the sections don't fit into a parameter
+    /// texture of the largest size stated in `wgpu::Device`.
+    TooBigParamsTexture(u32),
+    /// Render targets of the format can't be read back into an
+    /// [`Image`](crate::Image).
+    UnsupportedReadbackFormat(wgpu::TextureFormat),
+    /// Mapping the buffer an offscreen texture was copied into failed.
+    Readback(wgpu::BufferAsyncError),
 }
 
 impl Error for BrushError {}
@@ -17,13 +29,38 @@ impl Display for BrushError {
                 f,
                 "While trying to resize the \
                 cache texture, the 'wgpu::Limits {{ max_texture_dimension_2d }}' \
-                limit of {} was crossed!\n\
-                Resizing the cache texture should be avoided \
-                from the start by building TextBrush with \
-                BrushBuilder::initial_cache_size() and providing bigger cache \
-                texture dimensions.",
+                limit of {} was crossed, and a glyph doesn't fit into a page of \
+                this size or the 'max_texture_array_layers' limit doesn't allow \
+                more pages!\n\
+                Draw the text at a smaller scale or use a RenderMode with \
+                distance fields, which rasterizes glyphs at a fixed size.",
                 dimensions
             ),
+            BrushError::CacheBudgetExceeded(budget) => write!(
+                f,
+                "The glyphs of a single frame need cache textures bigger than \
+                the memory budget of {} bytes!\n\
+                Draw less text at once or build TextBrush with a bigger \
+                BrushBuilder::cache_memory_budget().",
+                budget
+            ),
+            BrushError::TooBigParamsTexture(dimensions) => write!(
+                f,
+                "The transforms and effects of the queued and retained \
+                sections need a parameter texture taller than the \
+                'wgpu::Limits {{ max_texture_dimension_2d }}' limit of {}!\n\
+                Draw fewer transformed sections or texts with effects at once.",
+                dimensions
+            ),
+            BrushError::UnsupportedReadbackFormat(format) => write!(
+                f,
+                "Can't read back render targets of the {:?} format, \
+                only 8-bit RGBA and BGRA formats are supported.",
+                format
+            ),
+            BrushError::Readback(error) => {
+                write!(f, "Failed to read back the offscreen texture: {}", error)
+            }
         }
     }
 }
@@ -0,0 +1,108 @@
+use glyph_brush::{Color, Extra};
+
+/// [`glyph_brush::Extra`] extended with text effects.
+///
+/// Set it on a [`Text`](glyph_brush::Text) with
+/// [`with_extra()`](glyph_brush::Text::with_extra). Sections using the plain
+/// [`glyph_brush::Extra`] are still accepted by [`TextBrush`](crate::TextBrush)
+/// and are drawn without effects.
+///
+/// # Example
+/// ```
+/// use wgpu_text::{glyph_brush::{Section, Text}, TextExtra};
+///
+/// let extra = TextExtra::default()
+///     .with_color([1.0, 1.0, 1.0, 1.0])
+///     .with_outline(2.0, [0.0, 0.0, 0.0, 1.0])
+///     .with_shadow([2.0, 3.0], 4.0, [0.0, 0.0, 0.0, 0.5]);
+/// let section = Section::builder().add_text(Text { extra, ..Text::new("HUD") });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextExtra {
+    pub color: Color,
+    pub z: f32,
+    /// Width of the outline in pixels, `0.0` disables it.
+    pub outline_width: f32,
+    pub outline_color: Color,
+    /// Offset of the shadow in pixels.
+    pub shadow_offset: [f32; 2],
+    /// Blur radius of the shadow in pixels.
+    pub shadow_blur: f32,
+    /// Color of the shadow, fully transparent disables it.
+    pub shadow_color: Color,
+}
+
+impl TextExtra {
+    #[inline]
+    pub fn with_color<C: Into<Color>>(mut self, color: C) -> Self {
+        self.color = color.into();
+        self
+    }
+
+    #[inline]
+    pub fn with_z<Z: Into<f32>>(mut self, z: Z) -> Self {
+        self.z = z.into();
+        self
+    }
+
+    /// Draws an outline of `width` pixels and `color` around the glyphs, in the
+    /// same pass as the glyphs themselves.
+    ///
+    /// In [`RenderMode::Sdf`](crate::RenderMode::Sdf) and
+    /// [`RenderMode::Msdf`](crate::RenderMode::Msdf) the width is limited by the
+    /// `spread` of the distance field. Color glyphs are drawn without an outline.
+    #[inline]
+    pub fn with_outline<C: Into<Color>>(mut self, width: f32, color: C) -> Self {
+        self.outline_width = width;
+        self.outline_color = color.into();
+        self
+    }
+
+    /// Draws a shadow of the glyphs moved by `offset` pixels and blurred over
+    /// `blur` pixels below them, in the same pass as the glyphs themselves.
+    ///
+    /// In [`RenderMode::Sdf`](crate::RenderMode::Sdf) and
+    /// [`RenderMode::Msdf`](crate::RenderMode::Msdf) the blur is limited by the
+    /// `spread` of the distance field. Color glyphs are drawn without a shadow.
+    #[inline]
+    pub fn with_shadow<C: Into<Color>>(
+        mut self,
+        offset: [f32; 2],
+        blur: f32,
+        color: C,
+    ) -> Self {
+        self.shadow_offset = offset;
+        self.shadow_blur = blur;
+        self.shadow_color = color.into();
+        self
+    }
+
+    /// Draws a glow fading out over `radius` pixels around the glyphs, a shadow
+    /// without an offset.
+    #[inline]
+    pub fn with_glow<C: Into<Color>>(self, radius: f32, color: C) -> Self {
+        self.with_shadow([0.0, 0.0], radius, color)
+    }
+}
+
+impl Default for TextExtra {
+    #[inline]
+    fn default() -> Self {
+        Extra::default().into()
+    }
+}
+
+impl From<Extra> for TextExtra {
+    #[inline]
+    fn from(Extra { color, z }: Extra) -> Self {
+        Self {
+            color,
+            z,
+            outline_width: 0.0,
+            outline_color: [0.0, 0.0, 0.0, 0.0],
+            shadow_offset: [0.0, 0.0],
+            shadow_blur: 0.0,
+            shadow_color: [0.0, 0.0, 0.0, 0.0],
+        }
+    }
+}
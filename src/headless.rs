@@ -0,0 +1,254 @@
+//! Offscreen rendering with CPU readback, for environments without a display.
+
+use std::sync::mpsc;
+
+use crate::error::BrushError;
+
+/// RGBA image with 8 bits per channel, read back from the GPU.
+///
+/// Rows are tightly packed from top to bottom, without the padding of the
+/// buffer they were copied into. Pixels of sRGB render targets stay sRGB encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Returns the RGBA pixel at `x` and `y`.
+    ///
+    /// # Panics
+    /// If the pixel is outside of the image.
+    #[inline]
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel outside of the image"
+        );
+        let i = (y * self.width + x) as usize * 4;
+        [
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        ]
+    }
+}
+
+/// Formats of the attachments a pipeline draws into.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TargetFormat {
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub depth_format: Option<wgpu::TextureFormat>,
+}
+
+/// Offscreen attachments matching a [`TargetFormat`].
+pub(crate) struct Target {
+    format: TargetFormat,
+    size: wgpu::Extent3d,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    /// Single sampled texture the multisampled `texture` is resolved into.
+    resolve: Option<(wgpu::Texture, wgpu::TextureView)>,
+    depth: Option<wgpu::TextureView>,
+}
+
+impl Target {
+    pub fn new(
+        device: &wgpu::Device,
+        format: TargetFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, BrushError> {
+        use wgpu::TextureFormat as Format;
+
+        if !matches!(
+            format.format,
+            Format::Rgba8Unorm
+                | Format::Rgba8UnormSrgb
+                | Format::Bgra8Unorm
+                | Format::Bgra8UnormSrgb
+        ) {
+            return Err(BrushError::UnsupportedReadbackFormat(format.format));
+        }
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let create_texture = |format, sample_count, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("wgpu-text Offscreen Texture"),
+                size,
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let attachment = wgpu::TextureUsages::RENDER_ATTACHMENT;
+        let readable = attachment | wgpu::TextureUsages::COPY_SRC;
+
+        let (texture, resolve) = if format.sample_count > 1 {
+            let resolve = create_texture(format.format, 1, readable);
+            let resolve_view =
+                resolve.create_view(&wgpu::TextureViewDescriptor::default());
+            (
+                create_texture(format.format, format.sample_count, attachment),
+                Some((resolve, resolve_view)),
+            )
+        } else {
+            (create_texture(format.format, 1, readable), None)
+        };
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth = format.depth_format.map(|depth_format| {
+            create_texture(depth_format, format.sample_count, attachment)
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+
+        Ok(Self {
+            format,
+            size,
+            texture,
+            view,
+            resolve,
+            depth,
+        })
+    }
+
+    /// Begins a render pass clearing the target with the `clear` color, the
+    /// depth to `1.0` and the stencil to `0`.
+    pub fn begin_render_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        clear: wgpu::Color,
+    ) -> wgpu::RenderPass<'a> {
+        let depth_format = self.format.depth_format;
+        let depth_stencil_attachment =
+            self.depth
+                .as_ref()
+                .map(|view| wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: depth_format
+                        .filter(|format| format.has_depth_aspect())
+                        .map(|_| wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                    stencil_ops: depth_format
+                        .filter(|format| format.has_stencil_aspect())
+                        .map(|_| wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(0),
+                            store: true,
+                        }),
+                });
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("wgpu-text Offscreen Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.view,
+                resolve_target: self.resolve.as_ref().map(|(_, view)| view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment,
+        })
+    }
+
+    /// Submits the `encoder` together with a copy of the rendered texture into a
+    /// buffer, waits for it and returns its pixels as an [`Image`].
+    pub fn read(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: wgpu::CommandEncoder,
+    ) -> Result<Image, BrushError> {
+        let texture = match &self.resolve {
+            Some((texture, _)) => texture,
+            None => &self.texture,
+        };
+        let mut data = read_texture(device, queue, encoder, texture)?;
+
+        let bgra = matches!(
+            self.format.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        if bgra {
+            data.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+        }
+
+        Ok(Image {
+            width: self.size.width,
+            height: self.size.height,
+            data,
+        })
+    }
+}
+
+/// Submits the `encoder` together with a copy of the `texture` into a buffer,
+/// waits for it and returns the texels in tightly packed rows, layer after layer.
+pub(crate) fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mut encoder: wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+) -> Result<Vec<u8>, BrushError> {
+    let size = texture.size();
+    let texel_bytes = texture.format().block_size(None).unwrap_or(4);
+
+    // Rows of buffer copies have to be aligned.
+    let row_bytes = size.width * texel_bytes;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_row_bytes = row_bytes.div_ceil(align) * align;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("wgpu-text Readback Buffer"),
+        size: (padded_row_bytes * size.height * size.depth_or_array_layers)
+            as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: Some(size.height),
+            },
+        },
+        size,
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .unwrap_or(Err(wgpu::BufferAsyncError))
+        .map_err(BrushError::Readback)?;
+
+    let mut data = Vec::with_capacity(
+        (row_bytes * size.height * size.depth_or_array_layers) as usize,
+    );
+    for row in slice
+        .get_mapped_range()
+        .chunks_exact(padded_row_bytes as usize)
+    {
+        data.extend_from_slice(&row[..row_bytes as usize]);
+    }
+    buffer.unmap();
+    Ok(data)
+}
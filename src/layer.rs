@@ -0,0 +1,111 @@
+use std::borrow::Cow;
+
+use glyph_brush::{Extra, OwnedSection, Section};
+
+use crate::{Billboard, Matrix, Transform};
+
+/// [`Section`] queued into a layer, see [`TextBrush::draw_layer()`](crate::TextBrush::draw_layer),
+/// and transformed as a whole.
+///
+/// Created from a `(layer, section)` tuple. Sections given to
+/// [`TextBrush::queue()`](crate::TextBrush::queue) without a layer go into the
+/// layer `0`.
+///
+/// # Example
+/// ```
+/// use wgpu_text::{glyph_brush::{Section, Text}, LayeredSection, Transform};
+///
+/// const BELOW_PANEL: u32 = 0;
+/// const ABOVE_PANEL: u32 = 1;
+///
+/// let title = Section::default().add_text(Text::new("Title"));
+/// let tooltip = Section::default().add_text(Text::new("Tooltip"));
+/// let sections = vec![
+///     LayeredSection::from((BELOW_PANEL, &title))
+///         .with_transform(Transform::default().rotate(0.1)),
+///     LayeredSection::from((ABOVE_PANEL, &tooltip)),
+/// ];
+/// ```
+#[derive(Debug, Clone)]
+pub struct LayeredSection<'a, X: Clone = Extra> {
+    pub layer: u32,
+    pub section: Cow<'a, Section<'a, X>>,
+    /// Applied to the glyph quads of the whole section before the render matrix.
+    pub transform: Matrix,
+    pub billboard: Billboard,
+}
+
+impl<'a, X: Clone> LayeredSection<'a, X> {
+    /// Moves the section into the `layer`.
+    #[inline]
+    pub fn with_layer(mut self, layer: u32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Transforms the section, e.g. with a [`Transform`] rotating it around a
+    /// pivot, before the render matrix is applied.
+    ///
+    /// The section bounds clip the glyphs before they are transformed, so they
+    /// are transformed together with the text.
+    #[inline]
+    pub fn with_transform<M: Into<Matrix>>(mut self, transform: M) -> Self {
+        self.transform = transform.into();
+        self
+    }
+
+    /// Places the section in world space, with the layout origin at `position`
+    /// and every layout pixel being `scale` world units. The y axis points up,
+    /// unlike in the layout.
+    ///
+    /// Use a view-projection matrix as the render matrix and a layout aligned
+    /// around the origin, e.g. a [`Section`] with the default `screen_position`
+    /// and centered [`Layout`](glyph_brush::Layout).
+    #[inline]
+    pub fn with_world_position(self, position: [f32; 3], scale: f32) -> Self {
+        let [x, y, z] = position;
+        self.with_transform([
+            [scale, 0.0, 0.0, 0.0],
+            [0.0, -scale, 0.0, 0.0],
+            [0.0, 0.0, scale, 0.0],
+            [x, y, z, 1.0],
+        ])
+    }
+
+    /// Turns the section towards the camera around the origin of its transform.
+    #[inline]
+    pub fn with_billboard(mut self, billboard: Billboard) -> Self {
+        self.billboard = billboard;
+        self
+    }
+}
+
+macro_rules! impl_from_section {
+    ($($section:ty),*) => {$(
+        impl<'a, X: Clone> From<$section> for LayeredSection<'a, X> {
+            #[inline]
+            fn from(section: $section) -> Self {
+                (0, section).into()
+            }
+        }
+
+        impl<'a, X: Clone> From<(u32, $section)> for LayeredSection<'a, X> {
+            #[inline]
+            fn from((layer, section): (u32, $section)) -> Self {
+                Self {
+                    layer,
+                    section: section.into(),
+                    transform: Transform::default().into(),
+                    billboard: Billboard::None,
+                }
+            }
+        }
+    )*};
+}
+
+impl_from_section!(
+    Section<'a, X>,
+    &'a Section<'a, X>,
+    &'a OwnedSection<X>,
+    Cow<'a, Section<'a, X>>
+);
@@ -0,0 +1,215 @@
+//! Glyph layouts of sections cached between frames, and hashes of the queued
+//! sections telling whether their vertices have to be built again.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{BuildHasher, Hash, Hasher},
+    mem,
+};
+
+use glyph_brush::{
+    ab_glyph::{point, Font, Rect},
+    GlyphPositioner, Section, SectionGeometry, SectionGlyph, Text,
+};
+
+use crate::{LayeredSection, TextExtra};
+
+/// Positioned glyphs of the sections laid out recently, by the hash of the parts
+/// of the section which affect the layout.
+pub(crate) struct LayoutCache<H> {
+    hasher: H,
+    /// Keep layouts between frames, see
+    /// [`cache_glyph_positioning()`](glyph_brush::GlyphBrushBuilder::cache_glyph_positioning).
+    enabled: bool,
+    layouts: HashMap<u64, Vec<SectionGlyph>>,
+    /// Layouts used since the last [`Self::trim()`].
+    used: HashSet<u64>,
+    /// Layouts used in the last frame which laid out the queued sections.
+    kept: HashSet<u64>,
+}
+
+impl<H: BuildHasher> LayoutCache<H> {
+    pub fn new(hasher: H, enabled: bool) -> Self {
+        Self {
+            hasher,
+            enabled,
+            layouts: HashMap::new(),
+            used: HashSet::new(),
+            kept: HashSet::new(),
+        }
+    }
+
+    /// Positioned glyphs of the `section`, laid out again only if it changed.
+    pub fn glyphs<F: Font, X>(
+        &mut self,
+        fonts: &[F],
+        section: &Section<X>,
+    ) -> &[SectionGlyph] {
+        let section = layout_section(section);
+        let hash = self.hasher.hash_one(&section);
+        self.used.insert(hash);
+        if !self.enabled || !self.layouts.contains_key(&hash) {
+            let geometry = SectionGeometry::from(&section);
+            let glyphs = section
+                .layout
+                .calculate_glyphs(fonts, &geometry, &section.text);
+            self.layouts.insert(hash, glyphs);
+        }
+        &self.layouts[&hash]
+    }
+
+    /// Bounding box of the glyphs of the `section` clipped to its bounds, like
+    /// [`GlyphCruncher::glyph_bounds()`](glyph_brush::GlyphCruncher::glyph_bounds).
+    pub fn glyph_bounds<F: Font, X>(
+        &mut self,
+        fonts: &[F],
+        section: &Section<X>,
+    ) -> Option<Rect> {
+        let Rect { min, max } =
+            section.layout.bounds_rect(&SectionGeometry::from(section));
+        self.glyphs(fonts, section)
+            .iter()
+            .map(|glyph| fonts[glyph.font_id.0].glyph_bounds(&glyph.glyph))
+            .reduce(|a, b| Rect {
+                min: point(a.min.x.min(b.min.x), a.min.y.min(b.min.y)),
+                max: point(a.max.x.max(b.max.x), a.max.y.max(b.max.y)),
+            })
+            .map(|bounds| Rect {
+                min: point(bounds.min.x.max(min.x), bounds.min.y.max(min.y)),
+                max: point(bounds.max.x.min(max.x), bounds.max.y.min(max.y)),
+            })
+    }
+
+    /// Drops the layouts which weren't used since the last call. Unless the
+    /// queued sections were `laid_out` again, the ones of the last frame which
+    /// laid them out are kept as well.
+    pub fn trim(&mut self, laid_out: bool) {
+        if laid_out {
+            self.kept = mem::take(&mut self.used);
+        }
+        let (used, kept) = (&self.used, &self.kept);
+        self.layouts
+            .retain(|hash, _| used.contains(hash) || kept.contains(hash));
+        self.used.clear();
+    }
+
+    /// Hash of everything the vertices of the `sections` are built from.
+    pub fn hash_sections<X>(&self, sections: &[LayeredSection<X>]) -> u64
+    where
+        X: Clone + Into<TextExtra>,
+    {
+        let mut state = self.hasher.build_hasher();
+        for LayeredSection {
+            layer,
+            section,
+            transform,
+            billboard,
+        } in sections
+        {
+            let Section {
+                screen_position,
+                bounds,
+                layout,
+                text,
+            } = &**section;
+            layer.hash(&mut state);
+            hash_floats(transform.iter().flatten().copied(), &mut state);
+            (*billboard as u32).hash(&mut state);
+            layout.hash(&mut state);
+            hash_floats(
+                [screen_position.0, screen_position.1, bounds.0, bounds.1],
+                &mut state,
+            );
+
+            for text in text {
+                let TextExtra {
+                    color,
+                    z,
+                    outline_width,
+                    outline_color,
+                    shadow_offset,
+                    shadow_blur,
+                    shadow_color,
+                } = text.extra.clone().into();
+                text.text.hash(&mut state);
+                text.font_id.hash(&mut state);
+                hash_floats([text.scale.x, text.scale.y, z], &mut state);
+                hash_floats([outline_width, shadow_blur], &mut state);
+                hash_floats(shadow_offset, &mut state);
+                hash_floats(color, &mut state);
+                hash_floats(outline_color, &mut state);
+                hash_floats(shadow_color, &mut state);
+            }
+        }
+        state.finish()
+    }
+}
+
+fn hash_floats(floats: impl IntoIterator<Item = f32>, state: &mut impl Hasher) {
+    for float in floats {
+        float.to_bits().hash(state);
+    }
+}
+
+/// Copy of the `section` without its `extra`, which doesn't affect layout.
+fn layout_section<'a, X>(section: &Section<'a, X>) -> Section<'a, ()> {
+    Section {
+        screen_position: section.screen_position,
+        bounds: section.bounds,
+        layout: section.layout,
+        text: section
+            .text
+            .iter()
+            .map(|text| Text {
+                text: text.text,
+                scale: text.scale,
+                font_id: text.font_id,
+                extra: (),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glyph_brush::{ab_glyph::FontRef, DefaultSectionHasher};
+
+    use super::*;
+
+    const FONT: &[u8] = include_bytes!("../examples/fonts/DejaVuSans.ttf");
+
+    fn section(text: &str) -> Section<'_, TextExtra> {
+        Section::new().add_text(Text::new(text))
+    }
+
+    #[test]
+    fn trim_keeps_layouts_of_the_last_frame() {
+        let fonts = [FontRef::try_from_slice(FONT).unwrap()];
+        let mut cache = LayoutCache::new(DefaultSectionHasher::default(), true);
+
+        assert_eq!(cache.glyphs(&fonts, &section("queued")).len(), 6);
+        cache.trim(true);
+        // Frames which didn't lay out the queued sections again keep them, but
+        // not the layouts of other frames.
+        cache.glyphs(&fonts, &section("measured"));
+        cache.trim(false);
+        cache.trim(false);
+        assert_eq!(cache.layouts.len(), 1);
+        cache.trim(true);
+        assert!(cache.layouts.is_empty());
+    }
+
+    #[test]
+    fn hash_covers_extras() {
+        let cache = LayoutCache::new(DefaultSectionHasher::default(), true);
+        let hash = |section: Section<'_, TextExtra>| {
+            cache.hash_sections(&[LayeredSection::from(section)])
+        };
+
+        let plain = section("text");
+        let mut outlined = plain.clone();
+        outlined.text[0].extra = TextExtra::default().with_outline(1.0, [0.0; 4]);
+        assert_eq!(hash(plain.clone()), hash(section("text")));
+        assert_ne!(hash(plain), hash(outlined));
+    }
+}
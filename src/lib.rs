@@ -15,13 +15,46 @@
 //!
 //! > Look trough [`examples`](https://github.com/Blatko1/wgpu_text/tree/master/examples).
 
+mod atlas;
+mod bitmap;
 mod brush;
 mod cache;
+mod color;
+mod debug;
 mod error;
+mod extra;
+mod headless;
+mod layer;
+mod layout;
+mod msdf;
+mod params;
+mod persist;
 mod pipeline;
+mod retained;
+mod sdf;
+mod shared;
+mod stats;
+mod subpixel;
+mod transform;
+mod vertex_buffer;
 
-pub use brush::{BrushBuilder, TextBrush};
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+
+pub use atlas::{Page, RenderMode};
+pub use bitmap::{BitmapFont, BitmapFontError};
+pub use brush::{BrushBuilder, PrewarmReport, TextBrush};
+pub use debug::DebugOverlay;
+pub use error::BrushError;
+pub use extra::TextExtra;
 pub use glyph_brush;
+pub use headless::Image;
+pub use layer::LayeredSection;
+pub use retained::TextHandle;
+pub use shared::{AtlasBuilder, AtlasEvent, SharedAtlas};
+pub use stats::{BrushStats, FrameStats};
+pub use subpixel::SubpixelOrder;
+pub use transform::{Billboard, Transform};
 
 /// Represents a two-dimensional array matrix with 4x4 dimensions.
 pub type Matrix = [[f32; 4]; 4];
@@ -0,0 +1,428 @@
+//! Multi-channel signed distance field generation from glyph outlines.
+//!
+//! A simplified take on Viktor Chlumský's *msdfgen*: outline curves are split
+//! into contours, edges between sharp corners get one of three channel colors and
+//! every channel stores the signed pseudo-distance to its nearest edge. The median
+//! of the three channels reconstructs the outline with sharp corners.
+
+use glyph_brush::ab_glyph::{point, OutlineCurve, Point};
+
+const RED: u8 = 1;
+const GREEN: u8 = 2;
+const BLUE: u8 = 4;
+const CYAN: u8 = GREEN | BLUE;
+const MAGENTA: u8 = RED | BLUE;
+const YELLOW: u8 = RED | GREEN;
+const WHITE: u8 = RED | GREEN | BLUE;
+
+/// Sine of the smallest angle between two edges which is treated as a corner.
+const CORNER_THRESHOLD: f32 = 0.14;
+
+/// Outline curve flattened into a polyline.
+struct Edge {
+    points: Vec<Point>,
+    color: u8,
+}
+
+/// Distance from a point to an [`Edge`].
+#[derive(Clone, Copy)]
+struct EdgeDistance {
+    distance: f32,
+    orthogonality: f32,
+    pseudo_distance: f32,
+}
+
+impl EdgeDistance {
+    const FAR: EdgeDistance = EdgeDistance {
+        distance: f32::INFINITY,
+        orthogonality: 0.0,
+        pseudo_distance: f32::INFINITY,
+    };
+
+    fn closer_than(&self, other: &EdgeDistance) -> bool {
+        if (self.distance - other.distance).abs() < 1e-4 {
+            self.orthogonality > other.orthogonality
+        } else {
+            self.distance < other.distance
+        }
+    }
+}
+
+/// Generates a `width` x `height` RGBA distance field of the `curves`, which must
+/// already be in pixel coordinates of the bitmap.
+///
+/// RGB channels hold the multi-channel field, alpha holds the true signed distance.
+/// `0.5` encodes the edge and the values fade out over `spread` pixels. `coverage`
+/// is the glyph rasterized into the same bitmap, used to determine the inside.
+pub fn generate(
+    curves: &[OutlineCurve],
+    width: usize,
+    height: usize,
+    spread: f32,
+    coverage: &[f32],
+) -> Vec<u8> {
+    let edges = colored_edges(curves);
+    let inside = |i: usize| coverage[i] > 0.5;
+
+    let mut distances = Vec::with_capacity(width * height);
+    // Outlines may be wound either way, vote on which side of an edge is inside.
+    let mut orientation = 0i64;
+    for y in 0..height {
+        for x in 0..width {
+            let p = point(x as f32 + 0.5, y as f32 + 0.5);
+            let mut channels = [EdgeDistance::FAR; 3];
+            let mut nearest = EdgeDistance::FAR;
+            for edge in &edges {
+                let d = edge_distance(&edge.points, p);
+                for (channel, bit) in channels.iter_mut().zip([RED, GREEN, BLUE]) {
+                    if edge.color & bit != 0 && d.closer_than(channel) {
+                        *channel = d;
+                    }
+                }
+                if d.closer_than(&nearest) {
+                    nearest = d;
+                }
+            }
+            if nearest.distance > 0.5 {
+                let agrees = (nearest.pseudo_distance > 0.0) == inside(distances.len());
+                orientation += if agrees { 1 } else { -1 };
+            }
+            distances.push((channels.map(|c| c.pseudo_distance), nearest.distance));
+        }
+    }
+
+    let sign = if orientation < 0 { -1.0 } else { 1.0 };
+    let encode =
+        |d: f32| ((0.5 + d / (2.0 * spread)).clamp(0.0, 1.0) * 255.0).round() as u8;
+
+    let mut data = Vec::with_capacity(width * height * 4);
+    for (i, (channels, distance)) in distances.into_iter().enumerate() {
+        let true_distance = if inside(i) { distance } else { -distance };
+        let mut channels = channels.map(|d| {
+            if d.is_finite() {
+                d * sign
+            } else {
+                true_distance
+            }
+        });
+
+        // Fall back to the true distance where the median lands on the wrong side.
+        let median = channels[0]
+            .min(channels[1])
+            .max(channels[0].max(channels[1]).min(channels[2]));
+        if distance > 1.0 && (median > 0.0) != inside(i) {
+            channels = [true_distance; 3];
+        }
+
+        data.extend(channels.map(encode));
+        data.push(encode(true_distance));
+    }
+    data
+}
+
+/// Splits curves into closed contours, flattens them and assigns channel colors.
+fn colored_edges(curves: &[OutlineCurve]) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    let mut contour: Vec<&OutlineCurve> = Vec::new();
+    let mut flush = |contour: &mut Vec<&OutlineCurve>| {
+        let colors = contour_colors(contour);
+        edges.extend(contour.drain(..).zip(colors).map(|(curve, color)| Edge {
+            points: flatten(curve),
+            color,
+        }));
+    };
+
+    for curve in curves {
+        if let Some(last) = contour.last() {
+            if !close(end_point(last), start_point(curve)) {
+                flush(&mut contour);
+            }
+        }
+        contour.push(curve);
+    }
+    flush(&mut contour);
+    edges
+}
+
+fn contour_colors(contour: &[&OutlineCurve]) -> Vec<u8> {
+    let n = contour.len();
+    let corners: Vec<usize> = (0..n)
+        .filter(|&i| {
+            let a = normalize(end_direction(contour[(i + n - 1) % n]));
+            let b = normalize(start_direction(contour[i]));
+            dot(a, b) <= 0.0 || cross(a, b).abs() > CORNER_THRESHOLD
+        })
+        .collect();
+
+    match corners.len() {
+        // Smooth contour, every channel sees all edges.
+        0 => vec![WHITE; n],
+        // Teardrop, split the contour into thirds starting at the corner.
+        1 if n >= 3 => {
+            let mut colors = vec![WHITE; n];
+            for i in 0..n {
+                colors[(corners[0] + i) % n] = [MAGENTA, WHITE, YELLOW][3 * i / n];
+            }
+            colors
+        }
+        1 => vec![WHITE; n],
+        // Switch colors at every corner, the first and last spline must differ.
+        count => {
+            let mut colors = vec![WHITE; n];
+            let first = CYAN;
+            let mut color = first;
+            for (i, &corner) in corners.iter().enumerate() {
+                let next_corner = corners[(i + 1) % count];
+                let mut edge = corner;
+                loop {
+                    colors[edge] = color;
+                    edge = (edge + 1) % n;
+                    if edge == next_corner {
+                        break;
+                    }
+                }
+                let banned = if i + 2 == count { first } else { color };
+                color = *[CYAN, MAGENTA, YELLOW]
+                    .iter()
+                    .find(|&&c| c != color && c != banned)
+                    .unwrap();
+            }
+            colors
+        }
+    }
+}
+
+fn edge_distance(points: &[Point], p: Point) -> EdgeDistance {
+    let last = points.len() - 2;
+    let mut result = EdgeDistance::FAR;
+    for (i, segment) in points.windows(2).enumerate() {
+        let (a, b) = (segment[0], segment[1]);
+        let ab = b - a;
+        let length_sq = dot(ab, ab);
+        if length_sq == 0.0 {
+            continue;
+        }
+        let t = dot(p - a, ab) / length_sq;
+        let nearest = a + scale(ab, t.clamp(0.0, 1.0));
+        let to_p = p - nearest;
+        let distance = length(to_p);
+        let direction = normalize(ab);
+        let perpendicular = cross(direction, p - a);
+
+        let candidate = EdgeDistance {
+            distance,
+            orthogonality: if distance > 0.0 {
+                (perpendicular / distance).abs()
+            } else {
+                1.0
+            },
+            // Extend the edge beyond its ends, this is what keeps corners sharp.
+            pseudo_distance: if (0.0..=1.0).contains(&t)
+                || (i == 0 && t < 0.0)
+                || (i == last && t > 1.0)
+            {
+                perpendicular
+            } else {
+                distance.copysign(perpendicular)
+            },
+        };
+        if candidate.closer_than(&result) {
+            result = candidate;
+        }
+    }
+    result
+}
+
+fn flatten(curve: &OutlineCurve) -> Vec<Point> {
+    match *curve {
+        OutlineCurve::Line(p0, p1) => vec![p0, p1],
+        OutlineCurve::Quad(p0, p1, p2) => {
+            let steps = segments(length(p1 - p0) + length(p2 - p1));
+            (0..=steps)
+                .map(|i| {
+                    let t = i as f32 / steps as f32;
+                    let mt = 1.0 - t;
+                    scale(p0, mt * mt) + scale(p1, 2.0 * mt * t) + scale(p2, t * t)
+                })
+                .collect()
+        }
+        OutlineCurve::Cubic(p0, p1, p2, p3) => {
+            let steps = segments(length(p1 - p0) + length(p2 - p1) + length(p3 - p2));
+            (0..=steps)
+                .map(|i| {
+                    let t = i as f32 / steps as f32;
+                    let mt = 1.0 - t;
+                    scale(p0, mt * mt * mt)
+                        + scale(p1, 3.0 * mt * mt * t)
+                        + scale(p2, 3.0 * mt * t * t)
+                        + scale(p3, t * t * t)
+                })
+                .collect()
+        }
+    }
+}
+
+/// Number of line segments for a curve of roughly `length` pixels.
+fn segments(length: f32) -> usize {
+    ((length / 2.0).ceil() as usize).clamp(2, 32)
+}
+
+fn start_point(curve: &OutlineCurve) -> Point {
+    match *curve {
+        OutlineCurve::Line(p0, ..)
+        | OutlineCurve::Quad(p0, ..)
+        | OutlineCurve::Cubic(p0, ..) => p0,
+    }
+}
+
+fn end_point(curve: &OutlineCurve) -> Point {
+    match *curve {
+        OutlineCurve::Line(_, p1) => p1,
+        OutlineCurve::Quad(_, _, p2) => p2,
+        OutlineCurve::Cubic(_, _, _, p3) => p3,
+    }
+}
+
+fn start_direction(curve: &OutlineCurve) -> Point {
+    let candidates = match *curve {
+        OutlineCurve::Line(p0, p1) => [p1 - p0; 3],
+        OutlineCurve::Quad(p0, p1, p2) => [p1 - p0, p2 - p0, p2 - p0],
+        OutlineCurve::Cubic(p0, p1, p2, p3) => [p1 - p0, p2 - p0, p3 - p0],
+    };
+    first_non_zero(candidates)
+}
+
+fn end_direction(curve: &OutlineCurve) -> Point {
+    let candidates = match *curve {
+        OutlineCurve::Line(p0, p1) => [p1 - p0; 3],
+        OutlineCurve::Quad(p0, p1, p2) => [p2 - p1, p2 - p0, p2 - p0],
+        OutlineCurve::Cubic(p0, p1, p2, p3) => [p3 - p2, p3 - p1, p3 - p0],
+    };
+    first_non_zero(candidates)
+}
+
+fn first_non_zero(candidates: [Point; 3]) -> Point {
+    candidates
+        .into_iter()
+        .find(|&d| dot(d, d) > 1e-12)
+        .unwrap_or(candidates[0])
+}
+
+fn close(a: Point, b: Point) -> bool {
+    (a.x - b.x).abs() < 1e-3 && (a.y - b.y).abs() < 1e-3
+}
+
+fn dot(a: Point, b: Point) -> f32 {
+    a.x * b.x + a.y * b.y
+}
+
+fn cross(a: Point, b: Point) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+fn length(a: Point) -> f32 {
+    dot(a, a).sqrt()
+}
+
+fn scale(a: Point, s: f32) -> Point {
+    point(a.x * s, a.y * s)
+}
+
+fn normalize(a: Point) -> Point {
+    let length = length(a);
+    if length == 0.0 {
+        a
+    } else {
+        scale(a, 1.0 / length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 16;
+
+    /// Square from 4 to 12 pixels, wound clockwise or counterclockwise.
+    fn square(clockwise: bool) -> Vec<OutlineCurve> {
+        let mut corners = [
+            point(4.0, 4.0),
+            point(12.0, 4.0),
+            point(12.0, 12.0),
+            point(4.0, 12.0),
+        ];
+        if !clockwise {
+            corners.reverse();
+        }
+        (0..4)
+            .map(|i| OutlineCurve::Line(corners[i], corners[(i + 1) % 4]))
+            .collect()
+    }
+
+    fn coverage() -> Vec<f32> {
+        (0..SIZE * SIZE)
+            .map(|i| {
+                let (x, y) = (i % SIZE, i / SIZE);
+                let inside = (4..12).contains(&x) && (4..12).contains(&y);
+                if inside {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    fn median_at(field: &[u8], x: usize, y: usize) -> u8 {
+        let [r, g, b] = [0, 1, 2].map(|c| field[(y * SIZE + x) * 4 + c]);
+        r.min(g).max(r.max(g).min(b))
+    }
+
+    #[test]
+    fn square_inside_and_outside() {
+        let field = generate(&square(true), SIZE, SIZE, 4.0, &coverage());
+        assert_eq!(field.len(), SIZE * SIZE * 4);
+
+        for (x, y) in [(8, 8), (4, 4), (11, 11), (4, 8)] {
+            assert!(median_at(&field, x, y) > 128, "({x}, {y}) is outside");
+            assert!(field[(y * SIZE + x) * 4 + 3] > 128);
+        }
+        for (x, y) in [(0, 0), (3, 8), (12, 8), (8, 15)] {
+            assert!(median_at(&field, x, y) < 128, "({x}, {y}) is inside");
+            assert!(field[(y * SIZE + x) * 4 + 3] < 128);
+        }
+        // Pixel centers 3.5 pixels away from the nearest edge.
+        assert_eq!(median_at(&field, 8, 8), 239);
+        assert_eq!(median_at(&field, 0, 8), 16);
+    }
+
+    #[test]
+    fn sharp_corners() {
+        let field = generate(&square(true), SIZE, SIZE, 4.0, &coverage());
+        // Just outside of the corner diagonally, where a single channel field
+        // would already be rounded off.
+        let corner = median_at(&field, 12, 12);
+        let side = median_at(&field, 12, 8);
+        assert!(corner <= side, "corner {corner}, side {side}");
+    }
+
+    #[test]
+    fn either_winding() {
+        let clockwise = generate(&square(true), SIZE, SIZE, 4.0, &coverage());
+        let counterclockwise = generate(&square(false), SIZE, SIZE, 4.0, &coverage());
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                assert_eq!(
+                    median_at(&clockwise, x, y) > 128,
+                    median_at(&counterclockwise, x, y) > 128
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn no_curves() {
+        let field = generate(&[], 2, 2, 4.0, &[0.0; 4]);
+        assert!(field.iter().all(|&d| d == 0));
+    }
+}
@@ -0,0 +1,369 @@
+//! Parameters shared by many glyph quads, like the transform of a section or the
+//! effects of a text. They are stored in a texture the vertex shader reads them
+//! from, the vertices only hold their index.
+
+use glyph_brush::ab_glyph::Rect;
+
+use crate::{BrushError, Matrix};
+
+/// Width of the parameter texture in texels, every row is 4 KiB.
+const TEXTURE_WIDTH: u32 = 256;
+
+/// Texels of section transforms and text effects, indexed by the vertices.
+///
+/// The index `0` stands for no parameters, so the first texel is never used.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Params {
+    texels: Vec<[f32; 4]>,
+}
+
+/// Effects of the glyphs of a text, in the units the shader works in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Effects {
+    pub outline_color: [f32; 4],
+    pub shadow_color: [f32; 4],
+    /// In texels or distance field units, see the render mode.
+    pub outline_width: f32,
+    pub shadow_blur: f32,
+    /// Offset of the shadow in texels.
+    pub shadow_offset: [f32; 2],
+    /// Pixels the quads grow by on every side to make room for the effects.
+    pub grow: f32,
+    /// The growth in texels.
+    pub tex_grow: [f32; 2],
+    /// Section bounds the grown quads are clipped to.
+    pub bounds: Rect,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            texels: vec![[0.0; 4]],
+        }
+    }
+}
+
+impl Params {
+    pub fn clear(&mut self) {
+        self.truncate(1);
+    }
+
+    /// Adds the `transform` of a section, returns its index.
+    pub fn push_transform(&mut self, transform: Matrix) -> u32 {
+        let index = self.texels.len() as u32;
+        self.texels.extend_from_slice(&transform);
+        index
+    }
+
+    /// Adds the `effects` of a text, returns their index.
+    pub fn push_effects(&mut self, effects: &Effects) -> u32 {
+        let Effects {
+            outline_color,
+            shadow_color,
+            outline_width,
+            shadow_blur,
+            shadow_offset: [x, y],
+            grow,
+            tex_grow,
+            bounds,
+        } = *effects;
+        // Kept finite for the shader, unbounded sides are infinite.
+        let bounds = [
+            bounds.min.x.max(f32::MIN),
+            bounds.min.y.max(f32::MIN),
+            bounds.max.x.min(f32::MAX),
+            bounds.max.y.min(f32::MAX),
+        ];
+
+        let index = self.texels.len() as u32;
+        self.texels.extend_from_slice(&[
+            outline_color,
+            shadow_color,
+            [outline_width, shadow_blur, x, y],
+            [grow, tex_grow[0], tex_grow[1], 0.0],
+            bounds,
+        ]);
+        index
+    }
+
+    /// The texel at the `index`.
+    #[inline]
+    pub fn texel(&self, index: u32) -> [f32; 4] {
+        self.texels[index as usize]
+    }
+
+    /// Appends the parameters of `other`, returns the offset its indices have
+    /// to be moved by, see [`Vertex::rebased()`](crate::pipeline::Vertex::rebased).
+    pub fn append(&mut self, other: &Params) -> u32 {
+        let offset = self.texels.len() as u32 - 1;
+        self.texels.extend_from_slice(&other.texels[1..]);
+        offset
+    }
+
+    /// Writes the parameters of `other` over the ones appended at the `offset`.
+    pub fn write_at(&mut self, offset: u32, other: &Params) {
+        let start = offset as usize + 1;
+        self.texels[start..start + other.texels.len() - 1]
+            .copy_from_slice(&other.texels[1..]);
+    }
+
+    /// Number of texels, including the unused first one.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.texels.len()
+    }
+
+    /// Drops the texels from the `len` on, the unused first one stays.
+    pub fn truncate(&mut self, len: usize) {
+        self.texels.truncate(len.max(1));
+    }
+
+    #[inline]
+    pub fn texels(&self) -> &[[f32; 4]] {
+        &self.texels
+    }
+}
+
+/// Texture holding the [`Params`] of a frame.
+///
+/// With more than one frame in flight, the texture is kept that many times. The
+/// first write of every frame moves on to the next copy, which gets the rows
+/// that changed since it was last written, so writes never wait for a copy the
+/// GPU may still be drawing from.
+#[derive(Debug)]
+pub(crate) struct ParamsTexture {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    textures: Vec<TextureCopy>,
+    /// Index of the texture drawn from.
+    current: usize,
+    /// The current texture was moved on to this frame.
+    rotated: bool,
+    /// Texels written so far, padded to whole rows.
+    texels: Vec<[f32; 4]>,
+}
+
+/// One of the copies of the [`ParamsTexture`].
+#[derive(Debug)]
+struct TextureCopy {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    /// Number of rows of the texture.
+    rows: u32,
+    /// Texels the texture holds.
+    texels: Vec<[f32; 4]>,
+}
+
+impl ParamsTexture {
+    pub fn new(device: &wgpu::Device, frames_in_flight: usize) -> Self {
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("wgpu-text Parameters Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                }],
+            });
+        let textures = (0..frames_in_flight.max(1))
+            .map(|_| {
+                let (texture, bind_group) = create_texture(device, &bind_group_layout, 1);
+                TextureCopy {
+                    texture,
+                    bind_group,
+                    rows: 1,
+                    texels: Vec::new(),
+                }
+            })
+            .collect();
+
+        Self {
+            bind_group_layout,
+            textures,
+            current: 0,
+            rotated: false,
+            texels: Vec::new(),
+        }
+    }
+
+    /// Writes the `texels` into the texture from the texel `at` on, unless they
+    /// were written there last time, doubling its height until they fit. Returns
+    /// the number of bytes written.
+    ///
+    /// Fails if they need more rows than the largest texture size stated in
+    /// `wgpu::Device`.
+    pub fn write(
+        &mut self,
+        at: usize,
+        texels: &[[f32; 4]],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<u64, BrushError> {
+        let end = at + texels.len();
+        if self.texels.get(at..end) == Some(texels) {
+            return Ok(0);
+        }
+        let width = TEXTURE_WIDTH as usize;
+        let rows = end.div_ceil(width) as u32;
+        let max_rows = device.limits().max_texture_dimension_2d;
+        if rows > max_rows {
+            return Err(BrushError::TooBigParamsTexture(max_rows));
+        }
+        if self.texels.len() < end {
+            self.texels.resize(rows as usize * width, [0.0; 4]);
+        }
+        self.texels[at..end].copy_from_slice(texels);
+
+        // The first write of the frame moves on to the next texture.
+        if !self.rotated {
+            self.rotated = true;
+            self.current = (self.current + 1) % self.textures.len();
+        }
+        let copy = &mut self.textures[self.current];
+        let rows = (self.texels.len() / width) as u32;
+        if rows > copy.rows {
+            copy.rows = rows.max(copy.rows * 2).min(max_rows);
+            (copy.texture, copy.bind_group) =
+                create_texture(device, &self.bind_group_layout, copy.rows);
+            copy.texels.clear();
+        }
+
+        // Whole rows are written, from the first to the last one which differs.
+        let differs = |row: &usize| {
+            let texels = row * width..(row + 1) * width;
+            copy.texels.get(texels.clone()) != Some(&self.texels[texels])
+        };
+        let Some(first_row) = (0..rows as usize).find(differs) else {
+            return Ok(0);
+        };
+        let last_row = (first_row..rows as usize)
+            .rfind(differs)
+            .unwrap_or(first_row);
+        let written = first_row * width..(last_row + 1) * width;
+        if copy.texels.len() < written.end {
+            copy.texels.resize(written.end, [0.0; 4]);
+        }
+        copy.texels[written.clone()].copy_from_slice(&self.texels[written.clone()]);
+
+        let data: &[u8] = bytemuck::cast_slice(&self.texels[written]);
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &copy.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: first_row as u32,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(
+                    TEXTURE_WIDTH * std::mem::size_of::<[f32; 4]>() as u32,
+                ),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: TEXTURE_WIDTH,
+                height: (last_row - first_row + 1) as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(data.len() as u64)
+    }
+
+    /// Ends the frame, the next write moves on to the next texture.
+    #[inline]
+    pub fn finish_frame(&mut self) {
+        self.rotated = false;
+    }
+
+    /// Bind group of the texture drawn from.
+    #[inline]
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.textures[self.current].bind_group
+    }
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    rows: u32,
+) -> (wgpu::Texture, wgpu::BindGroup) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("wgpu-text Parameters Texture"),
+        size: wgpu::Extent3d {
+            width: TEXTURE_WIDTH,
+            height: rows,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("wgpu-text Parameters Bind Group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+        }],
+    });
+    (texture, bind_group)
+}
+
+#[cfg(test)]
+mod tests {
+    use glyph_brush::ab_glyph::point;
+
+    use super::*;
+
+    #[test]
+    fn indices_skip_the_unused_texel() {
+        let mut params = Params::default();
+        let transform = params.push_transform([[1.0; 4]; 4]);
+        let effects = params.push_effects(&Effects {
+            outline_color: [1.0, 0.0, 0.0, 1.0],
+            shadow_color: [0.0; 4],
+            outline_width: 2.0,
+            shadow_blur: 0.0,
+            shadow_offset: [0.5, 0.25],
+            grow: 2.0,
+            tex_grow: [0.1, 0.2],
+            bounds: Rect {
+                min: point(f32::NEG_INFINITY, 0.0),
+                max: point(10.0, f32::INFINITY),
+            },
+        });
+
+        assert_eq!((transform, effects), (1, 5));
+        assert_eq!(params.texels[7], [2.0, 0.0, 0.5, 0.25]);
+        assert_eq!(params.texels[8], [2.0, 0.1, 0.2, 0.0]);
+        assert_eq!(params.texels[9], [f32::MIN, 0.0, 10.0, f32::MAX]);
+    }
+
+    #[test]
+    fn append_returns_offset() {
+        let mut first = Params::default();
+        first.push_transform([[1.0; 4]; 4]);
+        let mut second = Params::default();
+        let index = second.push_transform([[2.0; 4]; 4]);
+
+        let offset = first.append(&second);
+        assert_eq!(first.texels[(index + offset) as usize], [2.0; 4]);
+        assert_eq!(first.texels.len(), 9);
+
+        first.clear();
+        assert_eq!(first, Params::default());
+    }
+}
@@ -0,0 +1,176 @@
+//! Versioned binary format of saved glyph caches.
+//!
+//! All numbers are little endian. A file starts with [`MAGIC`] and [`VERSION`],
+//! followed by the hashes of the fonts, the atlas settings, packing and glyphs
+//! written by [`Atlas::encode()`](crate::atlas::Atlas::encode) and the pixels
+//! of the cache texture pages.
+
+pub const MAGIC: &[u8; 8] = b"WGPUTXTA";
+/// Increased whenever the format or the rasterization changes, older files are
+/// ignored.
+pub const VERSION: u32 = 1;
+
+/// Stable 64-bit FNV-1a hash of font data, the same across builds and platforms.
+pub fn font_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct Encoder {
+    pub data: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    /// Writes the length of the `bytes` before them.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+/// Reads what [`Encoder`] wrote, returning `None` when the data ends early.
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.data.split_first_chunk::<N>()?;
+        self.data = rest;
+        Some(*bytes)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|[byte]| byte)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    pub fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = usize::try_from(self.u64()?).ok()?;
+        if len > self.data.len() {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    /// Reads exactly the `expected` bytes, `None` if they differ.
+    pub fn expect(&mut self, expected: &[u8]) -> Option<()> {
+        let rest = self.data.strip_prefix(expected)?;
+        self.data = rest;
+        Some(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded() -> Vec<u8> {
+        let mut out = Encoder::default();
+        out.data.extend_from_slice(MAGIC);
+        out.u8(7);
+        out.u32(0xdead_beef);
+        out.u64(u64::MAX);
+        out.f32(-1.5);
+        out.bytes(b"glyphs");
+        out.bytes(&[]);
+        out.data
+    }
+
+    type Values<'a> = (u8, u32, u64, f32, &'a [u8], &'a [u8]);
+
+    fn decode(data: &[u8]) -> Option<Values<'_>> {
+        let mut input = Decoder::new(data);
+        input.expect(MAGIC)?;
+        Some((
+            input.u8()?,
+            input.u32()?,
+            input.u64()?,
+            input.f32()?,
+            input.bytes()?,
+            input.bytes()?,
+        ))
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = encoded();
+        assert_eq!(
+            decode(&data),
+            Some((7, 0xdead_beef, u64::MAX, -1.5, &b"glyphs"[..], &[][..]))
+        );
+
+        let mut input = Decoder::new(&data);
+        input.expect(MAGIC).unwrap();
+        assert!(!input.is_empty());
+    }
+
+    #[test]
+    fn truncated_data_fails() {
+        let data = encoded();
+        for len in 0..data.len() {
+            assert_eq!(decode(&data[..len]), None, "decoded {len} bytes");
+        }
+    }
+
+    #[test]
+    fn oversized_length_fails() {
+        let mut out = Encoder::default();
+        out.u64(u64::MAX);
+        out.data.extend_from_slice(b"short");
+        assert_eq!(Decoder::new(&out.data).bytes(), None);
+    }
+
+    #[test]
+    fn wrong_magic_fails() {
+        let mut input = Decoder::new(b"WGPUTXTB");
+        assert_eq!(input.expect(MAGIC), None);
+        assert!(!input.is_empty());
+    }
+
+    #[test]
+    fn font_hash_is_stable() {
+        assert_eq!(font_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(font_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -1,154 +1,617 @@
-use std::num::NonZeroU32;
+use std::{num::NonZeroU32, ops::Range, sync::Arc};
 
-use glyph_brush::{
-    ab_glyph::{point, Rect},
-    Rectangle,
-};
+use glyph_brush::ab_glyph::{point, Rect};
 use wgpu::util::DeviceExt;
 
-use crate::{cache::Cache, Matrix};
+use crate::{
+    atlas::{Page, RenderMode},
+    cache::Cache,
+    headless::TargetFormat,
+    params::{Params, ParamsTexture},
+    vertex_buffer::{VertexBuffer, VertexBufferSettings},
+    Billboard, BrushError, Matrix, TextExtra,
+};
 
 /// Responsible for drawing text.
 #[derive(Debug)]
 pub struct Pipeline {
     inner: wgpu::RenderPipeline,
-    cache: Cache,
+    /// Second pass of [`RenderMode::Subpixel`], adds the glyph colors to the
+    /// target darkened by the first one.
+    subpixel_colors: Option<wgpu::RenderPipeline>,
+    target: TargetFormat,
+    /// Matrix uniforms and their bind groups, one per frame in flight.
+    matrices: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+    /// Index of the matrix uniform drawn with.
+    matrix_index: usize,
+    /// Whether this frame moved on to the next matrix uniform already.
+    matrix_rotated: bool,
+    /// Contents of the matrix uniform, the matrix followed by the snapping
+    /// viewport padded to 16 bytes.
+    uniform: [[f32; 4]; 5],
+    /// Bind group of the cache textures, see [`Cache::bind_group`].
+    textures: Arc<wgpu::BindGroup>,
+
+    vertex_buffer: VertexBuffer,
+    params: ParamsTexture,
+
+    template: Template,
+    /// Whether the main cache texture has a single channel.
+    gray_atlas: bool,
+    debug: Option<DebugPipeline>,
+}
+
+/// Everything the render pipelines drawing onto the target are created from.
+#[derive(Debug)]
+struct Template {
+    shader: wgpu::ShaderModule,
+    layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    multisample: wgpu::MultisampleState,
+    multiview: Option<NonZeroU32>,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+}
+
+impl Template {
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        vertex_entry_point: &str,
+        fragment_entry_point: &str,
+        blend: wgpu::BlendState,
+        depth_stencil: Option<wgpu::DepthStencilState>,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("wgpu-text Render Pipeline"),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: vertex_entry_point,
+                buffers: &[Vertex::buffer_layout()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                strip_index_format: Some(wgpu::IndexFormat::Uint16),
+                ..Default::default()
+            },
+            depth_stencil,
+            multisample: self.multisample,
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: fragment_entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: self.multiview,
+        })
+    }
+}
 
+/// Draws the debug overlay, created once it is first enabled.
+#[derive(Debug)]
+struct DebugPipeline {
+    /// Outlines placed like the text.
+    outlines: wgpu::RenderPipeline,
+    /// Cache texture pages and their frames, placed in clip space.
+    atlas: wgpu::RenderPipeline,
+    atlas_frames: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     vertex_buffer_len: usize,
-    vertices: u32,
+    /// Number of outlines at the start of the vertex buffer, followed by the
+    /// cache texture pages.
+    outlines_len: u32,
+    pages_len: u32,
+}
+
+impl DebugPipeline {
+    fn new(device: &wgpu::Device, template: &Template, gray_atlas: bool) -> Self {
+        // Drawn on top of everything, without touching the depth or the stencil.
+        let depth_stencil =
+            template
+                .depth_stencil
+                .as_ref()
+                .map(|state| wgpu::DepthStencilState {
+                    format: state.format,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                });
+        let create_pipeline = |vertex_entry_point, fragment_entry_point| {
+            template.create_pipeline(
+                device,
+                vertex_entry_point,
+                fragment_entry_point,
+                wgpu::BlendState::ALPHA_BLENDING,
+                depth_stencil.clone(),
+            )
+        };
+        let atlas_entry_point = if gray_atlas {
+            "fs_debug_atlas_gray"
+        } else {
+            "fs_debug_atlas"
+        };
+
+        Self {
+            outlines: create_pipeline("vs_debug_outline", "fs_debug_outline"),
+            atlas: create_pipeline("vs_screen", atlas_entry_point),
+            atlas_frames: create_pipeline("vs_screen", "fs_debug_outline"),
+            vertex_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("wgpu-text Debug Vertex Buffer"),
+                size: 0,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            vertex_buffer_len: 0,
+            outlines_len: 0,
+            pages_len: 0,
+        }
+    }
 }
 
 impl Pipeline {
+    /// Draws the glyphs of an atlas in the `render_mode` onto targets of the
+    /// `render_format`. Subpixel glyphs are drawn by the coverage of the whole
+    /// pixel on targets which can't blend each color channel on its own.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         device: &wgpu::Device,
         render_format: wgpu::TextureFormat,
         depth_stencil: Option<wgpu::DepthStencilState>,
         multisample: wgpu::MultisampleState,
         multiview: Option<NonZeroU32>,
-        tex_dimensions: (u32, u32),
+        render_mode: RenderMode,
+        cache: &Cache,
         matrix: Matrix,
+        snap_viewport: [f32; 2],
+        vertex_buffer: VertexBufferSettings,
+        frames_in_flight: usize,
     ) -> Pipeline {
-        let cache = Cache::new(device, tex_dimensions, matrix);
+        let target = TargetFormat {
+            format: render_format,
+            sample_count: multisample.count,
+            depth_format: depth_stencil.as_ref().map(|state| state.format),
+        };
+
+        let [x, y] = snap_viewport;
+        let uniform = [matrix[0], matrix[1], matrix[2], matrix[3], [x, y, 0.0, 0.0]];
+
+        let matrix_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("wgpu-text Matrix Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
+        let matrices = (0..frames_in_flight.max(1))
+            .map(|_| {
+                let buffer =
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("wgpu-text Matrix Buffer"),
+                        contents: bytemuck::cast_slice(&uniform),
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("wgpu-text Matrix Bind Group"),
+                    layout: &matrix_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+                (buffer, bind_group)
+            })
+            .collect();
 
         let shader =
             device.create_shader_module(wgpu::include_wgsl!("shader/shader.wgsl"));
 
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("wgpu-text Vertex Buffer"),
-            size: 0,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let vertex_buffer = VertexBuffer::new(device, vertex_buffer, frames_in_flight);
+        let params = ParamsTexture::new(device, frames_in_flight);
 
         let pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("wgpu-text Render Pipeline Layout"),
-                bind_group_layouts: &[&cache.bind_group_layout],
+                bind_group_layouts: &[
+                    &matrix_bind_group_layout,
+                    &cache.bind_group_layout,
+                    &params.bind_group_layout,
+                ],
                 push_constant_ranges: &[],
             });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("wgpu-text Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::buffer_layout()],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
-                strip_index_format: Some(wgpu::IndexFormat::Uint16),
-                ..Default::default()
-            },
-            depth_stencil,
+        let template = Template {
+            shader,
+            layout: pipeline_layout,
+            format: render_format,
             multisample,
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: render_format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
             multiview,
-        });
+            depth_stencil: depth_stencil.clone(),
+        };
+        let create_pipeline =
+            |entry_point: &str,
+             blend: wgpu::BlendState,
+             depth_stencil: Option<wgpu::DepthStencilState>| {
+                template.create_pipeline(
+                    device,
+                    "vs_main",
+                    entry_point,
+                    blend,
+                    depth_stencil,
+                )
+            };
+
+        let (pipeline, subpixel_colors) = match render_mode {
+            RenderMode::Coverage => (
+                create_pipeline(
+                    "fs_main",
+                    wgpu::BlendState::ALPHA_BLENDING,
+                    depth_stencil,
+                ),
+                None,
+            ),
+            RenderMode::Sdf { .. } => (
+                create_pipeline(
+                    "fs_sdf",
+                    wgpu::BlendState::ALPHA_BLENDING,
+                    depth_stencil,
+                ),
+                None,
+            ),
+            RenderMode::Msdf { .. } => (
+                create_pipeline(
+                    "fs_msdf",
+                    wgpu::BlendState::ALPHA_BLENDING,
+                    depth_stencil,
+                ),
+                None,
+            ),
+            RenderMode::Subpixel(_)
+                if !Self::supports_subpixel(device, render_format) =>
+            {
+                log::warn!(
+                    "{render_format:?} render targets can't blend each color channel \
+                    on its own, drawing the RenderMode::Subpixel glyphs of the atlas \
+                    like RenderMode::Coverage."
+                );
+                (
+                    create_pipeline(
+                        "fs_subpixel_coverage",
+                        wgpu::BlendState::ALPHA_BLENDING,
+                        depth_stencil,
+                    ),
+                    None,
+                )
+            }
+            // Without dual-source blending each color channel is blended on its
+            // own in two passes: the first darkens the target by the coverage of
+            // every channel, the second adds the colors weighted by it.
+            RenderMode::Subpixel(_) => {
+                let darken = wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::Zero,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent::OVER,
+                };
+                let add = wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::Zero,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                };
+                // The second pass draws exactly where the first one did.
+                let colors_depth_stencil = depth_stencil.clone().map(|mut state| {
+                    if state.depth_write_enabled {
+                        state.depth_compare = wgpu::CompareFunction::Equal;
+                        state.depth_write_enabled = false;
+                    }
+                    state.stencil.write_mask = 0;
+                    state
+                });
+                (
+                    create_pipeline("fs_subpixel_darken", darken, depth_stencil),
+                    Some(create_pipeline(
+                        "fs_subpixel_colors",
+                        add,
+                        colors_depth_stencil,
+                    )),
+                )
+            }
+        };
 
         Self {
             inner: pipeline,
-            cache,
+            subpixel_colors,
+            target,
+            matrices,
+            matrix_index: 0,
+            matrix_rotated: false,
+            uniform,
+            textures: cache.bind_group.clone(),
 
             vertex_buffer,
-            vertex_buffer_len: 0,
-            vertices: 0,
+            params,
+
+            template,
+            gray_atlas: matches!(
+                render_mode.cache_format(),
+                wgpu::TextureFormat::R8Unorm
+            ),
+            debug: None,
         }
     }
 
-    /// Raw draw.
-    pub fn draw<'pass>(&'pass self, rpass: &mut wgpu::RenderPass<'pass>) {
-        if self.vertices != 0 {
-            rpass.set_pipeline(&self.inner);
-            rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            rpass.set_bind_group(0, &self.cache.bind_group, &[]);
+    /// Returns the `render_mode`, or [`RenderMode::Coverage`] if it is
+    /// [`RenderMode::Subpixel`] and can't draw onto targets of the `format`.
+    pub fn supported_render_mode(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        render_mode: RenderMode,
+    ) -> RenderMode {
+        match render_mode {
+            RenderMode::Subpixel(_) if !Self::supports_subpixel(device, format) => {
+                log::warn!(
+                    "{format:?} render targets can't blend each color channel \
+                    on its own, falling back to RenderMode::Coverage."
+                );
+                RenderMode::Coverage
+            }
+            render_mode => render_mode,
+        }
+    }
+
+    /// Whether [`RenderMode::Subpixel`] can draw onto targets of the `format`.
+    fn supports_subpixel(device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+        use wgpu::TextureFormat as Format;
+
+        let blendable = format
+            .guaranteed_format_features(device.features())
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::BLENDABLE);
+        let without_rgb = matches!(
+            format,
+            Format::R8Unorm
+                | Format::R8Snorm
+                | Format::R16Unorm
+                | Format::R16Snorm
+                | Format::R16Float
+                | Format::R32Float
+                | Format::Rg8Unorm
+                | Format::Rg8Snorm
+                | Format::Rg16Unorm
+                | Format::Rg16Snorm
+                | Format::Rg16Float
+                | Format::Rg32Float
+        );
+        blendable && !without_rgb
+    }
+
+    /// Whether glyphs are blended in two passes, which only works for quads that
+    /// don't overlap, see [`subpixel::sort_runs()`](crate::subpixel::sort_runs).
+    #[inline]
+    pub fn blends_in_two_passes(&self) -> bool {
+        self.subpixel_colors.is_some()
+    }
+
+    /// Draws only the `instances` range of the vertex buffer. With two passes
+    /// both are drawn for every run of it starting at one of the sorted `runs`.
+    pub fn draw_range<'pass>(
+        &'pass self,
+        rpass: &mut wgpu::RenderPass<'pass>,
+        instances: Range<u32>,
+        runs: &[u32],
+    ) {
+        if instances.is_empty() {
+            return;
+        }
+        rpass.set_vertex_buffer(0, self.vertex_buffer.buffer().slice(..));
+        rpass.set_bind_group(0, &self.matrices[self.matrix_index].1, &[]);
+        rpass.set_bind_group(1, &self.textures, &[]);
+        rpass.set_bind_group(2, self.params.bind_group(), &[]);
 
-            rpass.draw(0..4, 0..self.vertices);
+        let Some(subpixel_colors) = &self.subpixel_colors else {
+            rpass.set_pipeline(&self.inner);
+            rpass.draw(0..4, instances);
+            return;
+        };
+        let first = runs.partition_point(|&start| start <= instances.start);
+        let last = runs.partition_point(|&start| start < instances.end);
+        let mut start = instances.start;
+        for &end in runs[first..last].iter().chain([&instances.end]) {
+            rpass.set_pipeline(&self.inner);
+            rpass.draw(0..4, start..end);
+            rpass.set_pipeline(subpixel_colors);
+            rpass.draw(0..4, start..end);
+            start = end;
         }
     }
-    // TODO look into preallocating the vertex buffer instead of constantly reallocating
-    pub fn update_vertex_buffer(
+
+    /// Makes room for `len` vertices in the vertex buffer, see
+    /// [`VertexBuffer::reserve()`]. Returns whether it had to be recreated, which
+    /// loses its contents.
+    #[inline]
+    pub fn reserve_vertices(&mut self, len: usize, device: &wgpu::Device) -> bool {
+        self.vertex_buffer.reserve(len, device)
+    }
+
+    /// Stages the `vertices` to be written into the vertex buffer, starting at
+    /// the vertex `offset`, until [`Self::finish_frame()`].
+    #[inline]
+    pub fn write_vertices(
         &mut self,
-        vertices: Vec<Vertex>,
+        offset: usize,
+        vertices: &[Vertex],
         device: &wgpu::Device,
-        queue: &wgpu::Queue,
     ) {
-        self.vertices = vertices.len() as u32;
-        let data: &[u8] = bytemuck::cast_slice(&vertices);
+        self.vertex_buffer.write(offset, vertices, device);
+    }
+
+    /// Writes `texels` of the parameters the vertices index into, starting at
+    /// the texel `at`. Returns the number of bytes written.
+    #[inline]
+    pub fn write_params(
+        &mut self,
+        at: usize,
+        texels: &[[f32; 4]],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<u64, BrushError> {
+        self.params.write(at, texels, device, queue)
+    }
 
-        if vertices.len() > self.vertex_buffer_len {
-            self.vertex_buffer_len = vertices.len();
+    /// Submits the vertices written since the last call and ends the frame, the
+    /// next writes go into the buffers of the next frame in flight.
+    #[inline]
+    pub fn finish_frame(&mut self, queue: &wgpu::Queue) {
+        self.vertex_buffer.submit(queue);
+        self.params.finish_frame();
+        self.matrix_rotated = false;
+    }
 
-            self.vertex_buffer =
+    /// Writes the quads of the debug overlay, `outlines_len` outlines followed by
+    /// the cache texture pages. Creates the pipelines drawing them on first use.
+    pub fn update_debug(
+        &mut self,
+        vertices: &[Vertex],
+        outlines_len: usize,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let Pipeline {
+            template,
+            gray_atlas,
+            ..
+        } = self;
+        let debug = self
+            .debug
+            .get_or_insert_with(|| DebugPipeline::new(device, template, *gray_atlas));
+        debug.outlines_len = outlines_len as u32;
+        debug.pages_len = (vertices.len() - outlines_len) as u32;
+        let data: &[u8] = bytemuck::cast_slice(vertices);
+
+        if vertices.len() > debug.vertex_buffer_len {
+            debug.vertex_buffer_len = vertices.len();
+            debug.vertex_buffer =
                 device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("wgpu-text Vertex Buffer"),
+                    label: Some("wgpu-text Debug Vertex Buffer"),
                     usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
                     contents: data,
                 });
+            return;
+        }
+        queue.write_buffer(&debug.vertex_buffer, 0, data);
+    }
 
+    /// Stops drawing the debug overlay, keeping its pipelines for later.
+    pub fn clear_debug(&mut self) {
+        if let Some(debug) = &mut self.debug {
+            debug.outlines_len = 0;
+            debug.pages_len = 0;
+        }
+    }
+
+    /// Draws the debug overlay on top of everything drawn so far.
+    pub fn draw_debug<'pass>(&'pass self, rpass: &mut wgpu::RenderPass<'pass>) {
+        let Some(debug) = &self.debug else {
+            return;
+        };
+        if debug.outlines_len + debug.pages_len == 0 {
             return;
         }
-        queue.write_buffer(&self.vertex_buffer, 0, data);
+        rpass.set_vertex_buffer(0, debug.vertex_buffer.slice(..));
+        rpass.set_bind_group(0, &self.matrices[self.matrix_index].1, &[]);
+        rpass.set_bind_group(1, &self.textures, &[]);
+        rpass.set_bind_group(2, self.params.bind_group(), &[]);
+
+        let pages = debug.outlines_len..debug.outlines_len + debug.pages_len;
+        rpass.set_pipeline(&debug.atlas);
+        rpass.draw(0..4, pages.clone());
+        rpass.set_pipeline(&debug.atlas_frames);
+        rpass.draw(0..4, pages);
+        rpass.set_pipeline(&debug.outlines);
+        rpass.draw(0..4, 0..debug.outlines_len);
+    }
+
+    #[inline]
+    pub fn vertex_buffer_size(&self) -> u64 {
+        self.vertex_buffer.size()
     }
 
     #[inline]
     pub fn update_matrix(&mut self, matrix: Matrix, queue: &wgpu::Queue) {
-        self.cache.update_matrix(matrix, queue);
+        self.uniform[..4].copy_from_slice(&matrix);
+        self.write_uniform(queue);
     }
 
+    /// Snaps glyph quads to the pixels of a render target of the `viewport` size
+    /// in physical pixels, `[0.0, 0.0]` turns snapping off.
     #[inline]
-    pub fn update_texture(
-        &mut self,
-        size: Rectangle<u32>,
-        data: &[u8],
-        queue: &wgpu::Queue,
-    ) {
-        self.cache.update_texture(size, data, queue);
+    pub fn update_snap_viewport(&mut self, viewport: [f32; 2], queue: &wgpu::Queue) {
+        self.uniform[4] = [viewport[0], viewport[1], 0.0, 0.0];
+        self.write_uniform(queue);
+    }
+
+    /// Writes the whole matrix uniform into the one of this frame, moving on to
+    /// the next one with the first write of the frame.
+    fn write_uniform(&mut self, queue: &wgpu::Queue) {
+        if !self.matrix_rotated {
+            self.matrix_rotated = true;
+            self.matrix_index = (self.matrix_index + 1) % self.matrices.len();
+        }
+        queue.write_buffer(
+            &self.matrices[self.matrix_index].0,
+            0,
+            bytemuck::cast_slice(&self.uniform),
+        );
     }
 
+    /// Formats of the attachments the pipeline draws into.
     #[inline]
-    pub fn resize_texture(&mut self, device: &wgpu::Device, tex_dimensions: (u32, u32)) {
-        self.cache.recreate_texture(device, tex_dimensions);
+    pub fn target(&self) -> TargetFormat {
+        self.target
+    }
+
+    /// Draws from the current cache textures, which are recreated when resized.
+    #[inline]
+    pub fn update_textures(&mut self, cache: &Cache) {
+        self.textures = cache.bind_group.clone();
     }
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     top_left: [f32; 3],
     bottom_right: [f32; 2],
     tex_top_left: [f32; 2],
     tex_bottom_right: [f32; 2],
     color: [f32; 4],
+    // The following three are passed to the shader as a single attribute.
+    page: u32,
+    /// Index of the section transform in the [`Params`], with the [`Billboard`]
+    /// in the lowest two bits.
+    section: u32,
+    /// Index of the text [`Effects`](crate::params::Effects) in the [`Params`].
+    effects: u32,
 }
 
 impl Vertex {
@@ -158,9 +621,8 @@ impl Vertex {
             pixel_coords,
             bounds,
             extra,
-        }: glyph_brush::GlyphVertex,
+        }: glyph_brush::GlyphVertex<TextExtra>,
     ) -> Vertex {
-        let bounds = bounds;
         let mut rect = Rect {
             min: point(pixel_coords.min.x, pixel_coords.min.y),
             max: point(pixel_coords.max.x, pixel_coords.max.y),
@@ -198,9 +660,81 @@ impl Vertex {
             tex_top_left: [tex_coords.min.x, tex_coords.min.y],
             tex_bottom_right: [tex_coords.max.x, tex_coords.max.y],
             color: extra.color,
+            page: Page::Main as u32,
+            section: Billboard::None as u32,
+            effects: 0,
         }
     }
 
+    /// Applies the section transform at the `index` of the [`Params`] to the
+    /// quad before the render matrix, `0` for none.
+    #[inline]
+    pub fn with_section(mut self, index: u32, billboard: Billboard) -> Vertex {
+        // The billboard is in the lowest two bits, the shader splits them apart.
+        self.section = billboard as u32 | index << 2;
+        self
+    }
+
+    /// Draws the effects at the `index` of the [`Params`] around the glyph. The
+    /// shader grows the quad by them and clips it to the section bounds, so the
+    /// quad must not be clipped already.
+    #[inline]
+    pub fn with_effects(mut self, index: u32) -> Vertex {
+        self.effects = index;
+        self
+    }
+
+    /// Moves the indices into the [`Params`] by the `offset` they were appended
+    /// at, see [`Params::append()`].
+    #[inline]
+    pub fn rebased(mut self, offset: u32) -> Vertex {
+        if self.section >> 2 != 0 {
+            self.section += offset << 2;
+        }
+        if self.effects != 0 {
+            self.effects += offset;
+        }
+        self
+    }
+
+    /// Screen rectangle the quad covers before the section transform, grown by
+    /// its effects in the `params` and clipped to their bounds like the shader
+    /// does.
+    pub fn quad(&self, params: &Params) -> Rect {
+        let mut rect = Rect {
+            min: point(self.top_left[0], self.top_left[1]),
+            max: point(self.bottom_right[0], self.bottom_right[1]),
+        };
+        if self.effects != 0 {
+            let grow = params.texel(self.effects + 3)[0];
+            let [min_x, min_y, max_x, max_y] = params.texel(self.effects + 4);
+            rect.min.x = (rect.min.x - grow).max(min_x);
+            rect.min.y = (rect.min.y - grow).max(min_y);
+            rect.max.x = (rect.max.x + grow).min(max_x);
+            rect.max.y = (rect.max.y + grow).min(max_y);
+        }
+        rect
+    }
+
+    /// Samples the glyph from the page at the `index` of the cache texture of
+    /// the `page` kind.
+    #[inline]
+    pub fn with_page(mut self, page: Page, index: u32) -> Vertex {
+        // The kind is in the lowest bit, the shader splits them apart.
+        self.page = page as u32 | index << 1;
+        self
+    }
+
+    /// Turns the quad into an outline of the `color` for the debug overlay.
+    #[inline]
+    pub fn as_outline(mut self, color: [f32; 4]) -> Vertex {
+        self.tex_top_left = [0.0, 0.0];
+        self.tex_bottom_right = [1.0, 1.0];
+        self.color = color;
+        self.effects = 0;
+        self
+    }
+
     pub fn buffer_layout() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
@@ -231,6 +765,11 @@ impl Vertex {
                     offset: std::mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
                     shader_location: 4,
                 },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32x3,
+                    offset: std::mem::size_of::<[f32; 13]>() as wgpu::BufferAddress,
+                    shader_location: 5,
+                },
             ],
         }
     }
@@ -0,0 +1,517 @@
+//! Sections kept by a [`TextBrush`](crate::TextBrush) between frames, see
+//! [`TextBrush::insert()`](crate::TextBrush::insert).
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+};
+
+use glyph_brush::{OwnedSection, OwnedText};
+
+use crate::{
+    params::Params, pipeline::Vertex, subpixel, Billboard, LayeredSection, Matrix,
+    TextExtra,
+};
+
+/// Handle of a section retained by a [`TextBrush`](crate::TextBrush), returned by
+/// [`TextBrush::insert()`](crate::TextBrush::insert). Only valid for the brush
+/// which returned it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextHandle(u64);
+
+pub(crate) struct Entry {
+    pub section: OwnedSection<TextExtra>,
+    pub transform: Matrix,
+    pub billboard: Billboard,
+    pub vertices: Vec<Vertex>,
+    /// Parameters the vertices index into.
+    pub params: Params,
+    /// Offset of the parameters in the joined ones, see [`Vertex::rebased()`].
+    params_offset: u32,
+    /// Where the vertices and parameters are in the joined ones, `None` until
+    /// they are joined.
+    joined: Option<Joined>,
+    /// Outlines of the debug overlay.
+    pub outlines: Vec<Vertex>,
+    /// The section has to be laid out again.
+    pub dirty: bool,
+    /// The section was laid out since the vertices were joined.
+    pub pending: bool,
+}
+
+/// Ranges of the vertices and texels of a section in the joined ones.
+#[derive(Debug, Clone, PartialEq)]
+struct Joined {
+    vertices: Range<usize>,
+    texels: Range<usize>,
+}
+
+/// Parts of the joined vertices and parameters which changed, see
+/// [`Retained::join()`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Changes {
+    pub vertices: Range<usize>,
+    /// Texels of the parameters.
+    pub texels: Range<usize>,
+}
+
+#[derive(Default)]
+pub(crate) struct Retained {
+    next_handle: u64,
+    layers: HashMap<TextHandle, u32>,
+    /// Sections sorted by layer, then by the order they were inserted in.
+    pub sections: BTreeMap<(u32, TextHandle), Entry>,
+    /// Atlas epoch the vertices were created at.
+    pub epoch: u64,
+    /// Sections were laid out, inserted or removed since the vertices were joined.
+    pub changed: bool,
+    /// First of the sections which were removed or moved to another layer since
+    /// the vertices were joined, the ones following it move.
+    moved_from: Option<(u32, TextHandle)>,
+    /// Vertices of all sections, in the order of the vertex buffer.
+    pub vertices: Vec<Vertex>,
+    /// Parameters of all sections, in the same order.
+    pub params: Params,
+    /// Instance ranges of the layers, sorted by layer.
+    pub layers_ranges: Vec<(u32, Range<u32>)>,
+    /// The glyphs of every layer are sorted into runs of quads which don't
+    /// overlap, see [`subpixel::sort_runs()`].
+    split_runs: bool,
+    /// Starts of the runs, sorted.
+    pub runs: Vec<u32>,
+}
+
+impl Retained {
+    /// Empty retained sections, which sort their glyphs into runs if they are
+    /// blended in two passes.
+    pub fn new(split_runs: bool) -> Self {
+        Self {
+            split_runs,
+            ..Self::default()
+        }
+    }
+
+    pub fn insert(&mut self, layer: u32, entry: Entry) -> TextHandle {
+        let handle = TextHandle(self.next_handle);
+        self.next_handle += 1;
+        self.layers.insert(handle, layer);
+        self.sections.insert((layer, handle), entry);
+        handle
+    }
+
+    /// Replaces the section of the `handle`, returns `false` if there is none.
+    pub fn update(&mut self, handle: TextHandle, layer: u32, mut entry: Entry) -> bool {
+        let Some(&old_layer) = self.layers.get(&handle) else {
+            return false;
+        };
+        let key = (old_layer, handle);
+        let old = self.sections.remove(&key);
+        if old_layer == layer {
+            // Patched in place if it keeps its number of vertices and parameters.
+            if let Some(old) = old {
+                entry.params_offset = old.params_offset;
+                entry.joined = old.joined;
+            }
+        } else {
+            self.moved(key);
+        }
+        self.layers.insert(handle, layer);
+        self.sections.insert((layer, handle), entry);
+        true
+    }
+
+    /// Removes the section of the `handle`, returns `false` if there is none.
+    pub fn remove(&mut self, handle: TextHandle) -> bool {
+        let Some(layer) = self.layers.remove(&handle) else {
+            return false;
+        };
+        self.sections.remove(&(layer, handle));
+        self.moved((layer, handle));
+        true
+    }
+
+    /// The sections following the `key` move.
+    fn moved(&mut self, key: (u32, TextHandle)) {
+        self.moved_from = Some(self.moved_from.map_or(key, |from| from.min(key)));
+        self.changed = true;
+    }
+
+    /// Lays out all sections again with the next frame.
+    pub fn invalidate(&mut self) {
+        self.sections
+            .values_mut()
+            .for_each(|entry| entry.dirty = true);
+    }
+
+    /// Joins the vertices and parameters of all sections if any changed, returns
+    /// the parts of them which differ from the previously joined ones.
+    ///
+    /// Sections which were laid out again with the same number of vertices and
+    /// parameters are written over their old ones. All sections following one
+    /// which was inserted, removed or changed its size move. When glyphs are
+    /// sorted into runs, whole layers are sorted again instead of sections.
+    pub fn join(&mut self) -> Option<Changes> {
+        if !self.changed {
+            return None;
+        }
+        self.changed = false;
+
+        // Sections from this one on move.
+        let mut tail = self.moved_from.take();
+        for (&key, entry) in self.sections.iter().filter(|(_, entry)| entry.pending) {
+            let keeps_size = entry.joined.as_ref().is_some_and(|joined| {
+                joined.vertices.len() == entry.vertices.len()
+                    && joined.texels.len() == entry.params.len() - 1
+            });
+            if !keeps_size {
+                tail = Some(tail.map_or(key, |tail| tail.min(key)));
+                break;
+            }
+        }
+        // Glyphs of a layer are sorted across its sections.
+        let tail = tail.map(|(layer, handle)| match self.split_runs {
+            true => (layer, TextHandle(0)),
+            false => (layer, handle),
+        });
+        let mut changes: Option<Changes> = None;
+
+        // Sections in front of the moving ones are written over their old
+        // vertices and parameters.
+        let mut sorted_layers = Vec::new();
+        let in_place = match tail {
+            Some(tail) => self.sections.range_mut(..tail),
+            None => self.sections.range_mut(..),
+        };
+        for (&(layer, _), entry) in in_place.filter(|(_, entry)| entry.pending) {
+            entry.pending = false;
+            let Some(joined) = entry.joined.clone() else {
+                continue;
+            };
+            self.params.write_at(entry.params_offset, &entry.params);
+            if self.split_runs {
+                if sorted_layers.last() != Some(&layer) {
+                    sorted_layers.push(layer);
+                }
+            } else {
+                let offset = entry.params_offset;
+                let vertices = &mut self.vertices[joined.vertices.clone()];
+                for (vertex, new) in vertices.iter_mut().zip(&entry.vertices) {
+                    *vertex = new.rebased(offset);
+                }
+            }
+            Changes::add(&mut changes, joined.vertices, joined.texels);
+        }
+        for layer in sorted_layers {
+            let vertices = self.sort_layer(layer);
+            Changes::add(&mut changes, vertices, 0..0);
+        }
+
+        // The moving sections are appended again.
+        if let Some(tail) = tail {
+            let (vertices_end, texels_end) = self
+                .sections
+                .range(..tail)
+                .next_back()
+                .and_then(|(_, entry)| entry.joined.as_ref())
+                .map_or((0, 1), |joined| (joined.vertices.end, joined.texels.end));
+            self.vertices.truncate(vertices_end);
+            self.params.truncate(texels_end);
+            for entry in self.sections.range_mut(tail..).map(|(_, entry)| entry) {
+                entry.pending = false;
+                let texels = self.params.len();
+                entry.params_offset = self.params.append(&entry.params);
+                let start = self.vertices.len();
+                let offset = entry.params_offset;
+                self.vertices
+                    .extend(entry.vertices.iter().map(|vertex| vertex.rebased(offset)));
+                entry.joined = Some(Joined {
+                    vertices: start..self.vertices.len(),
+                    texels: texels..self.params.len(),
+                });
+            }
+            Changes::add(
+                &mut changes,
+                vertices_end..self.vertices.len(),
+                texels_end..self.params.len(),
+            );
+        }
+
+        self.layers_ranges.clear();
+        for (&(layer, _), entry) in &self.sections {
+            let Some(Joined { vertices, .. }) = &entry.joined else {
+                continue;
+            };
+            let (start, end) = (vertices.start as u32, vertices.end as u32);
+            match self.layers_ranges.last_mut() {
+                Some((last, range)) if *last == layer => range.end = end,
+                _ => self.layers_ranges.push((layer, start..end)),
+            }
+        }
+        if let (true, Some((tail, _))) = (self.split_runs, tail) {
+            let first = self
+                .layers_ranges
+                .partition_point(|(layer, _)| *layer < tail);
+            let moved = &self.layers_ranges[first..];
+            let start = moved
+                .first()
+                .map_or(self.vertices.len() as u32, |(_, range)| range.start);
+            self.runs
+                .truncate(self.runs.partition_point(|&run| run < start));
+            for (_, range) in moved {
+                let layer = &mut self.vertices[range.start as usize..range.end as usize];
+                subpixel::sort_runs(layer, &self.params, range.start, &mut self.runs);
+            }
+        }
+        changes
+    }
+
+    /// Writes the vertices of the sections of the `layer` over its old ones and
+    /// sorts them into runs again, returns their range.
+    fn sort_layer(&mut self, layer: u32) -> Range<usize> {
+        let sections = self
+            .sections
+            .range((layer, TextHandle(0))..=(layer, TextHandle(u64::MAX)));
+        let mut range: Option<Range<usize>> = None;
+        for (_, entry) in sections {
+            let Some(joined) = &entry.joined else {
+                continue;
+            };
+            let offset = entry.params_offset;
+            let vertices = &mut self.vertices[joined.vertices.clone()];
+            for (vertex, new) in vertices.iter_mut().zip(&entry.vertices) {
+                *vertex = new.rebased(offset);
+            }
+            range = Some(match range {
+                Some(range) => range.start..joined.vertices.end,
+                None => joined.vertices.clone(),
+            });
+        }
+        let range = range.unwrap_or_default();
+
+        let (start, end) = (range.start as u32, range.end as u32);
+        let first = self.runs.partition_point(|&run| run < start);
+        let last = self.runs.partition_point(|&run| run < end);
+        let mut runs = Vec::new();
+        let layer = &mut self.vertices[range.clone()];
+        subpixel::sort_runs(layer, &self.params, start, &mut runs);
+        self.runs.splice(first..last, runs);
+        range
+    }
+
+    /// Debug outlines of all sections.
+    pub fn outlines(&self) -> impl Iterator<Item = Vertex> + '_ {
+        self.sections.values().flat_map(|entry| {
+            let offset = entry.params_offset;
+            entry
+                .outlines
+                .iter()
+                .map(move |outline| outline.rebased(offset))
+        })
+    }
+}
+
+impl Changes {
+    /// Extends the `changes` by the `vertices` and `texels`.
+    fn add(changes: &mut Option<Changes>, vertices: Range<usize>, texels: Range<usize>) {
+        let (vertices, texels) = match changes.take() {
+            Some(changes) => (
+                changes.vertices.start.min(vertices.start)
+                    ..changes.vertices.end.max(vertices.end),
+                match texels.is_empty() {
+                    true => changes.texels,
+                    false => {
+                        changes.texels.start.min(texels.start)
+                            ..changes.texels.end.max(texels.end)
+                    }
+                },
+            ),
+            None => (vertices, texels),
+        };
+        *changes = Some(Changes { vertices, texels });
+    }
+}
+
+impl Entry {
+    /// Copy of the `section` which owns its text, with the extras converted into
+    /// [`TextExtra`]. Returns it together with its layer.
+    pub fn new<X>(section: &LayeredSection<X>) -> (u32, Self)
+    where
+        X: Clone + Into<TextExtra>,
+    {
+        let LayeredSection {
+            layer,
+            section,
+            transform,
+            billboard,
+        } = section;
+        let section = OwnedSection {
+            screen_position: section.screen_position,
+            bounds: section.bounds,
+            layout: section.layout,
+            text: section
+                .text
+                .iter()
+                .map(|text| OwnedText {
+                    text: text.text.to_owned(),
+                    scale: text.scale,
+                    font_id: text.font_id,
+                    extra: text.extra.clone().into(),
+                })
+                .collect(),
+        };
+        let entry = Self {
+            section,
+            transform: *transform,
+            billboard: *billboard,
+            vertices: Vec::new(),
+            params: Params::default(),
+            params_offset: 0,
+            joined: None,
+            outlines: Vec::new(),
+            dirty: true,
+            pending: false,
+        };
+        (*layer, entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use super::*;
+    use crate::atlas::Page;
+
+    /// Vertices told apart by their page index.
+    fn vertices(indices: impl IntoIterator<Item = u32>) -> Vec<Vertex> {
+        indices
+            .into_iter()
+            .map(|index| Vertex::zeroed().with_page(Page::Main, index))
+            .collect()
+    }
+
+    fn entry() -> Entry {
+        let section = OwnedSection::<TextExtra>::default();
+        Entry::new(&LayeredSection::from(&section)).1
+    }
+
+    fn set_vertices(
+        retained: &mut Retained,
+        handle: TextHandle,
+        indices: impl IntoIterator<Item = u32>,
+    ) {
+        let layer = retained.layers[&handle];
+        let entry = retained.sections.get_mut(&(layer, handle)).unwrap();
+        entry.vertices = vertices(indices);
+        entry.pending = true;
+        retained.changed = true;
+    }
+
+    fn insert(
+        retained: &mut Retained,
+        layer: u32,
+        indices: impl IntoIterator<Item = u32>,
+    ) -> TextHandle {
+        let handle = retained.insert(layer, entry());
+        set_vertices(retained, handle, indices);
+        handle
+    }
+
+    fn changes(vertices: Range<usize>, texels: Range<usize>) -> Option<Changes> {
+        Some(Changes { vertices, texels })
+    }
+
+    #[test]
+    fn join_sorts_by_layer() {
+        let mut retained = Retained::default();
+        insert(&mut retained, 1, [0, 1]);
+        insert(&mut retained, 0, [2]);
+        insert(&mut retained, 1, [3]);
+
+        assert_eq!(retained.join(), changes(0..4, 1..1));
+        assert_eq!(retained.vertices, vertices([2, 0, 1, 3]));
+        assert_eq!(retained.layers_ranges, [(0, 0..1), (1, 1..4)]);
+        assert_eq!(retained.join(), None);
+    }
+
+    #[test]
+    fn join_returns_changed_range() {
+        let mut retained = Retained::default();
+        let first = insert(&mut retained, 0, [0, 1, 2]);
+        let second = insert(&mut retained, 0, [3, 4, 5]);
+        let third = insert(&mut retained, 0, [6, 7]);
+        retained.join();
+
+        // Sections of the same length are written over their old vertices.
+        set_vertices(&mut retained, second, [3, 9, 5]);
+        assert_eq!(retained.join(), changes(3..6, 1..1));
+        assert_eq!(retained.vertices, vertices([0, 1, 2, 3, 9, 5, 6, 7]));
+
+        // Other lengths move all following vertices.
+        set_vertices(&mut retained, first, [0, 1]);
+        assert_eq!(retained.join(), changes(0..7, 1..1));
+        assert!(retained.remove(third));
+        assert_eq!(retained.join(), changes(5..5, 1..1));
+        assert_eq!(retained.vertices, vertices([0, 1, 3, 9, 5]));
+    }
+
+    #[test]
+    fn join_patches_parameters_in_place() {
+        let mut retained = Retained::default();
+        let sections = [0.0, 1.0].map(|x| {
+            let handle = insert(&mut retained, 0, [0]);
+            let entry = retained.sections.get_mut(&(0, handle)).unwrap();
+            entry.params.push_transform([[x; 4]; 4]);
+            handle
+        });
+        assert_eq!(retained.join(), changes(0..2, 1..9));
+
+        let entry = retained.sections.get_mut(&(0, sections[1])).unwrap();
+        entry.params.clear();
+        entry.params.push_transform([[2.0; 4]; 4]);
+        set_vertices(&mut retained, sections[1], [1]);
+        assert_eq!(retained.join(), changes(1..2, 5..9));
+        assert_eq!(retained.params.texel(1), [0.0; 4]);
+        assert_eq!(retained.params.texel(5), [2.0; 4]);
+        assert_eq!(retained.params.len(), 9);
+    }
+
+    #[test]
+    fn join_sorts_whole_layers_into_runs() {
+        let mut retained = Retained::new(true);
+        let first = insert(&mut retained, 0, [0, 1]);
+        insert(&mut retained, 0, [2]);
+        insert(&mut retained, 1, [3]);
+        assert_eq!(retained.join(), changes(0..4, 1..1));
+        assert_eq!(retained.runs, [0, 3]);
+
+        // The other sections of the layer are sorted again with it.
+        set_vertices(&mut retained, first, [4, 5]);
+        assert_eq!(retained.join(), changes(0..3, 1..1));
+        assert_eq!(retained.vertices, vertices([4, 5, 2, 3]));
+        assert_eq!(retained.runs, [0, 3]);
+
+        set_vertices(&mut retained, first, [4]);
+        assert_eq!(retained.join(), changes(0..3, 1..1));
+        assert_eq!(retained.layers_ranges, [(0, 0..2), (1, 2..3)]);
+        assert_eq!(retained.runs, [0, 2]);
+    }
+
+    #[test]
+    fn update_moves_between_layers() {
+        let mut retained = Retained::default();
+        let handle = insert(&mut retained, 0, [0]);
+        insert(&mut retained, 1, [1]);
+        retained.join();
+
+        assert!(retained.update(handle, 2, entry()));
+        set_vertices(&mut retained, handle, [0]);
+        assert_eq!(retained.join(), changes(0..2, 1..1));
+        assert_eq!(retained.vertices, vertices([1, 0]));
+        assert_eq!(retained.layers_ranges, [(1, 0..1), (2, 1..2)]);
+
+        assert!(retained.remove(handle));
+        assert!(!retained.remove(handle));
+        assert!(!retained.update(handle, 0, entry()));
+    }
+}
@@ -0,0 +1,170 @@
+//! Signed distance field generation from rasterized glyph coverage.
+//!
+//! Uses the linear time euclidean distance transform by Felzenszwalb and
+//! Huttenlocher, with partially covered pixels treated as sub-pixel edge
+//! offsets (the same approach as Mapbox's TinySDF).
+
+const INF: f32 = 1e20;
+
+/// Converts a `width` x `height` coverage bitmap into a signed distance field.
+///
+/// The glyph edge is encoded as `0.5`, values fall to `0.0` at `spread` pixels
+/// outside of the glyph and rise to `1.0` at `spread` pixels inside of it.
+pub fn from_coverage(
+    coverage: &[f32],
+    width: usize,
+    height: usize,
+    spread: f32,
+) -> Vec<u8> {
+    let mut outer = vec![0.0; width * height];
+    let mut inner = vec![0.0; width * height];
+
+    for (i, &a) in coverage.iter().enumerate() {
+        // Rounding leaves faint coverage along the rows of the rasterizer, which
+        // would otherwise turn into edges far from the glyph.
+        let a = (a.clamp(0.0, 1.0) * 255.0).round() / 255.0;
+        if a >= 1.0 {
+            inner[i] = INF;
+        } else if a <= 0.0 {
+            outer[i] = INF;
+        } else {
+            let d = 0.5 - a;
+            outer[i] = d.max(0.0).powi(2);
+            inner[i] = d.min(0.0).powi(2);
+        }
+    }
+
+    edt(&mut outer, width, height);
+    edt(&mut inner, width, height);
+
+    outer
+        .iter()
+        .zip(inner.iter())
+        .map(|(o, i)| {
+            let distance = o.sqrt() - i.sqrt();
+            ((0.5 - distance / (2.0 * spread)).clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+/// 2D squared euclidean distance transform, done in place.
+fn edt(grid: &mut [f32], width: usize, height: usize) {
+    let len = width.max(height);
+    let mut f = vec![0.0; len];
+    let mut v = vec![0; len];
+    let mut z = vec![0.0; len + 1];
+
+    for x in 0..width {
+        edt_1d(grid, x, width, height, &mut f, &mut v, &mut z);
+    }
+    for y in 0..height {
+        edt_1d(grid, y * width, 1, width, &mut f, &mut v, &mut z);
+    }
+}
+
+fn edt_1d(
+    grid: &mut [f32],
+    offset: usize,
+    stride: usize,
+    length: usize,
+    f: &mut [f32],
+    v: &mut [usize],
+    z: &mut [f32],
+) {
+    v[0] = 0;
+    z[0] = -INF;
+    z[1] = INF;
+    f[0] = grid[offset];
+
+    let mut k = 0;
+    for q in 1..length {
+        f[q] = grid[offset + q * stride];
+        let mut s;
+        loop {
+            let r = v[k];
+            s = (f[q] - f[r] + (q * q) as f32 - (r * r) as f32) / (q - r) as f32 / 2.0;
+            if s <= z[k] && k > 0 {
+                k -= 1;
+                continue;
+            }
+            break;
+        }
+        if s > z[k] {
+            k += 1;
+        }
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = INF;
+    }
+
+    let mut k = 0;
+    for q in 0..length {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let r = v[k];
+        let qr = q as f32 - r as f32;
+        grid[offset + q * stride] = f[r] + qr * qr;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Coverage of a `size` x `size` bitmap with a square from `min` to `max`.
+    fn square(size: usize, min: usize, max: usize) -> Vec<f32> {
+        (0..size * size)
+            .map(|i| {
+                let (x, y) = (i % size, i / size);
+                let inside = (min..max).contains(&x) && (min..max).contains(&y);
+                if inside {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn square_distances() {
+        let size = 16;
+        let field = from_coverage(&square(size, 4, 12), size, size, 4.0);
+        let at = |x: usize, y: usize| field[y * size + x];
+
+        // The edge is half way between the last pixel inside and the first outside.
+        assert!(at(4, 8) > 128 && at(3, 8) < 128);
+        assert_eq!(at(4, 8) - 128, 127 - at(3, 8));
+        // Values fall off with the distance to the edge until the spread.
+        assert!(at(8, 8) > at(5, 8) && at(5, 8) > at(4, 8));
+        assert!(at(3, 8) > at(2, 8) && at(2, 8) > at(1, 8));
+        assert_eq!(at(8, 8), 255);
+        assert_eq!(at(0, 0), 0);
+        // Symmetric around the center of the square.
+        assert_eq!(at(4, 8), at(11, 8));
+        assert_eq!(at(8, 4), at(8, 11));
+    }
+
+    #[test]
+    fn partial_coverage_moves_the_edge() {
+        let (width, height) = (9, 1);
+        let mut coverage = vec![0.0; width];
+        coverage[..4].fill(1.0);
+        coverage[4] = 0.5;
+        let field = from_coverage(&coverage, width, height, 2.0);
+
+        // Half covered pixels lie on the edge.
+        assert!(field[4].abs_diff(128) <= 1);
+        assert!(field[3] > field[4] && field[4] > field[5]);
+    }
+
+    #[test]
+    fn empty_and_full_coverage() {
+        let field = from_coverage(&[0.0; 16], 4, 4, 2.0);
+        assert!(field.iter().all(|&d| d == 0));
+        let field = from_coverage(&[1.0; 16], 4, 4, 2.0);
+        assert!(field.iter().all(|&d| d == 255));
+        assert!(from_coverage(&[], 0, 0, 2.0).is_empty());
+    }
+}
@@ -5,68 +5,523 @@ struct VertexInput {
     @location(2) tex_top_left: vec2<f32>,
     @location(3) tex_bottom_right: vec2<f32>,
     @location(4) color: vec4<f32>,
+    // Page, section transform with the billboard and effects, the last two index
+    // into the parameter texture.
+    @location(5) indices: vec3<u32>,
 }
 
 struct Matrix {
     v: mat4x4<f32>,
+    // Size of the render target in physical pixels which quads are snapped to,
+    // zero without pixel snapping.
+    snap_viewport: vec2<f32>,
 }
 
 @group(0) @binding(0)
 var<uniform> ortho: Matrix;
 
+@group(1) @binding(0)
+var texture: texture_2d_array<f32>;
+@group(1) @binding(1)
+var tex_sampler: sampler;
+@group(1) @binding(2)
+var color_texture: texture_2d_array<f32>;
+
+// Section transforms and text effects shared by many quads, a texel index of
+// zero stands for none.
+@group(2) @binding(0)
+var params: texture_2d<f32>;
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_pos: vec2<f32>,
     @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) page: u32,
+    @location(3) @interpolate(flat) outline_color: vec4<f32>,
+    @location(4) @interpolate(flat) outline_width: f32,
+    @location(5) @interpolate(flat) tex_bounds: vec4<f32>,
+    @location(6) @interpolate(flat) shadow_color: vec4<f32>,
+    @location(7) @interpolate(flat) shadow_offset: vec2<f32>,
+    @location(8) @interpolate(flat) shadow_blur: f32,
+    // Index of the page in the cache texture array.
+    @location(9) @interpolate(flat) page_index: i32,
 }
 
-@vertex
-fn vs_main(in: VertexInput) -> VertexOutput {
-    var out: VertexOutput;
+fn param(index: u32) -> vec4<f32> {
+    var width: u32 = u32(textureDimensions(params).x);
+    return textureLoad(params, vec2<i32>(i32(index % width), i32(index / width)), 0);
+}
+
+struct Quad {
+    min: vec2<f32>,
+    max: vec2<f32>,
+    tex_min: vec2<f32>,
+    tex_max: vec2<f32>,
+}
 
-    var pos: vec2<f32>;
-    var left: f32 = in.top_left.x;
-    var right: f32 = in.bottom_right.x;
-    var top: f32 = in.top_left.y;
-    var bottom: f32 = in.bottom_right.y;
+// Part of the `quad` within the `bounds`, keeping the texture aspect.
+fn clip(quad: Quad, bounds: vec4<f32>) -> Quad {
+    var clipped: Quad;
+    clipped.min = max(quad.min, bounds.xy);
+    clipped.max = min(quad.max, bounds.zw);
 
-    switch (in.vertex_index) {
+    var tex_per_pixel: vec2<f32> = (quad.tex_max - quad.tex_min) / (quad.max - quad.min);
+    clipped.tex_min = quad.tex_min + (clipped.min - quad.min) * tex_per_pixel;
+    clipped.tex_max = quad.tex_max - (quad.max - clipped.max) * tex_per_pixel;
+    return clipped;
+}
+
+struct Corner {
+    pos: vec2<f32>,
+    tex_pos: vec2<f32>,
+}
+
+// Corner of the `quad` at the `vertex_index` of its triangle strip.
+fn quad_corner(quad: Quad, vertex_index: u32) -> Corner {
+    var corner: Corner;
+    var left: f32 = quad.min.x;
+    var right: f32 = quad.max.x;
+    var top: f32 = quad.min.y;
+    var bottom: f32 = quad.max.y;
+
+    switch (vertex_index) {
         case 0u: {
-            pos = vec2<f32>(left, top);
-            out.tex_pos = in.tex_top_left;
+            corner.pos = vec2<f32>(left, top);
+            corner.tex_pos = quad.tex_min;
             break;
         }
         case 1u: {
-            pos = vec2<f32>(right, top);
-            out.tex_pos = vec2<f32>(in.tex_bottom_right.x, in.tex_top_left.y);
+            corner.pos = vec2<f32>(right, top);
+            corner.tex_pos = vec2<f32>(quad.tex_max.x, quad.tex_min.y);
             break;
         }
         case 2u: {
-            pos = vec2<f32>(left, bottom);
-            out.tex_pos = vec2<f32>(in.tex_top_left.x, in.tex_bottom_right.y);
+            corner.pos = vec2<f32>(left, bottom);
+            corner.tex_pos = vec2<f32>(quad.tex_min.x, quad.tex_max.y);
             break;
         }
         case 3u: {
-            pos = vec2<f32>(right, bottom);
-            out.tex_pos = in.tex_bottom_right;
+            corner.pos = vec2<f32>(right, bottom);
+            corner.tex_pos = quad.tex_max;
             break;
         }
         default: {}
     }
+    return corner;
+}
+
+fn input_quad(in: VertexInput) -> Quad {
+    return Quad(in.top_left.xy, in.bottom_right, in.tex_top_left, in.tex_bottom_right);
+}
+
+// Transform of the section at `index` in the parameters.
+fn section_transform(index: u32) -> mat4x4<f32> {
+    if (index == 0u) {
+        return mat4x4<f32>(
+            vec4<f32>(1.0, 0.0, 0.0, 0.0),
+            vec4<f32>(0.0, 1.0, 0.0, 0.0),
+            vec4<f32>(0.0, 0.0, 1.0, 0.0),
+            vec4<f32>(0.0, 0.0, 0.0, 1.0)
+        );
+    }
+    return mat4x4<f32>(param(index), param(index + 1u), param(index + 2u), param(index + 3u));
+}
+
+// Clip space position of the point `pos` of a quad at the depth `z`, with the
+// section transform and billboard packed into `section`.
+fn project(section: u32, pos: vec2<f32>, z: f32) -> vec4<f32> {
+    var transform: mat4x4<f32> = section_transform(section >> 2u);
+    var world: vec4<f32> = transform * vec4<f32>(pos, z, 1.0);
+
+    var billboard: u32 = section & 3u;
+    if (billboard != 0u) {
+        // Rows of the view-projection matrix point right and up from the camera.
+        var right: vec3<f32> = normalize(vec3<f32>(ortho.v[0].x, ortho.v[1].x, ortho.v[2].x));
+        var up: vec3<f32> = normalize(vec3<f32>(ortho.v[0].y, ortho.v[1].y, ortho.v[2].y));
+        if (billboard == 2u) {
+            // Turn only around the up axis of the transform, layout y points down.
+            up = -normalize(transform[1].xyz);
+            right = normalize(right - up * dot(right, up));
+        }
+        var scale: vec2<f32> = vec2<f32>(length(transform[0].xyz), length(transform[1].xyz));
+        world = vec4<f32>(
+            transform[3].xyz + right * pos.x * scale.x - up * pos.y * scale.y,
+            1.0
+        );
+    }
+
+    return ortho.v * world;
+}
+
+// Vertex of a glyph quad, with texture coordinates in texels of a texture of
+// `tex_size`.
+fn glyph_vertex(in: VertexInput, tex_size: vec2<f32>) -> VertexOutput {
+    var out: VertexOutput;
+
+    var quad: Quad = input_quad(in);
+    out.tex_bounds = vec4<f32>(quad.tex_min / tex_size, quad.tex_max / tex_size);
+    var effects: u32 = in.indices.z;
+    if (effects != 0u) {
+        out.outline_color = param(effects);
+        out.shadow_color = param(effects + 1u);
+        var sizes: vec4<f32> = param(effects + 2u);
+        out.outline_width = sizes.x;
+        out.shadow_blur = sizes.y;
+        out.shadow_offset = sizes.zw / tex_size;
+
+        // Makes room for the effects around the glyph.
+        var grow: vec4<f32> = param(effects + 3u);
+        quad.min -= grow.x;
+        quad.max += grow.x;
+        quad.tex_min -= grow.yz;
+        quad.tex_max += grow.yz;
+        quad = clip(quad, param(effects + 4u));
+    }
+
+    var corner: Corner = quad_corner(quad, in.vertex_index);
+    out.tex_pos = corner.tex_pos / tex_size;
+    out.clip_position = project(in.indices.y, corner.pos, in.top_left.z);
+
+    var viewport: vec2<f32> = ortho.snap_viewport;
+    if (viewport.x > 0.0) {
+        // Moves the whole quad by the distance of its top left corner to the
+        // closest pixel edge, keeping its size.
+        var origin: vec4<f32> = project(in.indices.y, quad.min, in.top_left.z);
+        var pixel: vec2<f32> = (origin.xy / origin.w * 0.5 + 0.5) * viewport;
+        var offset: vec2<f32> = (round(pixel) - pixel) / viewport * 2.0;
+        out.clip_position = vec4<f32>(
+            out.clip_position.xy + offset * out.clip_position.w,
+            out.clip_position.zw
+        );
+    }
 
-    out.clip_position = ortho.v * vec4<f32>(pos, in.top_left.z, 1.0);
     out.color = in.color;
+    // Kind of the page in the lowest bit, its index in the others.
+    out.page = in.indices.x & 1u;
+    out.page_index = i32(in.indices.x >> 1u);
     return out;
 }
 
-@group(0) @binding(1)
-var texture: texture_2d<f32>;
-@group(0) @binding(2)
-var tex_sampler: sampler;
+// Size of the cache texture of the `page` kind.
+fn cache_size(page: u32) -> vec2<f32> {
+    if (page == 1u) {
+        return vec2<f32>(textureDimensions(color_texture).xy);
+    }
+    return vec2<f32>(textureDimensions(texture).xy);
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    return glyph_vertex(in, cache_size(in.indices.x & 1u));
+}
+
+// Outlines of the debug overlay, placed like the text. Their texture coordinates
+// go from 0 to 1.
+@vertex
+fn vs_debug_outline(in: VertexInput) -> VertexOutput {
+    return glyph_vertex(in, vec2<f32>(1.0));
+}
+
+// Quads of the debug overlay which are given in clip space, independent of the
+// render matrix.
+@vertex
+fn vs_screen(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    var corner: Corner = quad_corner(input_quad(in), in.vertex_index);
+    out.clip_position = vec4<f32>(corner.pos, 0.0, 1.0);
+    out.tex_pos = corner.tex_pos;
+    out.color = in.color;
+    out.page = in.indices.x & 1u;
+    out.page_index = i32(in.indices.x >> 1u);
+    return out;
+}
+
+// Color glyphs are stored premultiplied and keep their own colors,
+// only the alpha of the text color applies. The texture coordinate derivatives
+// choose the mip level, they have to be taken outside of branches.
+fn color_glyph(in: VertexOutput, grad_x: vec2<f32>, grad_y: vec2<f32>) -> vec4<f32> {
+    var color: vec4<f32> =
+        textureSampleGrad(color_texture, tex_sampler, in.tex_pos, in.page_index, grad_x, grad_y);
+    var rgb: vec3<f32> = color.rgb / max(color.a, 0.0001);
+
+    return vec4<f32>(rgb, color.a * in.color.a);
+}
+
+// Premultiplied `color` covering `coverage` of the pixel.
+fn layer(color: vec4<f32>, coverage: f32) -> vec4<f32> {
+    var alpha: f32 = color.a * coverage;
+    return vec4<f32>(color.rgb * alpha, alpha);
+}
+
+fn over(top: vec4<f32>, bottom: vec4<f32>) -> vec4<f32> {
+    return top + bottom * (1.0 - top.a);
+}
+
+// Draws the fill over its outline over its shadow.
+fn composite(in: VertexOutput, fill: f32, outline: f32, shadow: f32) -> vec4<f32> {
+    var color: vec4<f32> = over(
+        layer(in.color, fill),
+        over(layer(in.outline_color, outline), layer(in.shadow_color, shadow))
+    );
+
+    return vec4<f32>(color.rgb / max(color.a, 0.0001), color.a);
+}
+
+fn outside(pos: vec2<f32>, bounds: vec4<f32>) -> bool {
+    return any(pos < bounds.xy) || any(pos > bounds.zw);
+}
+
+// The quads of glyphs with effects reach into neighbouring glyphs, which must
+// not be sampled.
+fn sample_glyph(pos: vec2<f32>, bounds: vec4<f32>, index: i32) -> vec4<f32> {
+    if (outside(pos, bounds)) {
+        return vec4<f32>(0.0);
+    }
+    return textureSampleLevel(texture, tex_sampler, pos, index, 0.0);
+}
+
+// Offset of `radius` texels in the `i`-th of 12 directions, every other ring
+// is rotated by half a step to fill the gaps.
+fn ring_offset(ring: i32, i: i32, radius: f32) -> vec2<f32> {
+    var texel: vec2<f32> = 1.0 / vec2<f32>(textureDimensions(texture));
+    var angle: f32 = (f32(i) + f32(ring % 2) * 0.5) * 0.5235988;
+    return vec2<f32>(cos(angle), sin(angle)) * radius * texel;
+}
+
+// Coverage in the `channel` of the cache texture.
+fn glyph_coverage(pos: vec2<f32>, bounds: vec4<f32>, index: i32, channel: i32) -> f32 {
+    var sample: vec4<f32> = sample_glyph(pos, bounds, index);
+    return sample[channel];
+}
+
+// Highest coverage within `width` texels, the glyph dilated by the outline.
+fn dilate(pos: vec2<f32>, width: f32, bounds: vec4<f32>, index: i32, channel: i32) -> f32 {
+    var rings: i32 = i32(clamp(ceil(width), 1.0, 4.0));
+    var result: f32 = glyph_coverage(pos, bounds, index, channel);
+
+    for (var ring: i32 = 1; ring <= rings; ring++) {
+        var radius: f32 = width * f32(ring) / f32(rings);
+        for (var i: i32 = 0; i < 12; i++) {
+            var offset: vec2<f32> = ring_offset(ring, i, radius);
+            result = max(result, glyph_coverage(pos + offset, bounds, index, channel));
+        }
+    }
+    return result;
+}
+
+// Coverage averaged over `radius` texels, weighted towards the center.
+fn blur(pos: vec2<f32>, radius: f32, bounds: vec4<f32>, index: i32, channel: i32) -> f32 {
+    var sum: f32 = glyph_coverage(pos, bounds, index, channel);
+    var weight: f32 = 1.0;
+
+    if (radius > 0.0) {
+        for (var ring: i32 = 1; ring <= 3; ring++) {
+            var ring_weight: f32 = 1.0 - f32(ring) / 4.0;
+            for (var i: i32 = 0; i < 12; i++) {
+                var offset: vec2<f32> = ring_offset(ring, i, radius * f32(ring) / 3.0);
+                sum += glyph_coverage(pos + offset, bounds, index, channel) * ring_weight;
+                weight += ring_weight;
+            }
+        }
+    }
+    return sum / weight;
+}
+
+// Glyph with its effects, drawn with the coverage in the `channel` of the cache
+// texture.
+fn coverage_glyph(in: VertexOutput, channel: i32) -> vec4<f32> {
+    var grad_x: vec2<f32> = dpdx(in.tex_pos);
+    var grad_y: vec2<f32> = dpdy(in.tex_pos);
+    var sample: vec4<f32> = textureSample(texture, tex_sampler, in.tex_pos, in.page_index);
+
+    if (in.page == 1u) {
+        return color_glyph(in, grad_x, grad_y);
+    }
+    var alpha: f32 = select(sample[channel], 0.0, outside(in.tex_pos, in.tex_bounds));
+
+    var outline: f32 = 0.0;
+    if (in.outline_width > 0.0) {
+        outline = dilate(in.tex_pos, in.outline_width, in.tex_bounds, in.page_index, channel);
+    }
+    var shadow: f32 = 0.0;
+    if (in.shadow_color.a > 0.0) {
+        shadow = blur(in.tex_pos - in.shadow_offset, in.shadow_blur, in.tex_bounds, in.page_index, channel);
+    }
+    return composite(in, alpha, outline, shadow);
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var alpha: f32 = textureSample(texture, tex_sampler, in.tex_pos).r;
+    return coverage_glyph(in, 0);
+}
+
+// Subpixel glyphs on targets which can't blend each color channel on its own,
+// drawn with the coverage of the whole pixel in alpha.
+@fragment
+fn fs_subpixel_coverage(in: VertexOutput) -> @location(0) vec4<f32> {
+    return coverage_glyph(in, 3);
+}
+
+// Subpixel glyph with its effects, premultiplied and with an alpha for every
+// color channel.
+struct Subpixel {
+    color: vec3<f32>,
+    alpha: vec3<f32>,
+}
+
+fn subpixel(in: VertexOutput) -> Subpixel {
+    var grad_x: vec2<f32> = dpdx(in.tex_pos);
+    var grad_y: vec2<f32> = dpdy(in.tex_pos);
+    var sample: vec4<f32> = textureSample(texture, tex_sampler, in.tex_pos, in.page_index);
+    var out: Subpixel;
+
+    if (in.page == 1u) {
+        var color: vec4<f32> = color_glyph(in, grad_x, grad_y);
+        out.color = color.rgb * color.a;
+        out.alpha = vec3<f32>(color.a);
+        return out;
+    }
+    if (outside(in.tex_pos, in.tex_bounds)) {
+        sample = vec4<f32>(0.0);
+    }
+
+    // Effects don't need subpixel precision, they use the pixel coverage in alpha.
+    var outline: f32 = 0.0;
+    if (in.outline_width > 0.0) {
+        outline = dilate(in.tex_pos, in.outline_width, in.tex_bounds, in.page_index, 3);
+    }
+    var shadow: f32 = 0.0;
+    if (in.shadow_color.a > 0.0) {
+        shadow = blur(in.tex_pos - in.shadow_offset, in.shadow_blur, in.tex_bounds, in.page_index, 3);
+    }
+    var below: vec4<f32> = over(layer(in.outline_color, outline), layer(in.shadow_color, shadow));
+
+    var fill_alpha: vec3<f32> = in.color.a * sample.rgb;
+    out.color = in.color.rgb * fill_alpha + below.rgb * (1.0 - fill_alpha);
+    out.alpha = fill_alpha + below.a * (1.0 - fill_alpha);
+    return out;
+}
+
+// First pass of subpixel glyphs, darkens every color channel of the target by
+// its coverage.
+@fragment
+fn fs_subpixel_darken(in: VertexOutput) -> @location(0) vec4<f32> {
+    var glyph: Subpixel = subpixel(in);
+    return vec4<f32>(glyph.alpha, max(glyph.alpha.r, max(glyph.alpha.g, glyph.alpha.b)));
+}
+
+// Second pass of subpixel glyphs, adds their colors.
+@fragment
+fn fs_subpixel_colors(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(subpixel(in).color, 0.0);
+}
+
+// Antialiased coverage of the distance field above `edge`, fading in over
+// `softness` on both sides of it.
+fn distance_coverage(distance: f32, edge: f32, softness: f32) -> f32 {
+    var w: f32 = clamp(softness, 0.0001, 0.5);
+    return smoothstep(edge - w, edge + w, distance);
+}
+
+@fragment
+fn fs_sdf(in: VertexOutput) -> @location(0) vec4<f32> {
+    var grad_x: vec2<f32> = dpdx(in.tex_pos);
+    var grad_y: vec2<f32> = dpdy(in.tex_pos);
+    var distance: f32 = textureSample(texture, tex_sampler, in.tex_pos, in.page_index).r;
+    // About one screen pixel at any scale.
+    var softness: f32 = fwidth(distance) * 0.7;
+
+    if (in.page == 1u) {
+        return color_glyph(in, grad_x, grad_y);
+    }
+    distance = select(distance, 0.0, outside(in.tex_pos, in.tex_bounds));
+    var alpha: f32 = distance_coverage(distance, 0.5, softness);
+
+    // Effect sizes are in distance field units.
+    var outline: f32 = 0.0;
+    if (in.outline_width > 0.0) {
+        outline = distance_coverage(distance, 0.5 - in.outline_width, softness);
+    }
+    var shadow: f32 = 0.0;
+    if (in.shadow_color.a > 0.0) {
+        var shadow_distance: f32 =
+            sample_glyph(in.tex_pos - in.shadow_offset, in.tex_bounds, in.page_index).r;
+        shadow = distance_coverage(shadow_distance, 0.5, max(softness, in.shadow_blur));
+    }
+    return composite(in, alpha, outline, shadow);
+}
+
+fn median(r: f32, g: f32, b: f32) -> f32 {
+    return max(min(r, g), min(max(r, g), b));
+}
+
+@fragment
+fn fs_msdf(in: VertexOutput) -> @location(0) vec4<f32> {
+    var grad_x: vec2<f32> = dpdx(in.tex_pos);
+    var grad_y: vec2<f32> = dpdy(in.tex_pos);
+    var sample: vec4<f32> = textureSample(texture, tex_sampler, in.tex_pos, in.page_index);
+    var distance: f32 = median(sample.r, sample.g, sample.b);
+    var softness: f32 = fwidth(distance) * 0.7;
+    // Multi-channel distances are only exact near the edge, effects use the true
+    // distance in alpha.
+    var true_softness: f32 = fwidth(sample.a) * 0.7;
+
+    if (in.page == 1u) {
+        return color_glyph(in, grad_x, grad_y);
+    }
+    if (outside(in.tex_pos, in.tex_bounds)) {
+        sample = vec4<f32>(0.0);
+        distance = 0.0;
+    }
+    var alpha: f32 = distance_coverage(distance, 0.5, softness);
 
-    return vec4<f32>(in.color.rgb, in.color.a * alpha);
+    var outline: f32 = 0.0;
+    if (in.outline_width > 0.0) {
+        outline = distance_coverage(sample.a, 0.5 - in.outline_width, true_softness);
+    }
+    var shadow: f32 = 0.0;
+    if (in.shadow_color.a > 0.0) {
+        var shadow_distance: f32 =
+            sample_glyph(in.tex_pos - in.shadow_offset, in.tex_bounds, in.page_index).a;
+        shadow = distance_coverage(shadow_distance, 0.5, max(true_softness, in.shadow_blur));
+    }
+    return composite(in, alpha, outline, shadow);
+}
+
+// Outline of a debug overlay quad, about a pixel wide at any transform. Its
+// texture coordinates go from 0 to 1.
+@fragment
+fn fs_debug_outline(in: VertexOutput) -> @location(0) vec4<f32> {
+    var width: vec2<f32> = fwidth(in.tex_pos);
+
+    if (all(in.tex_pos > width) && all(in.tex_pos < 1.0 - width)) {
+        discard;
+    }
+    return in.color;
+}
+
+// Whole page of a cache texture, color glyphs are stored premultiplied.
+fn debug_page(in: VertexOutput) -> vec4<f32> {
+    if (in.page == 1u) {
+        return textureSampleLevel(color_texture, tex_sampler, in.tex_pos, in.page_index, 0.0);
+    }
+    return textureSampleLevel(texture, tex_sampler, in.tex_pos, in.page_index, 0.0);
+}
+
+// Page of a cache texture with all color channels, on black.
+@fragment
+fn fs_debug_atlas(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(debug_page(in).rgb, 1.0);
+}
+
+// Page of a single channel cache texture in gray, on black.
+@fragment
+fn fs_debug_atlas_gray(in: VertexOutput) -> @location(0) vec4<f32> {
+    var sample: vec4<f32> = debug_page(in);
+
+    if (in.page == 1u) {
+        return vec4<f32>(sample.rgb, 1.0);
+    }
+    return vec4<f32>(sample.rrr, 1.0);
 }
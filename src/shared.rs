@@ -0,0 +1,661 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
+
+use crate::{
+    atlas::{Atlas, Page},
+    cache::{Cache, CacheSettings},
+    error::BrushError,
+    headless::read_texture,
+    persist::{font_hash, Decoder, Encoder, MAGIC, VERSION},
+    pipeline::Pipeline,
+    RenderMode,
+};
+
+/// Glyph cache which several [`TextBrush`](crate::TextBrush)es draw from, so
+/// glyphs used by more of them are rasterized and uploaded only once.
+///
+/// Built with [`AtlasBuilder`] and given to every brush with
+/// [`BrushBuilder::with_atlas()`](crate::BrushBuilder::with_atlas). Cloning it
+/// only clones the handle.
+///
+/// Glyphs are shared between brushes using the same font data. When a brush
+/// runs out of space and has to clear or resize the cache texture, the other
+/// brushes draw nothing until their next [`queue()`](crate::TextBrush::queue),
+/// instead of drawing glyphs which aren't there anymore. Queue all brushes
+/// before drawing any of them, and give the atlas a big enough
+/// [`initial_cache_size()`](AtlasBuilder::initial_cache_size), to avoid it.
+///
+/// # Example
+/// ```no_run
+/// use wgpu_text::{AtlasBuilder, BrushBuilder};
+/// # let (device, config): (wgpu::Device, wgpu::SurfaceConfiguration) = todo!();
+/// # let font: &[u8] = &[];
+///
+/// let atlas = AtlasBuilder::new()
+///     .initial_cache_size((1024, 1024))
+///     .build(&device, config.format);
+/// let hud = BrushBuilder::using_font_bytes(font)
+///     .unwrap()
+///     .with_atlas(atlas.clone())
+///     .build(&device, config.width, config.height, config.format);
+/// let labels = BrushBuilder::using_font_bytes(font)
+///     .unwrap()
+///     .with_atlas(atlas)
+///     .build(&device, config.width, config.height, config.format);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedAtlas {
+    state: Arc<Mutex<AtlasState>>,
+    epoch: Arc<AtomicU64>,
+}
+
+impl SharedAtlas {
+    /// Returns the [`RenderMode`] glyphs are rasterized with.
+    pub fn render_mode(&self) -> RenderMode {
+        self.lock().atlas.render_mode()
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, AtlasState> {
+        // The state is left consistent by every operation which can panic.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Saves the cached glyphs and the contents of the cache textures, to be
+    /// restored later with [`AtlasBuilder::with_cache_data()`].
+    ///
+    /// Reads the cache textures back from the GPU and blocks until it is done.
+    pub fn export(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<u8>, BrushError> {
+        self.lock().export(device, queue)
+    }
+
+    /// Incremented whenever cached glyphs are evicted.
+    #[inline]
+    pub(crate) fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+}
+
+/// Cached glyphs and the textures they are stored in.
+#[derive(Debug)]
+pub(crate) struct AtlasState {
+    pub atlas: Atlas,
+    pub cache: Cache,
+    epoch: Arc<AtomicU64>,
+    /// Atlas font ids of the font data hashes and lengths.
+    fonts: HashMap<(u64, usize), usize>,
+    font_count: usize,
+    /// Restored pages waiting to be uploaded into the cache textures.
+    pending: Vec<(Page, Vec<u8>)>,
+    memory_budget: u64,
+    on_event: Option<EventHandler>,
+}
+
+impl AtlasState {
+    /// Returns the id of the font with the `data` inside the atlas, the same for
+    /// all brushes using it. Fonts without data are never shared.
+    pub fn font_id(&mut self, data: &[u8]) -> usize {
+        let next = self.font_count;
+        let id = if data.is_empty() {
+            next
+        } else {
+            *self
+                .fonts
+                .entry((font_hash(data), data.len()))
+                .or_insert(next)
+        };
+        if id == next {
+            self.font_count += 1;
+        }
+        id
+    }
+
+    #[inline]
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// Uploads pages restored from cache data, before anything is drawn from them.
+    /// Returns the number of uploaded bytes. They are staged until the next
+    /// [`Cache::flush_uploads()`].
+    pub fn upload_pending(&mut self) -> u64 {
+        let mut bytes = 0;
+        for (page, data) in self.pending.drain(..) {
+            let pages = self.atlas.page_count(page);
+            self.cache.write_pages(page, pages, &data);
+            bytes += data.len() as u64;
+        }
+        bytes
+    }
+
+    /// See [`SharedAtlas::export()`].
+    pub fn export(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<u8>, BrushError> {
+        self.upload_pending();
+        self.cache.flush_uploads(device, queue);
+
+        let mut out = Encoder::default();
+        out.data.extend_from_slice(MAGIC);
+        out.u32(VERSION);
+
+        let mut fonts: Vec<_> = self.fonts.iter().map(|(key, id)| (*id, *key)).collect();
+        fonts.sort_unstable();
+        out.u32(fonts.len() as u32);
+        for (_, (hash, len)) in &fonts {
+            out.u64(*hash);
+            out.u64(*len as u64);
+        }
+        self.atlas.encode(&mut out, |font_id| {
+            let index = fonts.iter().position(|(id, _)| *id == font_id)?;
+            Some(index as u32)
+        });
+
+        for page in [Page::Main, Page::Color] {
+            let data = if page == Page::Color && !self.atlas.color_glyphs() {
+                Vec::new()
+            } else {
+                let encoder =
+                    device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("wgpu-text Cache Export Encoder"),
+                    });
+                let texture = self.cache.texture(page);
+                let mut data = read_texture(device, queue, encoder, texture)?;
+                // Without the spare layer.
+                let size = self.atlas.texture_size(page);
+                let texel_bytes = texture.format().block_size(None).unwrap_or(4);
+                data.truncate(
+                    (size.width * size.height * size.depth_or_array_layers * texel_bytes)
+                        as usize,
+                );
+                data
+            };
+            out.bytes(&data);
+        }
+        Ok(out.data)
+    }
+
+    /// Forgets all cached glyphs.
+    pub fn clear(&mut self) {
+        let glyphs = self.atlas.glyph_count();
+        self.pending.clear();
+        self.cache.discard_uploads();
+        self.atlas.clear();
+        self.epoch.fetch_add(1, Ordering::AcqRel);
+        self.emit(AtlasEvent::Cleared { glyphs });
+    }
+
+    /// Makes room for more glyphs of the `page` kind, keeping all cached ones.
+    /// Doubles the size of its pages up to the largest texture size of the
+    /// `device`, then adds more pages. Fails if the cache textures would take
+    /// more than the memory budget.
+    pub fn grow(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        page: Page,
+    ) -> Result<(), BrushError> {
+        let limits = device.limits();
+        let max_image_dimension = limits.max_texture_dimension_2d;
+        let old = self.atlas.texture_size(page);
+        let mut size = old;
+        if old.width < max_image_dimension || old.height < max_image_dimension {
+            size.width = (old.width * 2).min(max_image_dimension);
+            size.height = (old.height * 2).min(max_image_dimension);
+        } else if self.atlas.last_page_empty(page)
+            || Cache::layer_count(wgpu::Extent3d {
+                depth_or_array_layers: old.depth_or_array_layers + 1,
+                ..old
+            }) > limits.max_texture_array_layers
+        {
+            // Another page won't help if the glyph doesn't fit into an empty one.
+            return Err(BrushError::TooBigCacheTexture(max_image_dimension));
+        } else {
+            size.depth_or_array_layers += 1;
+        }
+        if self.memory_size(page, size) > self.memory_budget {
+            return Err(BrushError::CacheBudgetExceeded(self.memory_budget));
+        }
+
+        // Restored pages are staged with the page count they were saved with.
+        self.upload_pending();
+        self.cache.grow_texture(device, queue, page, size);
+        if size.depth_or_array_layers > old.depth_or_array_layers {
+            self.atlas.add_page(page);
+            self.emit(AtlasEvent::PageAdded {
+                page,
+                pages: size.depth_or_array_layers,
+            });
+        } else {
+            self.atlas.grow(page, (size.width, size.height));
+            // Texture coordinates of the vertices of other brushes are outdated.
+            self.epoch.fetch_add(1, Ordering::AcqRel);
+            self.emit(AtlasEvent::Resized {
+                page,
+                old: (old.width, old.height),
+                new: (size.width, size.height),
+            });
+        }
+        Ok(())
+    }
+
+    /// Bytes the cache textures take with the texture of the `page` kind having
+    /// the `size`.
+    fn memory_size(&self, page: Page, size: wgpu::Extent3d) -> u64 {
+        [Page::Main, Page::Color]
+            .into_iter()
+            .map(|kind| {
+                let size = if kind == page {
+                    size
+                } else {
+                    self.atlas.texture_size(kind)
+                };
+                let texture = self.cache.texture(kind);
+                let texel_bytes = texture.format().block_size(None).unwrap_or(4);
+                let texels: u64 = (0..texture.mip_level_count())
+                    .map(|mip_level| {
+                        let size =
+                            size.mip_level_size(mip_level, wgpu::TextureDimension::D2);
+                        size.width as u64 * size.height as u64
+                    })
+                    .sum();
+                texels * Cache::layer_count(size) as u64 * texel_bytes as u64
+            })
+            .sum()
+    }
+
+    fn emit(&self, event: AtlasEvent) {
+        if let Some(EventHandler(handler)) = &self.on_event {
+            handler(event);
+        }
+    }
+}
+
+/// Changes of the cache textures which make drawing slower for a frame, see
+/// [`AtlasBuilder::on_event()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AtlasEvent {
+    /// New glyphs didn't fit, so the cache texture of the `page` grew from the
+    /// `old` to the `new` size. Cached glyphs were copied into the new texture.
+    Resized {
+        page: Page,
+        old: (u32, u32),
+        new: (u32, u32),
+    },
+    /// New glyphs didn't fit into a cache texture of the largest size, so a page
+    /// was added to the cache texture of the `page` kind, which has `pages` now.
+    PageAdded { page: Page, pages: u32 },
+    /// The cache textures can't grow anymore without exceeding the memory budget,
+    /// so all `glyphs` were evicted to make room for the ones of the current frame.
+    Cleared { glyphs: usize },
+}
+
+/// Callback receiving [`AtlasEvent`]s.
+#[derive(Clone)]
+pub(crate) struct EventHandler(pub Arc<dyn Fn(AtlasEvent) + Send + Sync>);
+
+impl Debug for EventHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EventHandler(..)")
+    }
+}
+
+/// Builder for [`SharedAtlas`].
+#[derive(Debug, Clone)]
+pub struct AtlasBuilder {
+    render_mode: RenderMode,
+    color_glyphs: bool,
+    dimensions: (u32, u32),
+    scale_tolerance: f32,
+    position_tolerance: f32,
+    cache_data: Option<Vec<u8>>,
+    memory_budget: u64,
+    on_event: Option<EventHandler>,
+    cache: CacheSettings,
+}
+
+impl AtlasBuilder {
+    /// Creates an [`AtlasBuilder`] with the same defaults as
+    /// [`BrushBuilder`](crate::BrushBuilder).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Provide the [`RenderMode`] which decides how glyphs are rasterized into
+    /// the cache texture and drawn.
+    ///
+    /// Defaults to [`RenderMode::Coverage`].
+    pub fn with_render_mode(mut self, render_mode: RenderMode) -> Self {
+        self.render_mode = render_mode;
+        self
+    }
+
+    /// Enables drawing color glyphs, see
+    /// [`BrushBuilder::with_color_glyphs()`](crate::BrushBuilder::with_color_glyphs).
+    ///
+    /// Defaults to `true`.
+    pub fn with_color_glyphs(mut self, color_glyphs: bool) -> Self {
+        self.color_glyphs = color_glyphs;
+        self
+    }
+
+    /// Initial size of the cache texture, grown when it runs out of space.
+    ///
+    /// Defaults to `(256, 256)`.
+    pub fn initial_cache_size(mut self, size: (u32, u32)) -> Self {
+        self.dimensions = size;
+        self
+    }
+
+    /// Sets the scale tolerance of the cache, glyphs of scales closer than it
+    /// share the cached raster.
+    ///
+    /// Defaults to `0.1`.
+    pub fn draw_cache_scale_tolerance(mut self, tolerance: f32) -> Self {
+        self.scale_tolerance = tolerance;
+        self
+    }
+
+    /// Sets the subpixel position tolerance of the cache, glyphs positioned
+    /// closer than it share the cached raster.
+    ///
+    /// Defaults to `0.1`.
+    pub fn draw_cache_position_tolerance(mut self, tolerance: f32) -> Self {
+        self.position_tolerance = tolerance;
+        self
+    }
+
+    /// Largest number of bytes the cache textures may take together. Once they
+    /// reach the largest texture size of the device, more pages are added for new
+    /// glyphs until they would exceed it. Then glyphs which aren't needed for the
+    /// current frame are evicted, and if that isn't enough,
+    /// [`BrushError::CacheBudgetExceeded`] is returned.
+    ///
+    /// Defaults to 256 MiB.
+    pub fn cache_memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// Filters of the cache texture sampler when glyphs are drawn bigger
+    /// (`mag_filter`) or smaller (`min_filter`) than they are cached. Nearest
+    /// filtering keeps pixel-art fonts drawn at whole multiples of their size
+    /// sharp.
+    ///
+    /// Defaults to [`wgpu::FilterMode::Linear`] for both.
+    pub fn with_cache_filter(
+        mut self,
+        mag_filter: wgpu::FilterMode,
+        min_filter: wgpu::FilterMode,
+    ) -> Self {
+        self.cache.mag_filter = mag_filter;
+        self.cache.min_filter = min_filter;
+        self
+    }
+
+    /// Gives the cache textures `levels` mip levels, including the full size
+    /// one, which are sampled with the `mipmap_filter` between them. Reduces
+    /// the shimmering of text drawn much smaller than it's cached, e.g. far
+    /// away or at grazing angles in 3D.
+    ///
+    /// The mip levels of each glyph are generated when it's uploaded. Glyphs
+    /// are spaced and aligned so they don't bleed into each other in the
+    /// smallest level, which takes more space with every level. The levels are
+    /// limited by the initial cache texture size, and together they take a
+    /// third more memory.
+    ///
+    /// Defaults to `1` level, without mipmaps.
+    pub fn with_cache_mipmaps(
+        mut self,
+        levels: u32,
+        mipmap_filter: wgpu::FilterMode,
+    ) -> Self {
+        self.cache.mip_levels = levels.max(1);
+        self.cache.mipmap_filter = mipmap_filter;
+        self
+    }
+
+    /// Largest anisotropy of the cache texture sampler, from `1` to `16`. Keeps
+    /// text viewed at grazing angles sharp, best together with
+    /// [`with_cache_mipmaps()`](Self::with_cache_mipmaps).
+    ///
+    /// Needs linear filtering, see [`with_cache_filter()`](Self::with_cache_filter)
+    /// and [`with_cache_mipmaps()`](Self::with_cache_mipmaps), and is ignored with
+    /// a warning otherwise. Devices without anisotropic
+    /// filtering ignore it as well.
+    ///
+    /// Defaults to `1`, which disables anisotropic filtering.
+    pub fn with_cache_anisotropy(mut self, anisotropy: u16) -> Self {
+        self.cache.anisotropy = anisotropy;
+        self
+    }
+
+    /// Number of empty pixels kept between neighbouring glyphs in the cache
+    /// texture, so they don't bleed into each other when sampled. Mipmapped
+    /// cache textures keep at least as many as the smallest mip level needs.
+    ///
+    /// Defaults to `1`.
+    pub fn with_glyph_padding(mut self, padding: u32) -> Self {
+        self.cache.padding = padding;
+        self
+    }
+
+    /// Uses the `settings` of a [`BrushBuilder`](crate::BrushBuilder).
+    pub(crate) fn with_cache_settings(mut self, settings: CacheSettings) -> Self {
+        self.cache = settings;
+        self
+    }
+
+    /// Restores glyphs saved with [`SharedAtlas::export()`] or
+    /// [`TextBrush::export_cache()`](crate::TextBrush::export_cache), instead of
+    /// rasterizing them again. Glyphs which weren't saved are rasterized as usual.
+    ///
+    /// The data is ignored with a warning if it was saved by another version of
+    /// **wgpu-text** or with other render mode, color glyph or tolerance settings.
+    /// Glyphs are only restored for fonts with the same data as the saved ones,
+    /// and the saved cache texture size replaces the initial one.
+    pub fn with_cache_data(mut self, data: Vec<u8>) -> Self {
+        self.cache_data = Some(data);
+        self
+    }
+
+    /// Calls the `handler` whenever the cache texture grows, gets another page or is
+    /// cleared, e.g.
+    /// to collect metrics or to find a better
+    /// [`initial_cache_size()`](Self::initial_cache_size).
+    ///
+    /// The handler is called while the atlas is locked, from the brush which
+    /// caused the event.
+    pub fn on_event(
+        mut self,
+        handler: impl Fn(AtlasEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_event = Some(EventHandler(Arc::new(handler)));
+        self
+    }
+
+    /// Builds a [`SharedAtlas`] for brushes drawing onto textures of the
+    /// [`wgpu::TextureFormat`].
+    pub fn build(
+        self,
+        device: &wgpu::Device,
+        render_format: wgpu::TextureFormat,
+    ) -> SharedAtlas {
+        let render_mode =
+            Pipeline::supported_render_mode(device, render_format, self.render_mode);
+
+        // Color glyphs are converted to linear colors by sRGB targets.
+        let color_format = self.color_glyphs.then(|| {
+            if render_format.is_srgb() {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            }
+        });
+
+        let new_atlas = || {
+            Atlas::new(
+                render_mode,
+                color_format,
+                self.dimensions,
+                self.scale_tolerance,
+                self.position_tolerance,
+                &self.cache,
+            )
+        };
+        let mut atlas = new_atlas();
+        let mut fonts = HashMap::new();
+        let mut pending = Vec::new();
+        if let Some(data) = &self.cache_data {
+            let mut restored = new_atlas();
+            match preload(&mut restored, data, &device.limits()) {
+                Some((saved_fonts, pages)) => {
+                    atlas = restored;
+                    fonts = saved_fonts.into_iter().zip(0..).collect();
+                    pending = pages;
+                }
+                None => log::warn!(
+                    "Ignoring the cache data, it is invalid or was saved by another \
+                    version of wgpu-text or with other settings."
+                ),
+            }
+        }
+
+        let cache = Cache::new(
+            device,
+            render_mode.cache_format(),
+            atlas.texture_size(Page::Main),
+            color_format.unwrap_or(wgpu::TextureFormat::Rgba8Unorm),
+            atlas.texture_size(Page::Color),
+            &self.cache,
+        );
+
+        let epoch = Arc::new(AtomicU64::new(0));
+        SharedAtlas {
+            state: Arc::new(Mutex::new(AtlasState {
+                atlas,
+                cache,
+                epoch: epoch.clone(),
+                font_count: fonts.len(),
+                fonts,
+                pending,
+                memory_budget: self.memory_budget,
+                on_event: self.on_event,
+            })),
+            epoch,
+        }
+    }
+}
+
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        Self {
+            render_mode: RenderMode::default(),
+            color_glyphs: true,
+            dimensions: (256, 256),
+            scale_tolerance: 0.1,
+            position_tolerance: 0.1,
+            cache_data: None,
+            memory_budget: 256 << 20,
+            on_event: None,
+            cache: CacheSettings::default(),
+        }
+    }
+}
+
+/// Restores the `atlas` from data written by [`AtlasState::export()`] and
+/// returns the keys of the saved fonts, in the order of their ids, together with
+/// the pixels of the pages.
+#[allow(clippy::type_complexity)]
+fn preload(
+    atlas: &mut Atlas,
+    data: &[u8],
+    limits: &wgpu::Limits,
+) -> Option<(Vec<(u64, usize)>, Vec<(Page, Vec<u8>)>)> {
+    let mut input = Decoder::new(data);
+    input.expect(MAGIC)?;
+    (input.u32()? == VERSION).then_some(())?;
+
+    let fonts = (0..input.u32()?)
+        .map(|_| Some((input.u64()?, usize::try_from(input.u64()?).ok()?)))
+        .collect::<Option<Vec<_>>>()?;
+    atlas.decode(&mut input, fonts.len())?;
+
+    let mut pages = Vec::new();
+    for page in [Page::Main, Page::Color] {
+        let data = input.bytes()?;
+        if page == Page::Color && !atlas.color_glyphs() {
+            data.is_empty().then_some(())?;
+            continue;
+        }
+        let size = atlas.texture_size(page);
+        let texel_bytes = match page {
+            Page::Main => atlas.render_mode().cache_format().block_size(None)?,
+            Page::Color => 4,
+        };
+        let valid = size.width <= limits.max_texture_dimension_2d
+            && size.height <= limits.max_texture_dimension_2d
+            && Cache::layer_count(size) <= limits.max_texture_array_layers
+            && data.len() as u64
+                == size.width as u64
+                    * size.height as u64
+                    * size.depth_or_array_layers as u64
+                    * texel_bytes as u64;
+        valid.then_some(())?;
+        pages.push((page, data.to_vec()));
+    }
+    input.is_empty().then_some((fonts, pages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atlas(dimensions: (u32, u32)) -> Atlas {
+        let settings = CacheSettings::default();
+        Atlas::new(RenderMode::Coverage, None, dimensions, 0.5, 0.1, &settings)
+    }
+
+    /// Cache file of an empty atlas of the `dimensions` with `main` pixels.
+    fn file(dimensions: (u32, u32), main: &[u8]) -> Vec<u8> {
+        let mut out = Encoder::default();
+        out.data.extend_from_slice(MAGIC);
+        out.u32(VERSION);
+        out.u32(0);
+        atlas(dimensions).encode(&mut out, |_| None);
+        out.bytes(main);
+        out.bytes(&[]);
+        out.data
+    }
+
+    fn preload(data: &[u8]) -> bool {
+        super::preload(&mut atlas((256, 256)), data, &wgpu::Limits::default()).is_some()
+    }
+
+    #[test]
+    fn preloads_valid_file() {
+        assert!(preload(&file((16, 8), &[0; 16 * 8])));
+    }
+
+    #[test]
+    fn corrupt_files_are_ignored() {
+        // Zero sized cache textures can't be created.
+        assert!(!preload(&file((0, 8), &[])));
+        assert!(!preload(&file((16, 8), &[0; 16 * 7])));
+        assert!(!preload(&file((1 << 20, 1), &[0; 1 << 20])));
+        let data = file((16, 8), &[0; 16 * 8]);
+        assert!(!preload(&data[..data.len() - 1]));
+    }
+}
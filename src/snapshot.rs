@@ -0,0 +1,334 @@
+//! Golden-image snapshot testing of text rendering.
+
+use std::{
+    error::Error,
+    fmt::Display,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use glyph_brush::ab_glyph::Font;
+
+use crate::{
+    color::decode_png, error::BrushError, BrushBuilder, Image, LayeredSection, TextExtra,
+};
+
+/// Environment variable which makes [`Snapshot::compare()`] overwrite the
+/// reference images instead of comparing against them.
+pub const UPDATE_SNAPSHOTS_VAR: &str = "WGPU_TEXT_UPDATE_SNAPSHOTS";
+
+/// Renders sections headlessly and compares them against reference PNG images.
+///
+/// Owns its own device, preferably on a software adapter, so snapshots can be
+/// taken on machines without a GPU or display. Text is drawn onto an
+/// [`wgpu::TextureFormat::Rgba8Unorm`] texture with the fonts and settings of
+/// the given [`BrushBuilder`], use the same font files everywhere for
+/// reproducible results.
+///
+/// Set the [`UPDATE_SNAPSHOTS_VAR`] environment variable to create missing
+/// reference images, or to overwrite them after intended changes.
+///
+/// # Example
+/// ```no_run
+/// use wgpu_text::{glyph_brush::{Section, Text}, snapshot::Snapshot, BrushBuilder};
+///
+/// let font: &[u8] = include_bytes!("../examples/fonts/DejaVuSans.ttf");
+/// let section = Section::default().add_text(Text::new("Score: 100"));
+///
+/// Snapshot::new(200, 50)
+///     .unwrap()
+///     .with_tolerance(2)
+///     .compare(
+///         "tests/snapshots/score.png",
+///         BrushBuilder::using_font_bytes(font).unwrap(),
+///         vec![&section],
+///     )
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Snapshot {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    width: u32,
+    height: u32,
+    clear: wgpu::Color,
+    tolerance: u8,
+}
+
+impl Snapshot {
+    /// Creates a [`Snapshot`] rendering images of `width` and `height`, on a
+    /// software adapter if there is one.
+    pub fn new(width: u32, height: u32) -> Result<Self, SnapshotError> {
+        let backends =
+            wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all);
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                force_fallback_adapter: true,
+                ..Default::default()
+            }))
+            .or_else(|| pollster::block_on(instance.request_adapter(&Default::default())))
+            .ok_or(SnapshotError::NoAdapter)?;
+
+        let (device, queue) = pollster::block_on(
+            adapter.request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("wgpu-text Snapshot Device"),
+                    features: wgpu::Features::empty(),
+                    limits: wgpu::Limits::downlevel_webgl2_defaults()
+                        .using_resolution(adapter.limits()),
+                },
+                None,
+            ),
+        )
+        .map_err(SnapshotError::Device)?;
+
+        Ok(Self {
+            device,
+            queue,
+            width,
+            height,
+            clear: wgpu::Color::BLACK,
+            tolerance: 0,
+        })
+    }
+
+    /// Color the image is cleared with before drawing.
+    ///
+    /// Defaults to opaque black.
+    pub fn with_clear_color(mut self, clear: wgpu::Color) -> Self {
+        self.clear = clear;
+        self
+    }
+
+    /// Largest difference of a color channel for which pixels still match, to
+    /// allow for small rasterization differences between adapters.
+    ///
+    /// Defaults to `0`.
+    pub fn with_tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Builds a brush with the `brush` builder and renders the `sections` with it.
+    pub fn render<'a, F, H, X, S>(
+        &self,
+        brush: BrushBuilder<F, H>,
+        sections: Vec<S>,
+    ) -> Result<Image, SnapshotError>
+    where
+        F: Font + Sync,
+        H: std::hash::BuildHasher,
+        X: Clone + Into<TextExtra> + 'a,
+        S: Into<LayeredSection<'a, X>>,
+    {
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let mut brush = brush.build(&self.device, self.width, self.height, format);
+        brush.queue(&self.device, &self.queue, sections)?;
+        Ok(brush.render_to_image(
+            &self.device,
+            &self.queue,
+            self.width,
+            self.height,
+            self.clear,
+        )?)
+    }
+
+    /// Renders the `sections` like [`render()`](Self::render) and compares them
+    /// against the reference PNG image at `path`.
+    ///
+    /// On a mismatch, the rendered image is written next to the reference with
+    /// the `.actual.png` extension and an image with the mismatching pixels in
+    /// red with the `.diff.png` extension. A missing reference is an error, unless
+    /// [`UPDATE_SNAPSHOTS_VAR`] is set.
+    pub fn compare<'a, F, H, X, S, P>(
+        &self,
+        path: P,
+        brush: BrushBuilder<F, H>,
+        sections: Vec<S>,
+    ) -> Result<(), SnapshotError>
+    where
+        F: Font + Sync,
+        H: std::hash::BuildHasher,
+        X: Clone + Into<TextExtra> + 'a,
+        S: Into<LayeredSection<'a, X>>,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let actual = self.render(brush, sections)?;
+
+        if std::env::var_os(UPDATE_SNAPSHOTS_VAR).is_some() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            return save_png(&actual, path);
+        }
+        if !path.exists() {
+            return Err(SnapshotError::MissingReference(path.into()));
+        }
+
+        let expected = load_png(path)?;
+        if (expected.width, expected.height) != (actual.width, actual.height) {
+            save_png(&actual, &with_suffix(path, "actual"))?;
+            return Err(SnapshotError::SizeMismatch {
+                expected: (expected.width, expected.height),
+                actual: (actual.width, actual.height),
+            });
+        }
+
+        let mut mismatched = 0;
+        let mut diff = Vec::with_capacity(actual.data.len());
+        for (expected, actual) in expected
+            .data
+            .chunks_exact(4)
+            .zip(actual.data.chunks_exact(4))
+        {
+            let matches = expected
+                .iter()
+                .zip(actual)
+                .all(|(e, a)| e.abs_diff(*a) <= self.tolerance);
+            if matches {
+                // Matching pixels are dimmed to make the others stand out.
+                let luma =
+                    (actual[0] as u32 * 3 + actual[1] as u32 * 6 + actual[2] as u32) / 10;
+                let dimmed = (luma / 3) as u8;
+                diff.extend([dimmed, dimmed, dimmed, 255]);
+            } else {
+                mismatched += 1;
+                diff.extend([255, 0, 0, 255]);
+            }
+        }
+
+        if mismatched == 0 {
+            return Ok(());
+        }
+        let diff_path = with_suffix(path, "diff");
+        save_png(&actual, &with_suffix(path, "actual"))?;
+        save_png(
+            &Image {
+                data: diff,
+                ..actual
+            },
+            &diff_path,
+        )?;
+        Err(SnapshotError::Mismatch {
+            pixels: mismatched,
+            diff: diff_path,
+        })
+    }
+}
+
+/// `path` with the extension replaced by `suffix` and `png`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    path.with_extension(format!("{suffix}.png"))
+}
+
+fn load_png(path: &Path) -> Result<Image, SnapshotError> {
+    let data = std::fs::read(path)?;
+    let (width, height, pixels) =
+        decode_png(&data).ok_or_else(|| SnapshotError::InvalidReference(path.into()))?;
+    Ok(Image {
+        width,
+        height,
+        data: pixels.into_iter().flatten().collect(),
+    })
+}
+
+fn save_png(image: &Image, path: &Path) -> Result<(), SnapshotError> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        image.width,
+        image.height,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&image.data)?;
+    Ok(())
+}
+
+/// Result of [`Snapshot`] errors and mismatches.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// No adapter was found.
+    NoAdapter,
+    /// Requesting the device failed.
+    Device(wgpu::RequestDeviceError),
+    /// Rendering the sections failed.
+    Brush(BrushError),
+    /// Reading or writing an image failed.
+    Io(std::io::Error),
+    /// Encoding an image failed.
+    Encoding(png::EncodingError),
+    /// There is no reference image at the path.
+    MissingReference(PathBuf),
+    /// The reference image isn't a valid PNG image.
+    InvalidReference(PathBuf),
+    /// The rendered image has a different size than the reference one.
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// The rendered image differs from the reference one in the number of
+    /// `pixels`, marked in the `diff` image.
+    Mismatch { pixels: usize, diff: PathBuf },
+}
+
+impl Error for SnapshotError {}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "wgpu-text snapshot: ")?;
+        match self {
+            SnapshotError::NoAdapter => write!(f, "No adapters found!"),
+            SnapshotError::Device(error) => write!(f, "{}", error),
+            SnapshotError::Brush(error) => write!(f, "{}", error),
+            SnapshotError::Io(error) => write!(f, "{}", error),
+            SnapshotError::Encoding(error) => write!(f, "{}", error),
+            SnapshotError::MissingReference(path) => write!(
+                f,
+                "There is no reference image at {}.\n\
+                Set {} to create it from the rendered image.",
+                path.display(),
+                UPDATE_SNAPSHOTS_VAR
+            ),
+            SnapshotError::InvalidReference(path) => {
+                write!(f, "{} isn't a valid PNG image.", path.display())
+            }
+            SnapshotError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Expected an image of {}x{} pixels, but rendered {}x{}.",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            SnapshotError::Mismatch { pixels, diff } => write!(
+                f,
+                "{} pixels differ from the reference image, see {}.\n\
+                Set {} to update the reference images.",
+                pixels,
+                diff.display(),
+                UPDATE_SNAPSHOTS_VAR
+            ),
+        }
+    }
+}
+
+impl From<BrushError> for SnapshotError {
+    fn from(error: BrushError) -> Self {
+        SnapshotError::Brush(error)
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl From<png::EncodingError> for SnapshotError {
+    fn from(error: png::EncodingError) -> Self {
+        SnapshotError::Encoding(error)
+    }
+}
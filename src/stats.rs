@@ -0,0 +1,86 @@
+//! Counters of the work done by a [`TextBrush`](crate::TextBrush).
+
+use std::ops::AddAssign;
+
+/// Work done by [`TextBrush::queue()`](crate::TextBrush::queue), for a single
+/// frame or summed up over all frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Number of sections laid out, the queued ones and the retained ones which
+    /// changed, see [`TextBrush::insert()`](crate::TextBrush::insert).
+    pub sections: usize,
+    /// Number of glyph quads in the vertex buffer.
+    pub glyphs: usize,
+    /// Number of glyphs which had to be rasterized and uploaded into the cache
+    /// textures. Glyphs without anything to draw, like spaces, aren't counted.
+    pub glyphs_rasterized: usize,
+    /// Number of bytes uploaded into the cache textures, including cache data
+    /// restored with [`BrushBuilder::with_cache_data()`](crate::BrushBuilder::with_cache_data).
+    pub cache_upload_bytes: u64,
+    /// Number of copies into the cache textures. All glyphs rasterized in a
+    /// frame are staged in a single buffer, neighbours on a shelf of a page are
+    /// copied together, every mip level on its own.
+    pub cache_uploads: u32,
+    /// Number of bytes written into the vertex buffer and the texture of section
+    /// transforms and text effects, `0` if they were the same as in the last
+    /// frame.
+    pub vertex_upload_bytes: u64,
+    /// Number of times the vertex buffer was recreated, because it was too small
+    /// or shrank after a long time of low usage.
+    pub vertex_buffer_reallocations: u32,
+    /// Number of times the cache textures were resized or got another page.
+    pub cache_grows: u32,
+    /// Number of times the cache textures were full and had to be cleared.
+    pub cache_clears: u32,
+}
+
+impl AddAssign for FrameStats {
+    fn add_assign(&mut self, other: Self) {
+        self.sections += other.sections;
+        self.glyphs += other.glyphs;
+        self.glyphs_rasterized += other.glyphs_rasterized;
+        self.cache_upload_bytes += other.cache_upload_bytes;
+        self.cache_uploads += other.cache_uploads;
+        self.vertex_upload_bytes += other.vertex_upload_bytes;
+        self.vertex_buffer_reallocations += other.vertex_buffer_reallocations;
+        self.cache_grows += other.cache_grows;
+        self.cache_clears += other.cache_clears;
+    }
+}
+
+/// Result of [`TextBrush::stats()`](crate::TextBrush::stats).
+///
+/// The cache textures are the ones of the atlas the brush draws from, which may
+/// be shared with other brushes, see [`SharedAtlas`](crate::SharedAtlas).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BrushStats {
+    /// Work done by the last call to [`TextBrush::queue()`](crate::TextBrush::queue).
+    pub frame: FrameStats,
+    /// Work done by all calls to [`TextBrush::queue()`](crate::TextBrush::queue)
+    /// since the brush was built.
+    pub total: FrameStats,
+    /// Number of calls to [`TextBrush::queue()`](crate::TextBrush::queue) since the
+    /// brush was built.
+    pub frames: u64,
+    /// Number of glyphs in the cache textures, including glyphs without anything
+    /// to draw.
+    pub cached_glyphs: usize,
+    /// Size of a page of the cache texture.
+    pub cache_size: (u32, u32),
+    /// Number of pages of the cache texture.
+    pub cache_pages: u32,
+    /// Fraction of the cache texture pages taken up by glyphs, from `0.0` to
+    /// `1.0`. Glyphs are packed in rows, the unused space at the end of each
+    /// row counts as taken.
+    pub cache_usage: f32,
+    /// Size of a page of the cache texture holding color glyphs, `(1, 1)` if they
+    /// are disabled.
+    pub color_cache_size: (u32, u32),
+    /// Number of pages of color glyphs.
+    pub color_cache_pages: u32,
+    /// Fraction of the color glyph pages taken up by glyphs.
+    pub color_cache_usage: f32,
+    /// Size of the vertex buffer in bytes, of all its copies with more than one
+    /// frame in flight.
+    pub vertex_buffer_size: u64,
+}
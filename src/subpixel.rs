@@ -0,0 +1,210 @@
+//! Subpixel (LCD) glyph filtering.
+//!
+//! Glyphs are rasterized at three times the horizontal resolution, one sample for
+//! each of the red, green and blue stripes of a pixel. A five tap FIR filter, the
+//! default one of FreeType, spreads every sample over its neighbours, which keeps
+//! the color fringes at the glyph edges faint.
+
+use std::collections::HashMap;
+
+use glyph_brush::ab_glyph::Rect;
+
+use crate::{params::Params, pipeline::Vertex};
+
+/// Order of the color stripes of the pixels of a display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubpixelOrder {
+    /// Red on the left, the most common order.
+    #[default]
+    Rgb,
+    /// Blue on the left.
+    Bgr,
+}
+
+/// Size of the cells glyph quads are sorted into to find overlapping ones, in
+/// pixels.
+const CELL_SIZE: f32 = 64.0;
+
+const FILTER: [f32; 5] = [
+    8.0 / 256.0,
+    77.0 / 256.0,
+    86.0 / 256.0,
+    77.0 / 256.0,
+    8.0 / 256.0,
+];
+
+/// Filters a `width * 3` x `height` coverage bitmap into a `width` x `height` RGBA
+/// bitmap with the coverage of each color stripe.
+///
+/// The alpha channel holds the average coverage of the pixel, for effects which
+/// don't need subpixel precision.
+pub fn filter(
+    coverage: &[f32],
+    width: usize,
+    height: usize,
+    order: SubpixelOrder,
+) -> Vec<u8> {
+    let samples = width * 3;
+    let mut data = Vec::with_capacity(width * height * 4);
+
+    for row in coverage.chunks_exact(samples).take(height) {
+        let filtered = |x: usize| {
+            FILTER
+                .iter()
+                .enumerate()
+                .filter_map(|(i, weight)| {
+                    let sample = (x + i).checked_sub(2)?;
+                    row.get(sample).map(|c| c.clamp(0.0, 1.0) * weight)
+                })
+                .sum::<f32>()
+        };
+
+        for x in 0..width {
+            let mut rgb = [filtered(x * 3), filtered(x * 3 + 1), filtered(x * 3 + 2)];
+            if order == SubpixelOrder::Bgr {
+                rgb.reverse();
+            }
+            let alpha = (rgb[0] + rgb[1] + rgb[2]) / 3.0;
+            data.extend(
+                [rgb[0], rgb[1], rgb[2], alpha]
+                    .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+            );
+        }
+    }
+    data
+}
+
+/// Sorts the glyph `vertices` into runs of quads which don't overlap, glyphs
+/// overlapping earlier ones go into later runs. Appends the index of the first
+/// vertex of every run, moved by `offset`, to `runs`.
+///
+/// The two passes of subpixel glyphs only blend correctly where quads don't
+/// overlap, so every run gets both before the next one is drawn. Quads are
+/// compared before the section transforms are applied.
+pub(crate) fn sort_runs(
+    vertices: &mut [Vertex],
+    params: &Params,
+    offset: u32,
+    runs: &mut Vec<u32>,
+) {
+    let quads: Vec<Rect> = vertices.iter().map(|vertex| vertex.quad(params)).collect();
+    let mut sorted: Vec<(u32, Vertex)> = overlap_runs(&quads)
+        .into_iter()
+        .zip(vertices.iter().copied())
+        .collect();
+    // Stable, overlapping glyphs stay in order.
+    sorted.sort_by_key(|(run, _)| *run);
+
+    let mut last = None;
+    for (index, ((run, vertex), target)) in sorted.into_iter().zip(vertices).enumerate() {
+        if last != Some(run) {
+            last = Some(run);
+            runs.push(offset + index as u32);
+        }
+        *target = vertex;
+    }
+}
+
+/// Run of every quad, the one after the last run of the earlier quads it
+/// overlaps.
+fn overlap_runs(quads: &[Rect]) -> Vec<u32> {
+    let cell = |x: f32| (x / CELL_SIZE).floor() as i32;
+    let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    let mut runs: Vec<u32> = Vec::with_capacity(quads.len());
+
+    for (index, quad) in quads.iter().enumerate() {
+        let columns = cell(quad.min.x)..=cell(quad.max.x);
+        let rows = cell(quad.min.y)..=cell(quad.max.y);
+        let mut run = 0;
+        for y in rows.clone() {
+            for x in columns.clone() {
+                let earlier = cells.entry((x, y)).or_default();
+                for &other in earlier.iter() {
+                    if overlap(quad, &quads[other]) {
+                        run = run.max(runs[other] + 1);
+                    }
+                }
+                earlier.push(index);
+            }
+        }
+        runs.push(run);
+    }
+    runs
+}
+
+fn overlap(a: &Rect, b: &Rect) -> bool {
+    a.min.x < b.max.x && b.min.x < a.max.x && a.min.y < b.max.y && b.min.y < a.max.y
+}
+
+#[cfg(test)]
+mod tests {
+    use glyph_brush::ab_glyph::point;
+
+    use super::*;
+
+    #[test]
+    fn full_coverage() {
+        let data = filter(&[1.0; 4 * 3 * 2], 4, 2, SubpixelOrder::Rgb);
+        assert_eq!(data.len(), 4 * 2 * 4);
+        // The filter loses coverage only at the edges of the bitmap.
+        for row in data.chunks_exact(4 * 4) {
+            assert_eq!(row[4..12], [255; 8]);
+            assert!(row[0] < row[1] && row[1] < row[2] && row[2] == 255);
+        }
+    }
+
+    #[test]
+    fn spreads_samples_over_neighbours() {
+        // Only the green sample of the middle pixel is covered.
+        let mut coverage = [0.0; 9];
+        coverage[4] = 1.0;
+        let data = filter(&coverage, 3, 1, SubpixelOrder::Rgb);
+
+        assert_eq!(data[4..8], [77, 86, 77, 80]);
+        // The outer taps reach the closest stripes of the neighbours.
+        assert_eq!(data[..4], [0, 0, 8, 3]);
+        assert_eq!(data[8..], [8, 0, 0, 3]);
+    }
+
+    #[test]
+    fn bgr_order() {
+        let mut coverage = [0.0; 9];
+        coverage[3] = 1.0;
+        let rgb = filter(&coverage, 3, 1, SubpixelOrder::Rgb);
+        let bgr = filter(&coverage, 3, 1, SubpixelOrder::Bgr);
+
+        for (rgb, bgr) in rgb.chunks_exact(4).zip(bgr.chunks_exact(4)) {
+            assert_eq!(
+                [rgb[0], rgb[1], rgb[2], rgb[3]],
+                [bgr[2], bgr[1], bgr[0], bgr[3]]
+            );
+        }
+    }
+
+    #[test]
+    fn clamps_coverage() {
+        let data = filter(&[2.0, -1.0, 0.5], 1, 1, SubpixelOrder::Rgb);
+        assert_eq!(data, filter(&[1.0, 0.0, 0.5], 1, 1, SubpixelOrder::Rgb));
+    }
+
+    fn quad(min_x: f32, max_x: f32) -> Rect {
+        Rect {
+            min: point(min_x, 0.0),
+            max: point(max_x, 10.0),
+        }
+    }
+
+    #[test]
+    fn overlapping_quads_go_into_later_runs() {
+        // The second quad touches the first, the third overlaps both and the
+        // last two are in the next cell.
+        let quads = [
+            quad(0.0, 10.0),
+            quad(10.0, 20.0),
+            quad(5.0, 15.0),
+            quad(70.0, 80.0),
+            quad(75.0, 85.0),
+        ];
+        assert_eq!(overlap_runs(&quads), [0, 0, 1, 0, 1]);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::Matrix;
+
+/// 2D affine transform of text around a pivot point, in pixels.
+///
+/// Built from rotations, scales and skews, each applied after the previous one.
+/// Set it on a section with
+/// [`LayeredSection::with_transform()`](crate::LayeredSection::with_transform).
+///
+/// # Example
+/// ```
+/// use wgpu_text::Transform;
+///
+/// // Tilted by 30 degrees and twice as big, around the point (200, 100).
+/// let transform = Transform::default()
+///     .rotate(30f32.to_radians())
+///     .scale(2.0, 2.0)
+///     .with_pivot((200.0, 100.0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    /// Linear part as columns, maps `(x, y)` relative to the pivot to
+    /// `(m[0][0] * x + m[1][0] * y, m[0][1] * x + m[1][1] * y)`.
+    pub linear: [[f32; 2]; 2],
+    /// Point the transform is applied around, stays in place.
+    pub pivot: (f32, f32),
+}
+
+impl Transform {
+    /// Rotates by `angle` radians, clockwise on screen.
+    pub fn rotate(self, angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        self.then([[cos, sin], [-sin, cos]])
+    }
+
+    /// Scales by `x` horizontally and `y` vertically.
+    pub fn scale(self, x: f32, y: f32) -> Self {
+        self.then([[x, 0.0], [0.0, y]])
+    }
+
+    /// Skews by `x` radians along the horizontal axis and `y` radians along the
+    /// vertical one.
+    pub fn skew(self, x: f32, y: f32) -> Self {
+        self.then([[1.0, y.tan()], [x.tan(), 1.0]])
+    }
+
+    /// Applies the transform around the `pivot` instead of the origin.
+    pub fn with_pivot<P: Into<(f32, f32)>>(mut self, pivot: P) -> Self {
+        self.pivot = pivot.into();
+        self
+    }
+
+    fn then(mut self, next: [[f32; 2]; 2]) -> Self {
+        self.linear = self.linear.map(|column| {
+            [
+                next[0][0] * column[0] + next[1][0] * column[1],
+                next[0][1] * column[0] + next[1][1] * column[1],
+            ]
+        });
+        self
+    }
+}
+
+impl Default for Transform {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            linear: [[1.0, 0.0], [0.0, 1.0]],
+            pivot: (0.0, 0.0),
+        }
+    }
+}
+
+impl From<Transform> for Matrix {
+    fn from(Transform { linear, pivot }: Transform) -> Self {
+        let [[a, b], [c, d]] = linear;
+        let (x, y) = pivot;
+        [
+            [a, b, 0.0, 0.0],
+            [c, d, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [x - a * x - c * y, y - b * x - d * y, 0.0, 1.0],
+        ]
+    }
+}
+
+/// How glyphs placed in world space turn towards the camera.
+///
+/// Billboards keep the origin and the scale of the section transform, the camera
+/// is found from the view-projection matrix given to
+/// [`TextBrush::update_matrix()`](crate::TextBrush::update_matrix).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Billboard {
+    /// Glyphs are transformed like any other geometry.
+    #[default]
+    None,
+    /// Glyphs always face the camera.
+    Spherical,
+    /// Glyphs keep the up direction of the section transform and only turn
+    /// around it to face the camera, like signs on poles.
+    Cylindrical,
+}
@@ -0,0 +1,187 @@
+//! The vertex buffer holding the glyph quads of a [`TextBrush`](crate::TextBrush).
+
+use std::num::NonZeroU64;
+
+use wgpu::util::StagingBelt;
+
+use crate::pipeline::Vertex;
+
+/// Size of the staging buffers vertices are uploaded through, bigger uploads get
+/// a staging buffer of their own.
+const STAGING_CHUNK_SIZE: wgpu::BufferAddress = 1 << 16;
+
+/// Usage below which the vertex buffer counts as oversized, as the denominator
+/// of a fraction of its capacity.
+const LOW_USAGE: usize = 4;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct VertexBufferSettings {
+    /// Number of vertices the buffer has room for at first, and at least.
+    pub initial_capacity: usize,
+    /// Number of frames in a row with low usage after which the buffer shrinks,
+    /// `None` to never shrink.
+    pub shrink_after: Option<u32>,
+}
+
+/// Vertex buffer which grows geometrically and optionally shrinks after a spike.
+///
+/// Writes are staged through a [`StagingBelt`] and copied into the buffer by a
+/// command encoder, which is submitted with [`Self::submit()`].
+///
+/// With more than one frame in flight, the buffer is kept that many times. Every
+/// frame which writes vertices moves on to the next copy, which gets the vertices
+/// of the last frame copied over on the GPU before the writes, so they never
+/// wait for a copy the GPU may still be drawing from.
+#[derive(Debug)]
+pub(crate) struct VertexBuffer {
+    buffers: Vec<wgpu::Buffer>,
+    /// Index of the buffer drawn from.
+    current: usize,
+    /// Number of vertices the buffers have room for.
+    capacity: usize,
+    /// Number of vertices reserved for this frame.
+    len: usize,
+    /// Number of vertices of the current buffer which are kept this frame.
+    kept: usize,
+    settings: VertexBufferSettings,
+    /// Frames in a row which needed only a small part of the capacity.
+    low_usage_frames: u32,
+    belt: StagingBelt,
+    /// Encoder of the copies staged since the last submit.
+    encoder: Option<wgpu::CommandEncoder>,
+}
+
+impl VertexBuffer {
+    pub fn new(
+        device: &wgpu::Device,
+        settings: VertexBufferSettings,
+        frames_in_flight: usize,
+    ) -> Self {
+        Self {
+            buffers: (0..frames_in_flight.max(1))
+                .map(|_| create_buffer(device, settings.initial_capacity))
+                .collect(),
+            current: 0,
+            capacity: settings.initial_capacity,
+            len: 0,
+            kept: 0,
+            settings,
+            low_usage_frames: 0,
+            belt: StagingBelt::new(STAGING_CHUNK_SIZE),
+            encoder: None,
+        }
+    }
+
+    /// Makes room for `len` vertices, doubling the capacity until they fit. Shrinks
+    /// the buffer once `len` stayed below a quarter of the capacity for long
+    /// enough. Returns whether it had to be recreated, which loses its contents.
+    ///
+    /// Has to be called once per frame, before writing its vertices.
+    pub fn reserve(&mut self, len: usize, device: &wgpu::Device) -> bool {
+        self.kept = self.len.min(len);
+        self.len = len;
+        if len > self.capacity {
+            self.low_usage_frames = 0;
+            self.recreate(len.max(self.capacity * 2), device);
+            return true;
+        }
+
+        let Some(shrink_after) = self.settings.shrink_after else {
+            return false;
+        };
+        // Keeps room for the usage to double again.
+        let capacity = (len * 2).max(self.settings.initial_capacity);
+        if len > self.capacity / LOW_USAGE || capacity >= self.capacity {
+            self.low_usage_frames = 0;
+            return false;
+        }
+        self.low_usage_frames += 1;
+        if self.low_usage_frames < shrink_after {
+            return false;
+        }
+        self.low_usage_frames = 0;
+        self.recreate(capacity, device);
+        true
+    }
+
+    fn recreate(&mut self, capacity: usize, device: &wgpu::Device) {
+        self.capacity = capacity;
+        self.kept = 0;
+        for buffer in &mut self.buffers {
+            *buffer = create_buffer(device, capacity);
+        }
+    }
+
+    /// Stages the `vertices` to be written at the vertex `offset`. The buffer must
+    /// have room for them, see [`Self::reserve()`].
+    pub fn write(&mut self, offset: usize, vertices: &[Vertex], device: &wgpu::Device) {
+        let data: &[u8] = bytemuck::cast_slice(vertices);
+        let Some(size) = NonZeroU64::new(data.len() as u64) else {
+            return;
+        };
+        let encoder = match &mut self.encoder {
+            Some(encoder) => encoder,
+            // The first write of the frame moves on to the next buffer.
+            None => {
+                let mut encoder =
+                    device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("wgpu-text Vertex Upload Encoder"),
+                    });
+                let next = (self.current + 1) % self.buffers.len();
+                if next != self.current && self.kept > 0 {
+                    encoder.copy_buffer_to_buffer(
+                        &self.buffers[self.current],
+                        0,
+                        &self.buffers[next],
+                        0,
+                        (self.kept * std::mem::size_of::<Vertex>())
+                            as wgpu::BufferAddress,
+                    );
+                }
+                self.current = next;
+                self.encoder.insert(encoder)
+            }
+        };
+        self.belt
+            .write_buffer(
+                encoder,
+                &self.buffers[self.current],
+                (offset * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress,
+                size,
+                device,
+            )
+            .copy_from_slice(data);
+    }
+
+    /// Submits the writes staged since the last call.
+    pub fn submit(&mut self, queue: &wgpu::Queue) {
+        if let Some(encoder) = self.encoder.take() {
+            self.belt.finish();
+            queue.submit(Some(encoder.finish()));
+            self.belt.recall();
+        }
+    }
+
+    /// The buffer holding the vertices of the last frame.
+    #[inline]
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffers[self.current]
+    }
+
+    /// Size of all copies of the buffer in bytes.
+    pub fn size(&self) -> u64 {
+        self.buffers.iter().map(wgpu::Buffer::size).sum()
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("wgpu-text Vertex Buffer"),
+        size: (capacity * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress,
+        // Copied into the next buffer with more than one frame in flight.
+        usage: wgpu::BufferUsages::VERTEX
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
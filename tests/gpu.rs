@@ -0,0 +1,184 @@
+//! Tests drawing text on a GPU. They pass without doing anything on machines
+//! without an adapter, prefer a software one like llvmpipe or WARP.
+
+use std::sync::{Arc, Mutex};
+
+use wgpu_text::{
+    glyph_brush::{ab_glyph::Font, Section, Text},
+    AtlasEvent, BrushBuilder, Image, TextBrush,
+};
+
+const FONT: &[u8] = include_bytes!("../examples/fonts/DejaVuSans.ttf");
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+const SIZE: (u32, u32) = (256, 128);
+
+/// A device on a software adapter if there is one, with textures of at most
+/// `max_texture_size`.
+fn device(max_texture_size: Option<u32>) -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter =
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            force_fallback_adapter: true,
+            ..Default::default()
+        }))
+        .or_else(|| pollster::block_on(instance.request_adapter(&Default::default())));
+    let Some(adapter) = adapter else {
+        eprintln!("No adapter, skipping the test.");
+        return None;
+    };
+
+    let mut limits =
+        wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits());
+    if let Some(size) = max_texture_size {
+        limits.max_texture_dimension_2d = size;
+    }
+    let descriptor = wgpu::DeviceDescriptor {
+        label: None,
+        features: wgpu::Features::empty(),
+        limits,
+    };
+    pollster::block_on(adapter.request_device(&descriptor, None)).ok()
+}
+
+fn section(text: &str, scale: f32) -> Section<'_> {
+    Section::default()
+        .add_text(Text::new(text).with_scale(scale).with_color([1.0; 4]))
+        .with_screen_position((4.0, 4.0))
+}
+
+fn render<F: Font + Sync>(
+    brush: &mut TextBrush<F>,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    sections: Vec<&Section>,
+) -> Image {
+    brush.queue(device, queue, sections).unwrap();
+    brush
+        .render_to_image(device, queue, SIZE.0, SIZE.1, wgpu::Color::BLACK)
+        .unwrap()
+}
+
+fn lit_pixels(image: &Image) -> usize {
+    image
+        .data
+        .chunks_exact(4)
+        .filter(|pixel| pixel[..3] != [0, 0, 0])
+        .count()
+}
+
+#[test]
+fn renders_and_reads_back() {
+    let Some((device, queue)) = device(None) else {
+        return;
+    };
+    let mut brush = BrushBuilder::using_font_bytes(FONT)
+        .unwrap()
+        .build(&device, SIZE.0, SIZE.1, FORMAT);
+
+    let image = render(&mut brush, &device, &queue, vec![&section("Hello", 40.0)]);
+    assert_eq!((image.width, image.height), SIZE);
+    assert_eq!(image.data.len(), (SIZE.0 * SIZE.1 * 4) as usize);
+    assert!(lit_pixels(&image) > 100);
+    // Nothing is drawn right of the text or below it.
+    assert_eq!(image.pixel(SIZE.0 - 1, SIZE.1 - 1), [0, 0, 0, 255]);
+
+    let empty = render(&mut brush, &device, &queue, Vec::new());
+    assert_eq!(lit_pixels(&empty), 0);
+}
+
+#[test]
+fn grown_cache_keeps_glyphs() {
+    let Some((device, queue)) = device(None) else {
+        return;
+    };
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let handler_events = events.clone();
+    let mut brush = BrushBuilder::using_font_bytes(FONT)
+        .unwrap()
+        .initial_cache_size((64, 64))
+        .on_atlas_event(move |event| handler_events.lock().unwrap().push(event))
+        .build(&device, SIZE.0, SIZE.1, FORMAT);
+
+    let hello = section("Hello", 40.0);
+    let before = render(&mut brush, &device, &queue, vec![&hello]);
+    render(
+        &mut brush,
+        &device,
+        &queue,
+        vec![&section("ABCDEFGHIJKLM", 48.0)],
+    );
+    let stats = brush.stats().frame;
+    assert!(stats.cache_grows > 0);
+    assert!(events
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|event| matches!(event, AtlasEvent::Resized { .. })));
+
+    // Drawn from the copied glyphs, without rasterizing them again.
+    let after = render(&mut brush, &device, &queue, vec![&hello]);
+    assert_eq!(brush.stats().frame.glyphs_rasterized, 0);
+    assert!(after == before);
+}
+
+#[test]
+fn full_cache_adds_pages() {
+    let Some((device, queue)) = device(Some(256)) else {
+        return;
+    };
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let handler_events = events.clone();
+    let mut brush = BrushBuilder::using_font_bytes(FONT)
+        .unwrap()
+        .initial_cache_size((128, 128))
+        .on_atlas_event(move |event| handler_events.lock().unwrap().push(event))
+        .build(&device, SIZE.0, SIZE.1, FORMAT);
+
+    let hello = section("Hello", 40.0);
+    let before = render(&mut brush, &device, &queue, vec![&hello]);
+    let alphabet = section("ABCDEFGHIJKLMNOPQRSTUVWXYZ", 80.0);
+    render(&mut brush, &device, &queue, vec![&alphabet]);
+    assert!(events
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|event| matches!(event, AtlasEvent::PageAdded { .. })));
+
+    let after = render(&mut brush, &device, &queue, vec![&hello]);
+    assert_eq!(brush.stats().frame.glyphs_rasterized, 0);
+    assert!(after == before);
+}
+
+#[test]
+fn glyphs_of_a_frame_are_copied_together() {
+    let Some((device, queue)) = device(None) else {
+        return;
+    };
+    let build = || {
+        BrushBuilder::using_font_bytes(FONT)
+            .unwrap()
+            .build(&device, SIZE.0, SIZE.1, FORMAT)
+    };
+    let text = "Staged";
+
+    let mut batched = build();
+    let image = render(&mut batched, &device, &queue, vec![&section(text, 40.0)]);
+    let stats = batched.stats().frame;
+    // Glyphs of different heights may go onto different shelves.
+    assert_eq!(stats.glyphs_rasterized, text.len());
+    assert!(stats.cache_uploads < text.len() as u32);
+
+    // The same glyphs, uploaded one per frame.
+    let mut single = build();
+    for end in 1..=text.len() {
+        render(
+            &mut single,
+            &device,
+            &queue,
+            vec![&section(&text[..end], 40.0)],
+        );
+        assert_eq!(single.stats().frame.cache_uploads, 1);
+    }
+    let expected = render(&mut single, &device, &queue, vec![&section(text, 40.0)]);
+    assert!(image == expected);
+}
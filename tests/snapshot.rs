@@ -0,0 +1,59 @@
+//! Tests of the snapshot harness, which pass without doing anything on machines
+//! without an adapter. A single test, as it sets an environment variable.
+#![cfg(feature = "snapshot")]
+
+use wgpu_text::{
+    glyph_brush::{Section, Text},
+    snapshot::{Snapshot, SnapshotError, UPDATE_SNAPSHOTS_VAR},
+    BrushBuilder,
+};
+
+const FONT: &[u8] = include_bytes!("../examples/fonts/DejaVuSans.ttf");
+
+fn section(text: &str) -> Section<'_> {
+    Section::default().add_text(Text::new(text).with_scale(32.0).with_color([1.0; 4]))
+}
+
+#[test]
+fn compares_against_references() {
+    let snapshot = match Snapshot::new(160, 48) {
+        Ok(snapshot) => snapshot,
+        Err(SnapshotError::NoAdapter) => {
+            eprintln!("No adapter, skipping the test.");
+            return;
+        }
+        Err(error) => panic!("{error}"),
+    };
+    let dir =
+        std::env::temp_dir().join(format!("wgpu-text-snapshot-{}", std::process::id()));
+    let path = dir.join("hello.png");
+    let brush = || BrushBuilder::using_font_bytes(FONT).unwrap();
+
+    let missing = snapshot.compare(&path, brush(), vec![&section("Hello")]);
+    assert!(matches!(missing, Err(SnapshotError::MissingReference(_))));
+
+    std::env::set_var(UPDATE_SNAPSHOTS_VAR, "1");
+    let written = snapshot.compare(&path, brush(), vec![&section("Hello")]);
+    std::env::remove_var(UPDATE_SNAPSHOTS_VAR);
+    written.unwrap();
+    assert!(path.exists());
+
+    snapshot
+        .compare(&path, brush(), vec![&section("Hello")])
+        .unwrap();
+    let mismatch = snapshot.compare(&path, brush(), vec![&section("Hallo")]);
+    let Err(SnapshotError::Mismatch { pixels, diff }) = mismatch else {
+        panic!("expected a mismatch, got {mismatch:?}");
+    };
+    assert!(pixels > 0);
+    assert!(diff.exists());
+    assert!(dir.join("hello.actual.png").exists());
+
+    // Any difference passes with the largest tolerance.
+    snapshot
+        .with_tolerance(255)
+        .compare(&path, brush(), vec![&section("Hallo")])
+        .unwrap();
+
+    std::fs::remove_dir_all(dir).unwrap();
+}